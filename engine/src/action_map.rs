@@ -0,0 +1,216 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use serde::{Serialize, Deserialize};
+
+use winit::event as ev;
+use crate::input_cache::{InputCache, InputState, Key};
+
+/// A single input that triggers an action.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Binding {
+    Key(Key),
+    MouseButton(ev::MouseButton),
+    /// The key is active only if all the modifiers are held (pressed or down) too. A released
+    /// modifier doesn't count.
+    Chord { modifiers: Vec<Key>, key: Key },
+}
+
+impl Binding {
+
+    /// Get the state of the binding.
+    pub fn state(&self, input: &InputCache) -> Option<InputState> {
+        match self {
+            Binding::Key(key) => input.key_state(key),
+            Binding::MouseButton(button) => input.mouse_button_state(button),
+            Binding::Chord { modifiers, key } => {
+                let held = |m: &Key| matches!(input.key_state(m), Some(InputState::Pressed(_)) | Some(InputState::Down(_, _)));
+                if modifiers.iter().all(held) { input.key_state(key) }
+                else { None }
+            }
+        }
+    }
+}
+
+/// An input that produces an axis value.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum AxisBinding {
+    /// 1.0 if positive is active, -1.0 if negative is active.
+    Buttons { positive: Binding, negative: Binding },
+    /// The horizontal mouse delta in pixels.
+    MouseX,
+    /// The vertical mouse delta in pixels.
+    MouseY,
+    /// The vertical mouse wheel delta.
+    Wheel,
+}
+
+impl AxisBinding {
+
+    /// Get the value of the axis binding.
+    pub fn value(&self, input: &InputCache) -> f32 {
+        match self {
+            AxisBinding::Buttons { positive, negative } => {
+                let p = if positive.state(input).is_some() { 1.0 } else { 0.0 };
+                let n = if negative.state(input).is_some() { 1.0 } else { 0.0 };
+                p - n
+            }
+            AxisBinding::MouseX => input.get_mouse_delta().x as f32,
+            AxisBinding::MouseY => input.get_mouse_delta().y as f32,
+            AxisBinding::Wheel => input.get_scroll_delta(),
+        }
+    }
+}
+
+/// Maps named actions and axes to inputs. The bindings can be saved to and loaded from a RON file.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ActionMap {
+    actions: BTreeMap<String, Vec<Binding>>,
+    axes: BTreeMap<String, Vec<AxisBinding>>,
+}
+
+impl ActionMap {
+
+    /// Create an empty action map.
+    pub fn init() -> Self {
+        Self {
+            actions: BTreeMap::new(),
+            axes: BTreeMap::new(),
+        }
+    }
+
+    /// Add a binding for action.
+    pub fn bind(&mut self, action: &str, binding: Binding) {
+        self.actions.entry(action.to_string()).or_default().push(binding);
+    }
+
+    /// Add a binding for axis.
+    pub fn bind_axis(&mut self, axis: &str, binding: AxisBinding) {
+        self.axes.entry(axis.to_string()).or_default().push(binding);
+    }
+
+    /// Remove all bindings of action.
+    pub fn unbind(&mut self, action: &str) {
+        self.actions.remove(action);
+    }
+
+    /// Remove all bindings of axis.
+    pub fn unbind_axis(&mut self, axis: &str) {
+        self.axes.remove(axis);
+    }
+
+    /// Replace the bindings of action.
+    pub fn rebind(&mut self, action: &str, binding: Binding) {
+        self.actions.insert(action.to_string(), vec![binding]);
+    }
+
+    /// Get the bindings of action.
+    pub fn get_bindings(&self, action: &str) -> &[Binding] {
+        self.actions.get(action).map(|b| b.as_slice()).unwrap_or(&[])
+    }
+
+    /// Get the bindings of axis.
+    pub fn get_axis_bindings(&self, axis: &str) -> &[AxisBinding] {
+        self.axes.get(axis).map(|b| b.as_slice()).unwrap_or(&[])
+    }
+
+    /// Get the state of the first active binding of action.
+    pub fn action_state(&self, action: &str, input: &InputCache) -> Option<InputState> {
+        self.get_bindings(action).iter().find_map(|b| b.state(input))
+    }
+
+    /// Is any binding of action active.
+    pub fn is_active(&self, action: &str, input: &InputCache) -> bool {
+        self.action_state(action, input).is_some()
+    }
+
+    /// Was any binding of action pressed on this frame.
+    pub fn is_pressed(&self, action: &str, input: &InputCache) -> bool {
+        self.get_bindings(action).iter().any(|b| matches!(b.state(input), Some(InputState::Pressed(_))))
+    }
+
+    /// Is any binding of action down (pressed on a previous frame and still held).
+    pub fn is_down(&self, action: &str, input: &InputCache) -> bool {
+        matches!(self.action_state(action, input), Some(InputState::Down(_, _)))
+    }
+
+    /// The sum of the values of the axis bindings.
+    pub fn axis(&self, axis: &str, input: &InputCache) -> f32 {
+        self.get_axis_bindings(axis).iter().map(|b| b.value(input)).sum()
+    }
+
+    /// Serialize the bindings to a RON string.
+    pub fn to_ron(&self) -> Result<String, ron::Error> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+    }
+
+    /// Deserialize the bindings from a RON string.
+    pub fn from_ron(s: &str) -> Result<Self, ron::error::SpannedError> {
+        ron::from_str(s)
+    }
+
+    /// Save the bindings to a RON file.
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let s = self.to_ron().map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        std::fs::write(path, s)
+    }
+
+    /// Load the bindings from a RON file.
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let s = std::fs::read_to_string(path)?;
+        Self::from_ron(&s).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    /// Load the bindings from a RON file. Returns default if the file doesn't exist or can't
+    /// be parsed.
+    pub fn load_or(path: impl AsRef<Path>, default: ActionMap) -> Self {
+        let path = path.as_ref();
+        if !path.exists() { return default; }
+
+        match Self::load(path) {
+            Ok(map) => map,
+            Err(e) => {
+                log::warn!("Failed to load bindings {}: {}", path.display(), e);
+                default
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input_cache::InputClock;
+    use crate::input_record::InputEvent;
+    use ev::ElementState;
+
+    fn key(input: &mut InputCache, key: Key, state: ElementState) {
+        input.push_event(&InputEvent::Key { key, state });
+    }
+
+    #[test]
+    fn chord_requires_held_modifiers() {
+        let mut map = ActionMap::init();
+        map.bind("save", Binding::Chord { modifiers: vec![Key::ControlLeft], key: Key::KeyS });
+
+        let mut input = InputCache::with_clock(InputClock::Manual(0));
+        key(&mut input, Key::ControlLeft, ElementState::Pressed);
+        key(&mut input, Key::KeyS, ElementState::Pressed);
+        assert!(map.is_pressed("save", &input));
+
+        // The modifier is released on the same frame: it is no longer held.
+        input.pre_update_with_time(1);
+        key(&mut input, Key::ControlLeft, ElementState::Released);
+        assert!(matches!(input.key_state(&Key::ControlLeft), Some(InputState::Released(_, _))));
+        assert!(!map.is_active("save", &input));
+    }
+
+    #[test]
+    fn chord_without_modifiers_is_inactive() {
+        let mut map = ActionMap::init();
+        map.bind("save", Binding::Chord { modifiers: vec![Key::ControlLeft], key: Key::KeyS });
+
+        let mut input = InputCache::with_clock(InputClock::Manual(0));
+        key(&mut input, Key::KeyS, ElementState::Pressed);
+        assert!(!map.is_active("save", &input));
+    }
+}
@@ -3,9 +3,12 @@ use std::borrow::Cow;
 use bytemuck::{Zeroable, Pod};
 use crate::pipelines::BindGroupMapper;
 use crate::pipelines::ComputePipelineWrapper;
-use crate::buffer::buffer_from_data;
+use crate::buffer::GpuBuffer;
 use crate::common_structs::{DrawIndirect};
 use crate::histogram::Histogram;
+use crate::error::EngineError;
+use crate::check_wgsl_layout;
+use crate::wgsl_layout::AddressSpace;
 use crate::bindgroups::{
     create_uniform_bindgroup_layout,
     create_buffer_bindgroup_layout,
@@ -23,39 +26,50 @@ pub struct McParams {
     pub noise_local_dimension: [u32; 4], 
 }
 
+impl McParams {
+
+    /// Check the layout against the McParams struct of the marching cubes shader.
+    pub fn check_layout() -> Result<(), EngineError> {
+        check_wgsl_layout!(McParams, include_str!("wgsl/marching_cubes_indirect.wgsl"), AddressSpace::Uniform,
+                           base_position, isovalue, cube_length, future_usage1, future_usage2,
+                           noise_global_dimension, noise_local_dimension)
+    }
+}
+
 pub struct MarchingCubes {
     // The mc pipeline.
     compute_pipeline_wrapper: ComputePipelineWrapper,
     mc_params: McParams,
-    mc_params_buffer: wgpu::Buffer,
+    mc_params_buffer: GpuBuffer<McParams>,
     buffer_counter: Histogram,
-    indirect_buffer: wgpu::Buffer,
+    indirect_buffer: GpuBuffer<DrawIndirect>,
     bind_group: wgpu::BindGroup,
 }
 
 impl MarchingCubes {
 
-    pub fn get_counter_value(&self, device:&wgpu::Device, queue: &wgpu::Queue) -> u32 {
-        self.buffer_counter.get_values(device, queue)[0]
+    /// Read the counter back with a blocking read (see Histogram::get_values).
+    pub fn get_counter_value(&self, device:&wgpu::Device, queue: &wgpu::Queue) -> Result<u32, EngineError> {
+        Ok(self.buffer_counter.get_values(device, queue)?[0])
     }
     pub fn get_draw_indirect_buffer(&self) -> &wgpu::Buffer {
-        &self.indirect_buffer
+        self.indirect_buffer.get_buffer()
     }
     pub fn reset_counter_value(&self, device: &wgpu::Device, queue: &wgpu::Queue) {
         self.buffer_counter.set_values_cpu_version(device, queue, &vec![0]);
         
-        queue.write_buffer(
-            &self.indirect_buffer,
-            0,
-            bytemuck::cast_slice(&[
+        self.indirect_buffer.write(
+            queue,
+            0..1,
+            &[
                 DrawIndirect {
                     vertex_count: 0,
                     instance_count: 1,
                     base_vertex: 0,
                     base_instance: 0,
                 }
-            ])
-        );
+            ]
+        ).expect("The indirect buffer holds one draw.");
     }
 
     pub fn update_mc_params(&mut self, queue: &wgpu::Queue, mc_params: McParams) {
@@ -63,11 +77,7 @@ impl MarchingCubes {
         // self.mc_params.isovalue = isovalue;
         self.mc_params = mc_params;
 
-        queue.write_buffer(
-            &self.mc_params_buffer,
-            0,
-            bytemuck::cast_slice(&[self.mc_params ])
-        );
+        self.mc_params_buffer.write(queue, 0..1, &[self.mc_params]).expect("The mc params buffer holds one element.");
     }
 
     pub fn get_mc_params(&self) -> McParams {
@@ -102,8 +112,8 @@ impl MarchingCubes {
                 base_instance: 0,
             };
 
-        let indirect_buffer = 
-            buffer_from_data::<DrawIndirect>(
+        let indirect_buffer =
+            GpuBuffer::<DrawIndirect>::from_data(
             &device,
             &[indirect_data],
             wgpu::BufferUsages::COPY_SRC |
@@ -144,7 +154,7 @@ impl MarchingCubes {
                 bind_group_mapper,
                 Some("Mc pipeline"));
 
-        let mc_params_buffer = buffer_from_data::<McParams>(
+        let mc_params_buffer = GpuBuffer::<McParams>::from_data(
                 &device,
                 &[*mc_params],
                 wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::UNIFORM,
@@ -154,8 +164,8 @@ impl MarchingCubes {
         let bind_group = pipeline_wrapper.create_bind_group(
             device,
             &vec![
-                &mc_params_buffer.as_entire_binding(),
-                &indirect_buffer.as_entire_binding(),
+                &mc_params_buffer.get_buffer().as_entire_binding(),
+                &indirect_buffer.get_buffer().as_entire_binding(),
                 &histogram.get_histogram_buffer().as_entire_binding(),
                 &noise_buffer.as_entire_binding(),
                 &output_buffer.as_entire_binding()
@@ -182,3 +192,13 @@ impl MarchingCubes {
             x, y, z, Some("mc dispatch"));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mc_params_layout_matches_the_shader() {
+        McParams::check_layout().unwrap();
+    }
+}
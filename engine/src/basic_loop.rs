@@ -1,18 +1,171 @@
+use std::sync::Arc;
 use crate::input_cache::InputCache;
+use crate::input_record;
+use crate::frame_stats::{FrameStats, FrameSample};
 use crate::core::{
     Loop,
     WGPUContext,
     SurfaceWrapper,
     EventLoopWrapper,
     Application,
+    forward_window_event,
 };
 
 use winit::{
     event::{Event, KeyEvent, /* StartCause, */ WindowEvent},
     event_loop::{EventLoop, EventLoopWindowTarget, ControlFlow},
     keyboard::{Key, NamedKey},
+    window::Window,
     };
 
+/// Run the winit event loop. On wasm the event loop is spawned and this returns immediately.
+pub(crate) fn run_event_loop(event_loop: EventLoop<()>, handler: impl FnMut(Event<()>, &EventLoopWindowTarget<()>) + 'static) {
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "wasm32")] {
+            use winit::platform::web::EventLoopExtWebSys;
+            event_loop.spawn(handler);
+        } else {
+            if let Err(e) = event_loop.run(handler) {
+                log::error!("{}", e);
+            }
+        }
+    }
+}
+
+/// The state and the event handling shared by the windowed loops: surface creation and resume,
+/// suspend, resize, exit on escape or close and the input cache. The loops only render frames.
+pub(crate) struct WindowLoopState<A: Application> {
+    pub(crate) context: WGPUContext,
+    pub(crate) surface: SurfaceWrapper,
+    pub(crate) window: Arc<Window>,
+    pub(crate) application: Option<A>,
+    pub(crate) input_cache: InputCache,
+    // Application::resumed is only called after a suspend. Desktop platforms send Resumed at startup.
+    suspended: bool,
+}
+
+impl<A: Application> WindowLoopState<A> {
+
+    /// Create the state. The application is created when the surface is ready.
+    pub(crate) fn init(context: WGPUContext, surface: SurfaceWrapper, window: Arc<Window>) -> Self {
+        let mut input_cache = InputCache::init();
+        #[cfg(not(target_arch = "wasm32"))]
+        input_record::setup_from_env(&mut input_cache);
+
+        Self {
+            context,
+            surface,
+            window,
+            application: None,
+            input_cache,
+            suspended: false,
+        }
+    }
+
+    /// Handle an event. Returns the surface texture and a view of it when a frame should be
+    /// rendered. The caller presents the frame.
+    pub(crate) fn handle_event(&mut self, event: Event<()>, target: &EventLoopWindowTarget<()>) -> Option<(wgpu::SurfaceTexture, wgpu::TextureView)> {
+
+        target.set_control_flow(ControlFlow::Poll);
+        // target.set_control_flow(ControlFlow::Wait);
+
+        match event {
+            ref e if SurfaceWrapper::start_condition(e) => {
+                if let Err(e) = self.surface.resume(&self.context, self.window.clone(), true) { // E::SRGB);
+                    log::error!("{}", e);
+                    target.exit();
+                    return None;
+                }
+
+                // If we haven't created the example yet, do so now.
+                if self.application.is_none() {
+                    self.application = Some(A::init(&self.context, &self.surface));
+                }
+                else if self.suspended {
+                    self.suspended = false;
+                    self.application.as_mut().unwrap().resumed(&self.context);
+                }
+            }
+            Event::Resumed => {
+                if self.suspended {
+                    self.suspended = false;
+                    if let Some(app) = self.application.as_mut() { app.resumed(&self.context); }
+                }
+            }
+            Event::NewEvents(..) => {
+                self.input_cache.pre_update();
+            },
+            Event::Suspended => {
+                self.suspended = true;
+                if let Some(app) = self.application.as_mut() { app.suspended(&self.context); }
+                self.surface.suspend();
+            }
+            Event::LoopExiting => {
+                if let Some(app) = self.application.as_mut() { app.close(&self.context); }
+                #[cfg(not(target_arch = "wasm32"))]
+                input_record::save_to_env(&mut self.input_cache);
+            }
+            Event::WindowEvent { event, .. } => {
+                self.input_cache.update(&event);
+                if let Some(app) = self.application.as_mut() { forward_window_event(app, &self.context, &event); }
+                match event {
+                    WindowEvent::Resized(size) => {
+                        self.surface.resize(&self.context, size);
+                        if let Some(app) = self.application.as_mut() {
+                            app.resize(&self.context, self.surface.config(), size);
+                        }
+                        self.window.request_redraw();
+                    }
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                logical_key: Key::Named(NamedKey::Escape),
+                                ..
+                            },
+                            ..
+                    }
+                    | WindowEvent::CloseRequested => {
+                        target.exit();
+                    }
+                    WindowEvent::RedrawRequested => {
+                        return self.acquire_frame();
+                    }
+                    _ => { },
+                } // match event
+            }, // Event::WindowsEvent
+            _ => {}
+        } // match event
+        None
+    }
+
+    /// Acquire the next frame. None if the application doesn't exist yet or the surface texture
+    /// isn't available (another redraw is requested).
+    fn acquire_frame(&mut self) -> Option<(wgpu::SurfaceTexture, wgpu::TextureView)> {
+
+        // On MacOS, currently redraw requested comes in _before_ Init does.
+        // If this happens, just drop the requested redraw on the floor.
+        //
+        // See https://github.com/rust-windowing/winit/issues/3235 for some discussion
+        if self.application.is_none() {
+            return None;
+        }
+
+        let frame = match self.surface.acquire(&self.context) {
+            Ok(frame) => frame,
+            Err(e) => {
+                log::warn!("{}", e);
+                self.window.request_redraw();
+                return None;
+            }
+        };
+        let view = frame.texture.create_view(&wgpu::TextureViewDescriptor {
+            format: Some(self.surface.config().view_formats[0]),
+            ..wgpu::TextureViewDescriptor::default()
+        });
+        Some((frame, view))
+    }
+}
+
 /// A "basic" loop.
 pub struct BasicLoop { }
 
@@ -23,121 +176,42 @@ impl Loop for BasicLoop {
     }
 
     // fn start<A: Application>(&self, title: &str, context: WGPUContext, surface: SurfaceWrapper) {
-    fn start<A: Application>(_title: &str, context: WGPUContext, mut surface: SurfaceWrapper, window_loop: EventLoopWrapper) {
+    fn start<A: Application>(_title: &str, context: WGPUContext, surface: SurfaceWrapper, window_loop: Option<EventLoopWrapper>) {
 
-        let mut application = None;
-        let mut input_cache = InputCache::init();
-
-        cfg_if::cfg_if! {
-            if #[cfg(target_arch = "wasm32")] {
-                use winit::platform::web::EventLoopExtWebSys;
-                let event_loop_function = EventLoop::spawn;
-            } else {
-                let event_loop_function = EventLoop::run;
-            }
-        }
+        let window_loop = window_loop.expect("BasicLoop requires a window.");
+        let mut state = WindowLoopState::<A>::init(context, surface, window_loop.window.clone());
+        let mut frame_stats = FrameStats::init(120);
+        let mut previous_frame_time = 0;
 
         log::info!("Entering event loop...");
 
-        // On native this is a result, but on wasm it's a unit type.
-        #[allow(clippy::let_unit_value)]
-        let _ = (event_loop_function)(
+        run_event_loop(
             window_loop.event_loop,
             move |event: Event<()>, target: &EventLoopWindowTarget<()>| {
 
-                target.set_control_flow(ControlFlow::Poll);
-                // target.set_control_flow(ControlFlow::Wait);
+                let Some((frame, view)) = state.handle_event(event, target) else { return };
+                let application = state.application.as_mut().unwrap();
 
-                match event {
-                    ref e if SurfaceWrapper::start_condition(e) => {
-                        surface.resume(&context, window_loop.window.clone(), true); // E::SRGB);
-
-                        // If we haven't created the example yet, do so now.
-                        if application.is_none() {
-                            application = Some(A::init(
-                                    &context,
-                                    &surface,
-                                    ));
-                        }
-                    }
-                    Event::NewEvents(..) => {
-                        input_cache.pre_update();
-                    },
-                    Event::Suspended => {
-                        surface.suspend();
-                    }
-                    Event::WindowEvent { event, .. } => {
-                        input_cache.update(&event);
-                        match event {
-                            WindowEvent::Resized(size) => {
-                                surface.resize(&context, size);
-                                application.as_mut().unwrap().resize(
-                                    &context,
-                                    surface.config(),
-                                    size
-                                    );
-
-                                window_loop.window.request_redraw();
-                            }
-                            WindowEvent::KeyboardInput {
-                                event:
-                                    KeyEvent {
-                                        logical_key: Key::Named(NamedKey::Escape),
-                                        ..
-                                    },
-                                    ..
-                            }
-                            | WindowEvent::CloseRequested => {
-                                target.exit();
-                            }
-                            #[cfg(not(target_arch = "wasm32"))]
-                            WindowEvent::KeyboardInput {  .. } =>  {  
-                                // event:
-                                //     KeyEvent {
-                                //         logical_key: Key::Character(s),
-                                //         ..
-                                //     },
-                                // ..
-
-                            }, //if s == "r" => {
-                               //println!("{:#?}", context.instance.generate_report());
-                               // }
-
-                            WindowEvent::RedrawRequested => {
-                                // On MacOS, currently redraw requested comes in _before_ Init does.
-                                // If this happens, just drop the requested redraw on the floor.
-                                //
-                                // See https://github.com/rust-windowing/winit/issues/3235 for some discussion
-                                if application.is_none() {
-                                    return;
-                                }
-
-                                //frame_counter.update();
-
-                                let frame = surface.acquire(&context);
-                                let view = frame.texture.create_view(&wgpu::TextureViewDescriptor {
-                                    format: Some(surface.config().view_formats[0]),
-                                    ..wgpu::TextureViewDescriptor::default()
-                                });
-
-                                application.as_mut().unwrap().update(&context, &input_cache); // Input cache
-                                application
-                                    .as_mut()
-                                    .unwrap()
-                                    .render(&context, &view, &surface);
-
-                                frame.present();
-
-                                window_loop.window.request_redraw();
-                            }
-                            _ => {
-                                // log::info!("{:?}", event);
-                                // application.as_mut().unwrap().update(&context, &input_cache ); // Input cache
-                            },
-                        } // match event
-                    }, // Event::WindowsEvent
-                       _ => {}
-                } // match event 
+                application.frame_stats(&state.context, &frame_stats);
+
+                let update_start = instant::Instant::now();
+                application.update(&state.context, &state.input_cache); // Input cache
+                let update_time = update_start.elapsed().as_nanos();
+
+                let render_start = instant::Instant::now();
+                application.render(&state.context, &view, &state.surface);
+                let render_time = render_start.elapsed().as_nanos();
+
+                frame_stats.push(FrameSample {
+                    frame_time: state.input_cache.get_time() - previous_frame_time,
+                    update_time,
+                    render_time,
+                });
+                previous_frame_time = state.input_cache.get_time();
+
+                frame.present();
+
+                state.window.request_redraw();
             }, // move
         );
     }
@@ -11,6 +11,21 @@ pub fn create_uniform_bindgroup_layout(binding_index: u32, visibility: wgpu::Sha
     }
 }
 
+/// A uniform layout entry with a dynamic offset (see UniformRing). The min_binding_size is the
+/// size of the struct bound through the entry.
+pub fn create_dynamic_uniform_bindgroup_layout(binding_index: u32, visibility: wgpu::ShaderStages, min_binding_size: Option<std::num::NonZeroU64>) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding: binding_index,
+        visibility: visibility,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: true,
+            min_binding_size: min_binding_size,
+        },
+        count: None,
+    }
+}
+
 pub fn create_buffer_bindgroup_layout(binding_index: u32, visibility: wgpu::ShaderStages, read_only: bool) -> wgpu::BindGroupLayoutEntry {
     wgpu::BindGroupLayoutEntry {
         binding: binding_index,
@@ -1,20 +1,32 @@
+use std::marker::PhantomData;
+use std::mem::size_of;
+use std::ops::Range;
 use crate::misc::Convert2Vec;
+use crate::error::EngineError;
+use crate::readback::ReadbackPool;
+use std::future::Future;
 use bytemuck::Pod;
 use wgpu::util::DeviceExt;
 use wgpu::BufferAddress;
 
-/// Add data to buffer. TODO: validate that there is enougt space in buffer.
+/// Add data to buffer at the byte offset. Returns an error with the byte range if the data
+/// doesn't fit in the buffer (see GpuBuffer::write for a typed version).
 pub fn add_data<T: Pod>(
     device: &wgpu::Device,
     queue: &wgpu::Queue,
     t: &[T],
     buffer: &wgpu::Buffer,
-    offset: BufferAddress) {
+    offset: BufferAddress) -> Result<(), EngineError> {
 
     // log::info!("Writing data: offset {:?}", offset);
-    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Add Data") });
+    let end = offset.saturating_add(std::mem::size_of_val(t) as BufferAddress);
+    if end > buffer.size() {
+        return Err(EngineError::BufferOutOfBounds { start: offset as usize, end: end as usize, len: buffer.size() as usize });
+    }
+    let encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Add Data") });
     queue.write_buffer(buffer, offset, bytemuck::cast_slice(t));
     queue.submit(Some(encoder.finish()));
+    Ok(())
 }
 
 /// Create wgpu::buffer from data.
@@ -108,42 +120,271 @@ pub fn buffer_from_data<T: Pod>(
 //++ }
 
 
-/// Copy the content of the buffer into a vector.
-pub fn to_vec<T: Convert2Vec + std::clone::Clone + bytemuck::Pod + std::marker::Send>(
+/// Copy the content of the buffer into a vector through the pool. Blocks until the copy is
+/// done, so this doesn't work on wasm (see ReadbackPool::read_async).
+pub fn to_vec<T: Convert2Vec>(
+    pool: &ReadbackPool,
     device: &wgpu::Device,
     queue: &wgpu::Queue,
     buffer: &wgpu::Buffer,
-    _src_offset: wgpu::BufferAddress,
+    src_offset: wgpu::BufferAddress,
     copy_size: wgpu::BufferAddress,
     // _spawner: &Spawner,
-    ) -> Option<Vec<T>> {
-
-    // TODO: Recycle staging buffers.
-    let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-        label: None,
-        size: copy_size,
-        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
-        mapped_at_creation: false,
-    });
-
-    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-    encoder.copy_buffer_to_buffer(buffer, 0, &staging_buffer, 0, copy_size);
-    queue.submit(Some(encoder.finish()));
+    ) -> Result<Vec<T>, EngineError> {
+
+    let data = pool.read(device, queue, buffer, src_offset, copy_size)?;
+    T::convert(&data)
+}
+
+/// A typed gpu buffer. Tracks the number of elements (length), the number of elements that fit
+/// in the buffer (capacity) and the usage. The size of T must be a multiple of 4 bytes
+/// (wgpu::COPY_BUFFER_ALIGNMENT). COPY_SRC and COPY_DST are always added to the usage.
+pub struct GpuBuffer<T: Pod> {
+    buffer: wgpu::Buffer,
+    len: usize,
+    capacity: usize,
+    usage: wgpu::BufferUsages,
+    label: Option<String>,
+    phantom: PhantomData<T>,
+}
+
+impl<T: Pod> GpuBuffer<T> {
+
+    fn check_element_size() {
+        assert!(size_of::<T>() > 0 && size_of::<T>() as BufferAddress % wgpu::COPY_BUFFER_ALIGNMENT == 0,
+                "Element size must be a multiple of {} bytes.", wgpu::COPY_BUFFER_ALIGNMENT);
+    }
+
+    fn create_buffer(device: &wgpu::Device, capacity: usize, usage: wgpu::BufferUsages, label: &Option<String>) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: label.as_deref(),
+            size: (capacity.max(1) * size_of::<T>()) as BufferAddress,
+            usage,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Create an empty buffer with room for capacity elements.
+    pub fn with_capacity(device: &wgpu::Device, capacity: usize, usage: wgpu::BufferUsages, label: Option<&str>) -> Self {
+        Self::check_element_size();
+        let usage = usage | wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST;
+        let label = label.map(|l| l.to_string());
+        Self {
+            buffer: Self::create_buffer(device, capacity, usage, &label),
+            len: 0,
+            capacity: capacity.max(1),
+            usage,
+            label,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Create a buffer of len zeroed elements.
+    pub fn zeroed(device: &wgpu::Device, len: usize, usage: wgpu::BufferUsages, label: Option<&str>) -> Self {
+        let mut buffer = Self::with_capacity(device, len, usage, label);
+        buffer.len = len;
+        buffer
+    }
+
+    /// Create a buffer from data. The capacity is the length of the data.
+    pub fn from_data(device: &wgpu::Device, data: &[T], usage: wgpu::BufferUsages, label: Option<&str>) -> Self {
+        if data.is_empty() { return Self::with_capacity(device, 0, usage, label); }
+
+        Self::check_element_size();
+        let usage = usage | wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST;
+        Self {
+            buffer: buffer_from_data::<T>(device, data, usage, label),
+            len: data.len(),
+            capacity: data.len(),
+            usage,
+            label: label.map(|l| l.to_string()),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Get the wgpu buffer. Note that growing the buffer replaces the wgpu buffer, so bind
+    /// groups must be recreated.
+    pub fn get_buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    /// The number of elements.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Is the buffer empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The number of elements that fit in the buffer without growing.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Get the usage of the buffer.
+    pub fn get_usage(&self) -> wgpu::BufferUsages {
+        self.usage
+    }
+
+    /// Set the length, e.g. after a shader has written elements. Panics if len > capacity.
+    pub fn set_len(&mut self, len: usize) {
+        assert!(len <= self.capacity, "Length {} exceeds the capacity {}.", len, self.capacity);
+        self.len = len;
+    }
+
+    /// Remove all elements. The content of the buffer is not changed.
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    fn check_range(&self, range: &Range<usize>) -> Result<(), EngineError> {
+        if range.start > range.end || range.end > self.len {
+            return Err(EngineError::BufferOutOfBounds { start: range.start, end: range.end, len: self.len });
+        }
+        Ok(())
+    }
+
+    /// Write data to the elements in range. The range must be inside the length.
+    pub fn write(&self, queue: &wgpu::Queue, range: Range<usize>, data: &[T]) -> Result<(), EngineError> {
+        self.check_range(&range)?;
+        if range.len() != data.len() {
+            return Err(EngineError::BufferLengthMismatch { range: range.len(), data: data.len() });
+        }
+        if !data.is_empty() {
+            queue.write_buffer(&self.buffer, (range.start * size_of::<T>()) as BufferAddress, bytemuck::cast_slice(data));
+        }
+        Ok(())
+    }
+
+    /// Read the elements in range through the pool. The range must be inside the length.
+    /// Blocks until the gpu has finished the copy (see ReadbackPool::read).
+    pub fn read_with(&self, pool: &ReadbackPool, device: &wgpu::Device, queue: &wgpu::Queue, range: Range<usize>) -> Result<Vec<T>, EngineError> {
+        self.check_range(&range)?;
+        let (offset, size) = Self::byte_range(&range);
+        T::convert(&pool.read(device, queue, &self.buffer, offset, size)?)
+    }
+
+    /// Read the elements in range through the pool asynchronously. Works on wasm.
+    pub fn read_async(&self, pool: &ReadbackPool, device: &wgpu::Device, queue: &wgpu::Queue, range: Range<usize>)
+        -> impl Future<Output = Result<Vec<T>, EngineError>> {
+
+        let checked = self.check_range(&range);
+        let (offset, size) = Self::byte_range(&range);
+        let data = match checked {
+            Ok(()) => Some(pool.read_async(device, queue, &self.buffer, offset, size)),
+            Err(_) => None,
+        };
+
+        async move {
+            checked?;
+            T::convert(&data.unwrap().await?)
+        }
+    }
+
+    fn byte_range(range: &Range<usize>) -> (BufferAddress, BufferAddress) {
+        ((range.start * size_of::<T>()) as BufferAddress, (range.len() * size_of::<T>()) as BufferAddress)
+    }
+
+    /// Make room for additional elements. The capacity grows at least to twice the old capacity
+    /// and the old elements are copied to the new buffer. Returns true if the wgpu buffer was
+    /// replaced.
+    pub fn reserve(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, additional: usize) -> bool {
+        let needed = self.len + additional;
+        if needed <= self.capacity { return false; }
+
+        let capacity = needed.max(2 * self.capacity);
+        let buffer = Self::create_buffer(device, capacity, self.usage, &self.label);
+
+        if self.len > 0 {
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("GpuBuffer grow encoder") });
+            encoder.copy_buffer_to_buffer(&self.buffer, 0, &buffer, 0, (self.len * size_of::<T>()) as BufferAddress);
+            queue.submit(Some(encoder.finish()));
+        }
+
+        self.buffer = buffer;
+        self.capacity = capacity;
+        true
+    }
+
+    /// Append data to the end of the buffer, growing it if needed. Returns true if the wgpu
+    /// buffer was replaced.
+    pub fn push(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, data: &[T]) -> bool {
+        let reallocated = self.reserve(device, queue, data.len());
+        let start = self.len;
+        self.len += data.len();
+        self.write(queue, start..self.len, data).expect("The buffer was reserved.");
+        reallocated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winit::dpi::PhysicalSize;
+    use crate::core::{test_context, WGPUContext};
+
+    fn context() -> Option<WGPUContext> {
+        test_context(PhysicalSize::new(4, 4)).map(|(context, _)| context)
+    }
+
+    fn read_all(buffer: &GpuBuffer<u32>, pool: &ReadbackPool, context: &WGPUContext) -> Vec<u32> {
+        buffer.read_with(pool, &context.device, &context.queue, 0..buffer.len()).unwrap()
+    }
+
+    #[test]
+    fn writes_and_reads_are_bounds_checked() {
+        let Some(context) = context() else { return };
+        let (device, queue) = (&context.device, &context.queue);
+        let pool = ReadbackPool::init();
+        let buffer = GpuBuffer::<u32>::from_data(device, &[1, 2, 3, 4], wgpu::BufferUsages::STORAGE, None);
+
+        buffer.write(queue, 1..3, &[20, 30]).unwrap();
+        assert_eq!(read_all(&buffer, &pool, &context), vec![1, 20, 30, 4]);
+        assert_eq!(buffer.read_with(&pool, device, queue, 2..4).unwrap(), vec![30, 4]);
+
+        assert!(matches!(buffer.write(queue, 3..5, &[0, 0]), Err(EngineError::BufferOutOfBounds { start: 3, end: 5, len: 4 })));
+        assert!(matches!(buffer.write(queue, 0..2, &[0]), Err(EngineError::BufferLengthMismatch { range: 2, data: 1 })));
+        assert!(matches!(buffer.read_with(&pool, device, queue, 2..5), Err(EngineError::BufferOutOfBounds { .. })));
+        assert_eq!(read_all(&buffer, &pool, &context), vec![1, 20, 30, 4]);
+    }
+
+    #[test]
+    fn push_grows_and_keeps_the_old_elements() {
+        let Some(context) = context() else { return };
+        let (device, queue) = (&context.device, &context.queue);
+        let pool = ReadbackPool::init();
+        let mut buffer = GpuBuffer::<u32>::with_capacity(device, 2, wgpu::BufferUsages::STORAGE, None);
+
+        assert!(!buffer.push(device, queue, &[1, 2]));
+        assert_eq!((buffer.len(), buffer.capacity()), (2, 2));
+
+        // The capacity at least doubles and the old elements are copied over.
+        assert!(buffer.push(device, queue, &[3]));
+        assert_eq!((buffer.len(), buffer.capacity()), (3, 4));
+        assert_eq!(read_all(&buffer, &pool, &context), vec![1, 2, 3]);
 
-    let res: Vec<T>;
+        assert!(buffer.push(device, queue, &[4, 5, 6, 7, 8, 9, 10]));
+        assert_eq!((buffer.len(), buffer.capacity()), (10, 10));
+        assert_eq!(read_all(&buffer, &pool, &context), (1..=10).collect::<Vec<u32>>());
 
-    let buffer_slice = staging_buffer.slice(..);
-    //++ let (sender, receiver) = futures_intrusive::channel::shared::oneshot_channel();
-    //++ let _ = buffer_slice.map_async(wgpu::MapMode::Read, true);
-    // let _ = buffer_slice.map_async(wgpu::MapMode::Read, move |v| sender.send(v).unwrap());
-    buffer_slice.map_async(wgpu::MapMode::Read, move |_| ());
-    device.poll(wgpu::Maintain::Wait);
+        // Reserving within the capacity keeps the buffer.
+        buffer.clear();
+        assert!(!buffer.reserve(device, queue, 10));
+        assert_eq!(buffer.get_buffer().size(), 40);
+    }
 
-    // Wasm version crashes: DOMException.getMappedRange: Buffer not mapped.
-    let data = buffer_slice.get_mapped_range().to_vec();
-    res = Convert2Vec::convert(&data);
-    drop(data);
-    staging_buffer.unmap();
+    #[test]
+    fn add_data_rejects_data_past_the_end() {
+        let Some(context) = context() else { return };
+        let (device, queue) = (&context.device, &context.queue);
+        let pool = ReadbackPool::init();
+        let buffer = GpuBuffer::<u32>::zeroed(device, 4, wgpu::BufferUsages::STORAGE, None);
 
-    Some(res)
+        add_data(device, queue, &[7u32, 8], buffer.get_buffer(), 8).unwrap();
+        assert_eq!(read_all(&buffer, &pool, &context), vec![0, 0, 7, 8]);
+        assert!(matches!(add_data(device, queue, &[7u32, 8], buffer.get_buffer(), 12),
+                         Err(EngineError::BufferOutOfBounds { start: 12, end: 20, len: 16 })));
+    }
 }
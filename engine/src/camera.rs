@@ -2,13 +2,46 @@
 // use crate::misc::clamp;
 use libm::asin;
 use libm::atan2;
-use crate::input_cache::{InputCache, InputState};
+use crate::input_cache::InputCache;
+use crate::action_map::{ActionMap, Binding, AxisBinding};
+use crate::camera_path::{CameraPath, PathPlayer, PathSample};
+use crate::camera_controller::{CameraController, ControllerSettings};
+use crate::camera_snapshot::{CameraSnapshot, CameraBookmarks, SnapshotTransition, CAMERA_BOOKMARK_SLOTS};
+use crate::texture::DepthConvention;
+use crate::frustum::Frustum;
+use crate::error::EngineError;
+use crate::picking::Ray;
+use crate::collision::CameraCollider;
 use crate::buffer::buffer_from_data;
 use cgmath::{prelude::*, Vector3, Vector4, Point3};
 use bytemuck::{Pod, Zeroable};
+use serde::{Serialize, Deserialize};
+use std::path::{Path, PathBuf};
 
 // pub use winit::event::VirtualKeyCode as Key;
 use winit::keyboard::KeyCode;
+
+/// The camera actions. See Camera::default_bindings.
+pub const CAMERA_FORWARD: &str = "camera_forward";
+pub const CAMERA_BACKWARD: &str = "camera_backward";
+pub const CAMERA_RIGHT: &str = "camera_right";
+pub const CAMERA_LEFT: &str = "camera_left";
+pub const CAMERA_UP: &str = "camera_up";
+pub const CAMERA_DOWN: &str = "camera_down";
+pub const CAMERA_SLOW: &str = "camera_slow";
+pub const CAMERA_SPRINT: &str = "camera_sprint";
+pub const CAMERA_ROTATE: &str = "camera_rotate";
+pub const CAMERA_PAN: &str = "camera_pan";
+
+/// The camera zoom axis (orbit mode).
+pub const CAMERA_ZOOM: &str = "camera_zoom";
+
+/// The prefixes of the bookmark actions. The slot number is appended (e.g. camera_bookmark_save_1).
+pub const CAMERA_BOOKMARK_SAVE: &str = "camera_bookmark_save_";
+pub const CAMERA_BOOKMARK_RECALL: &str = "camera_bookmark_recall_";
+
+/// The default file for the camera bindings.
+pub const CAMERA_BINDINGS_FILE: &str = "camera_bindings.ron";
 // pub use winit::event::VirtualKeyCode; //VirtualKeyCode;
                                       
 pub use winit::event::MouseButton as MouseButton;
@@ -19,6 +52,14 @@ fn clamp(min: f32, max: f32, val: f32) -> f32 {
     else { val }
 }
 
+/// The default vertical field of view of the perspective projection and the ray camera.
+const DEFAULT_FOV_Y: f32 = std::f32::consts::PI / 2.0;
+
+/// The horizontal and vertical fov of the ray camera for a vertical fov and aspect ratio.
+fn ray_fov(fov_y: f32, aspect: f32) -> cgmath::Vector2<f32> {
+    cgmath::Vector2::new(2.0 * ((0.5 * fov_y).tan() * aspect).atan(), fov_y)
+}
+
 
 /// Opengl to wgpu matrix
 //#[cfg_attr(rustfmt, surtfmt_skip)]
@@ -56,8 +97,77 @@ pub struct RayCameraUniform {
     padding2: [u32; 2],
 }
 
-/// A camera for basic rendering and ray tracing purposes.
-pub struct Camera {
+/// The camera projection. Angles are in radians.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Projection {
+    /// Perspective projection with a vertical field of view.
+    Perspective { fov_y: f32 },
+    /// Orthographic projection. The height of the view volume is given in world units and the
+    /// width is height * aspect.
+    Orthographic { height: f32 },
+    /// Perspective projection with reverse-Z and an infinite far plane. Requires a depth buffer
+    /// with DepthConvention::ReverseZ.
+    ReverseZInfinite { fov_y: f32 },
+}
+
+impl Projection {
+
+    /// The depth convention required by the projection.
+    pub fn depth_convention(&self) -> DepthConvention {
+        match self {
+            Projection::ReverseZInfinite { .. } => DepthConvention::ReverseZ,
+            _ => DepthConvention::Standard,
+        }
+    }
+
+    /// Check that the fov is in range (0, PI) or the orthographic height is > 0.
+    pub fn validate(&self) -> Result<(), EngineError> {
+        match *self {
+            Projection::Perspective { fov_y } | Projection::ReverseZInfinite { fov_y } if !(fov_y > 0.0 && fov_y < std::f32::consts::PI) =>
+                Err(EngineError::InvalidCameraParameter(format!("Fov must be in range (0, PI). Fov == {}", fov_y))),
+            Projection::Orthographic { height } if !(height > 0.0 && height.is_finite()) =>
+                Err(EngineError::InvalidCameraParameter(format!("Orthographic height must be > 0. Height == {}", height))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Build the projection matrix for wgpu (depth range [0, 1]).
+    pub fn build_matrix(&self, aspect: f32, znear: f32, zfar: f32) -> cgmath::Matrix4<f32> {
+        match *self {
+            Projection::Perspective { fov_y } => {
+                OPENGL_TO_WGPU_MATRIX * cgmath::perspective(cgmath::Rad(fov_y), aspect, znear, zfar)
+            }
+            Projection::Orthographic { height } => {
+                let (w, h) = (0.5 * height * aspect, 0.5 * height);
+                OPENGL_TO_WGPU_MATRIX * cgmath::ortho(-w, w, -h, h, znear, zfar)
+            }
+            Projection::ReverseZInfinite { fov_y } => {
+                // Depth is znear / distance: 1.0 on the near plane and 0.0 at infinity.
+                let f = 1.0 / (0.5 * fov_y).tan();
+                cgmath::Matrix4::new(
+                    f / aspect, 0.0, 0.0,  0.0,
+                    0.0,        f,   0.0,  0.0,
+                    0.0,        0.0, 0.0, -1.0,
+                    0.0,        0.0, znear, 0.0,
+                )
+            }
+        }
+    }
+}
+
+/// The camera control mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CameraMode {
+    /// Free-fly camera. Move with the movement actions and rotate with the rotate action.
+    Fly,
+    /// Orbit around a target point. Rotate with the rotate action, pan with the pan action and
+    /// zoom with the zoom axis.
+    Orbit,
+}
+
+/// The camera pose, projection and controller without any gpu resources. Produces the uniform
+/// data for CameraBinding.
+pub struct CameraState {
     pos: cgmath::Vector3<f32>,
     view: cgmath::Vector3<f32>,
     up: cgmath::Vector3<f32>,
@@ -71,13 +181,29 @@ pub struct Camera {
     yaw: f32,
     aperture_radius: f32, // For ray tracer camera.
     focal_distance: f32, // For ray tracer camera.
-    camera_buffer: Option<wgpu::Buffer>, // A buffer to basic camera uniform buffer.
-    ray_camera_buffer: Option<wgpu::Buffer>, // A buffer to ray tracing camear uniform buffer.
     restriction_area: [cgmath::Vector3<f32> ; 2],
     restriction_area_enabled: bool,
+    actions: ActionMap,
+    mode: CameraMode,
+    orbit_target: cgmath::Vector3<f32>,
+    orbit_target_goal: cgmath::Vector3<f32>,
+    orbit_distance: f32,
+    orbit_distance_goal: f32,
+    zoom_sensitivity: f32,
+    pan_sensitivity: f32,
+    smoothing_time: f32, // The time constant of orbit target and distance smoothing in milli seconds.
+    path_player: Option<PathPlayer>, // The camera path that overrides the user input.
+    projection: Projection,
+    collider: CameraCollider,
+    controller: CameraController,
+    bookmarks: CameraBookmarks,
+    bookmarks_file: Option<PathBuf>, // The bookmarks are saved here when a bookmark is saved with a hotkey.
+    bookmark_transition_time: f32, // The duration of the transition to a recalled bookmark in seconds.
+    transition: Option<SnapshotTransition>, // The transition that overrides the user input.
+    dirty: bool, // The uniform data has changed since the previous upload.
 }
 
-impl Camera {
+impl CameraState {
 
     pub fn set_movement_sensitivity(&mut self, sensitivity: f32) {
 
@@ -90,12 +216,11 @@ impl Camera {
         self.rotation_sensitivity = sensitivity;
     }
 
-    pub fn set_focal_distance(&mut self, value: f32, queue: &wgpu::Queue) {
+    pub fn set_focal_distance(&mut self, value: f32) {
         if value > 0.0 {
             self.focal_distance = value;
+            self.dirty = true;
         }
-        // self.update_camera(&queue);
-        self.update_ray_camera(queue);
     }
 
     pub fn get_focal_distance(&self) -> f32 {
@@ -110,78 +235,38 @@ impl Camera {
         [self.pos.x, self.pos.y, self.pos.z]
     }
 
-    pub fn move_forward(&mut self, amount: f32, queue: &wgpu::Queue) {
+    pub fn move_forward(&mut self, amount: f32) {
         self.pos += self.view * amount;
-        self.update_camera(queue);
-        self.update_ray_camera(queue);
+        self.dirty = true;
     }
-    pub fn set_lookat(&mut self, at: [f32; 3], queue: &wgpu::Queue) {
+    pub fn set_lookat(&mut self, at: [f32; 3]) {
         self.view = Vector3::new(
             at[0] - self.pos.x, // - at[0],
             at[1] - self.pos.y, // - at[1],
             at[2] - self.pos.z, // - at[2],
         ).normalize_to(1.0);
-        self.update_camera(queue);
-        self.update_ray_camera(queue);
+        self.dirty = true;
     }
 
-    /// Get a reference to camera uniform buffer. Creates the buffer is it doens't already exist.
-    pub fn get_camera_uniform(&mut self, device: &wgpu::Device) -> &wgpu::Buffer {
-
-        // The camera uniform buffer doesn't exist. Create camera buffer.
-        if self.camera_buffer.is_none() {
-
-            // Create camera uniform data.
-            let camera_uniform = CameraUniform {
-                view_proj: self.build_projection_matrix(),
-                pos: Vector4::new(self.pos.x, self.pos.y, self.pos.z, 1.0),
-            };
+    /// Has the uniform data changed since the previous upload.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
 
-            self.camera_buffer = Some(buffer_from_data::<CameraUniform>(
-                device,
-                &[camera_uniform],
-                wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-                None)
-            );
-        }
+    /// Force the next CameraBinding::upload to write the uniforms.
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
 
-        self.camera_buffer.as_ref().unwrap()
+    /// Get the aspect ratio width / height.
+    pub fn get_aspect(&self) -> f32 {
+        self.aspect
     }
-    
-    // TODO: update uniform?
+
     pub fn resize(&mut self, aspect_width: f32, aspect_height: f32) {
         self.aspect = aspect_width / aspect_height;
-    }
-
-    /// Get a reference to ray tracing camera uniform buffer. Creates the buffer is it doesn't already exist.
-    /// TODO: create buffer on init().
-    pub fn get_ray_camera_uniform(&mut self, device: &wgpu::Device) -> &wgpu::Buffer {
-
-        // The ray camera uniform buffer doesn't exist. Create ray camera buffer.
-        if self.ray_camera_buffer.is_none() {
-
-            // println!("Creating ray camera uniform.");
-            // Create ray camera uniform data.
-            let ray_camera_uniform = RayCameraUniform {
-                pos: [self.pos.x, self.pos.y, self.pos.z],
-                aperture_radius: self.aperture_radius,
-                view: [self.view.x, self.view.y, self.view.z],
-                focal_distance: self.focal_distance,
-                up: [self.up.x, self.up.y, self.up.z],
-                padding: 0,
-                fov: [self.fov.x, self.fov.y],
-                padding2: [0, 0],
-            };
-
-            self.ray_camera_buffer = Some(buffer_from_data::<RayCameraUniform>(
-                device,
-                &[ray_camera_uniform],
-                wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-                None)
-            );
-        }
-
-        self.ray_camera_buffer.as_ref().unwrap()
+        self.fov = ray_fov(self.fov.y, self.aspect);
+        self.dirty = true;
     }
 
     /// TODO: something better.
@@ -205,7 +290,7 @@ impl Camera {
             view, //Vector3::new(0.0, 0.0, -1.0).normalize(),
             up: cgmath::Vector3::unit_y(),
             aspect: aspect_width / aspect_height,
-            fov: (1.485387,0.785387).into(),
+            fov: ray_fov(DEFAULT_FOV_Y, aspect_width / aspect_height),
             znear: 0.01,
             zfar: 1000.0,
             movement_sensitivity: 0.003,
@@ -214,134 +299,539 @@ impl Camera {
             yaw, // -50.5,
             aperture_radius: 0.01,
             focal_distance: 1.0,
-            camera_buffer: None,
-            ray_camera_buffer: None,
             restriction_area: [cgmath::Vector3::<f32>::new(0.0, 0.0, 0.0), cgmath::Vector3::<f32>::new(0.0, 0.0, 0.0)],
             restriction_area_enabled: false,
+            actions: Self::default_bindings(),
+            mode: CameraMode::Fly,
+            orbit_target: look_at_position.into(),
+            orbit_target_goal: look_at_position.into(),
+            orbit_distance: 10.0,
+            orbit_distance_goal: 10.0,
+            zoom_sensitivity: 0.1,
+            pan_sensitivity: 0.001,
+            smoothing_time: 80.0,
+            path_player: None,
+            projection: Projection::Perspective { fov_y: DEFAULT_FOV_Y },
+            collider: CameraCollider::init(),
+            controller: CameraController::default(),
+            bookmarks: CameraBookmarks::init(),
+            bookmarks_file: None,
+            bookmark_transition_time: 1.0,
+            transition: None,
+            dirty: true,
+        }
+    }
+
+    /// The default camera bindings: WASD, E/C for up/down, left shift for slow movement, space
+    /// for sprinting and the left mouse button for rotation. Ctrl + number key saves a bookmark
+    /// and the number key recalls it. Sprint isn't bound to control, so saving a bookmark while
+    /// moving doesn't sprint.
+    pub fn default_bindings() -> ActionMap {
+        let mut actions = ActionMap::init();
+        actions.bind(CAMERA_FORWARD, Binding::Key(KeyCode::KeyW));
+        actions.bind(CAMERA_BACKWARD, Binding::Key(KeyCode::KeyS));
+        actions.bind(CAMERA_RIGHT, Binding::Key(KeyCode::KeyD));
+        actions.bind(CAMERA_LEFT, Binding::Key(KeyCode::KeyA));
+        actions.bind(CAMERA_UP, Binding::Key(KeyCode::KeyE));
+        actions.bind(CAMERA_DOWN, Binding::Key(KeyCode::KeyC));
+        actions.bind(CAMERA_SLOW, Binding::Key(KeyCode::ShiftLeft));
+        actions.bind(CAMERA_SPRINT, Binding::Key(KeyCode::Space));
+        actions.bind(CAMERA_ROTATE, Binding::MouseButton(MouseButton::Left));
+        actions.bind(CAMERA_PAN, Binding::MouseButton(MouseButton::Middle));
+        actions.bind_axis(CAMERA_ZOOM, AxisBinding::Wheel);
+
+        let digits = [KeyCode::Digit1, KeyCode::Digit2, KeyCode::Digit3, KeyCode::Digit4, KeyCode::Digit5,
+                      KeyCode::Digit6, KeyCode::Digit7, KeyCode::Digit8, KeyCode::Digit9];
+        for (slot, key) in (1..=CAMERA_BOOKMARK_SLOTS).zip(digits) {
+            actions.bind(&bookmark_save_action(slot), Binding::Chord { modifiers: vec![KeyCode::ControlLeft], key });
+            actions.bind(&bookmark_save_action(slot), Binding::Chord { modifiers: vec![KeyCode::ControlRight], key });
+            actions.bind(&bookmark_recall_action(slot), Binding::Key(key));
+        }
+        actions
+    }
+
+    /// Get the projection.
+    pub fn get_projection(&self) -> Projection {
+        self.projection
+    }
+
+    /// Set the projection. A perspective fov is also used for the ray camera. The projection
+    /// isn't changed if it's invalid (see Projection::validate).
+    pub fn set_projection(&mut self, projection: Projection) -> Result<(), EngineError> {
+        projection.validate()?;
+        match projection {
+            Projection::Perspective { fov_y } | Projection::ReverseZInfinite { fov_y } => {
+                self.fov = ray_fov(fov_y, self.aspect);
+            }
+            Projection::Orthographic { .. } => { }
+        }
+        self.projection = projection;
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Get the depth convention of the projection. Use this for depth textures and pipelines.
+    pub fn get_depth_convention(&self) -> DepthConvention {
+        self.projection.depth_convention()
+    }
+
+    /// Get the camera mode.
+    pub fn get_mode(&self) -> CameraMode {
+        self.mode
+    }
+
+    /// Change the camera mode. The camera pose doesn't change, so the switch is seamless. In orbit
+    /// mode the target is placed orbit distance away in front of the camera.
+    pub fn set_mode(&mut self, mode: CameraMode) {
+        if mode == CameraMode::Orbit && self.mode != CameraMode::Orbit {
+            self.sync_angles_from_view();
+            self.orbit_target = self.pos + self.view * self.orbit_distance;
+            self.orbit_target_goal = self.orbit_target;
+            self.orbit_distance_goal = self.orbit_distance;
+        }
+        self.mode = mode;
+        self.dirty = true;
+    }
+
+    /// Get the orbit target.
+    pub fn get_orbit_target(&self) -> [f32; 3] {
+        [self.orbit_target.x, self.orbit_target.y, self.orbit_target.z]
+    }
+
+    /// Set the orbit target. The camera moves smoothly to the new target.
+    pub fn set_orbit_target(&mut self, target: [f32; 3]) {
+        self.orbit_target_goal = target.into();
+    }
+
+    /// Set the orbit distance. The camera moves smoothly to the new distance. The distance must
+    /// be > 0.
+    pub fn set_orbit_distance(&mut self, distance: f32) -> Result<(), EngineError> {
+        if !(distance > 0.0 && distance.is_finite()) {
+            return Err(EngineError::InvalidCameraParameter(format!("Orbit distance must be > 0. Orbit distance == {}", distance)));
+        }
+        self.orbit_distance_goal = distance;
+        Ok(())
+    }
+
+    /// Set the zoom factor per wheel line. Must be in range (0, 1).
+    pub fn set_zoom_sensitivity(&mut self, sensitivity: f32) -> Result<(), EngineError> {
+        if !(sensitivity > 0.0 && sensitivity < 1.0) {
+            return Err(EngineError::InvalidCameraParameter(format!("Zoom sensitivity must be in range (0, 1). Zoom sensitivity == {}", sensitivity)));
+        }
+        self.zoom_sensitivity = sensitivity;
+        Ok(())
+    }
+
+    /// Set the pan speed. The pan amount is scaled by the orbit distance. Must be > 0.
+    pub fn set_pan_sensitivity(&mut self, sensitivity: f32) -> Result<(), EngineError> {
+        if !(sensitivity > 0.0 && sensitivity.is_finite()) {
+            return Err(EngineError::InvalidCameraParameter(format!("Pan sensitivity must be > 0. Pan sensitivity == {}", sensitivity)));
+        }
+        self.pan_sensitivity = sensitivity;
+        Ok(())
+    }
+
+    /// Set the time constant of the orbit target and distance smoothing in milli seconds. 0.0
+    /// disables smoothing.
+    pub fn set_smoothing_time(&mut self, milli_seconds: f32) -> Result<(), EngineError> {
+        if !(milli_seconds >= 0.0 && milli_seconds.is_finite()) {
+            return Err(EngineError::InvalidCameraParameter(format!("Smoothing time must be >= 0. Smoothing time == {}", milli_seconds)));
         }
+        self.smoothing_time = milli_seconds;
+        Ok(())
+    }
+
+    /// Compute pitch and yaw (in degrees) from the view vector.
+    fn sync_angles_from_view(&mut self) {
+        self.pitch = self.view.y.clamp(-1.0, 1.0).asin().to_degrees();
+        self.yaw = self.view.z.atan2(self.view.x).to_degrees();
+    }
+
+    /// Compute the view vector from pitch and yaw.
+    fn view_from_angles(&self) -> cgmath::Vector3<f32> {
+        Vector3::new(
+            self.pitch.to_radians().cos() * self.yaw.to_radians().cos(),
+            self.pitch.to_radians().sin(),
+            self.pitch.to_radians().cos() * self.yaw.to_radians().sin()
+            ).normalize_to(1.0)
+    }
+
+    /// Set the bindings used by update_from_input.
+    pub fn set_action_map(&mut self, actions: ActionMap) {
+        self.actions = actions;
+    }
+
+    /// Get the bindings used by update_from_input.
+    pub fn get_action_map(&self) -> &ActionMap {
+        &self.actions
     }
 
-    pub fn set_restriction_area(&mut self, min: [f32; 3], max: [f32; 3]) {
-        // TODO: asserts
+    /// Set the restriction area [min, max]. The min must be <= max on every axis, otherwise the
+    /// area isn't changed.
+    pub fn set_restriction_area(&mut self, min: [f32; 3], max: [f32; 3]) -> Result<(), EngineError> {
+        if !(0..3).all(|i| min[i] <= max[i]) {
+            return Err(EngineError::InvalidCameraParameter(format!("Restriction area min must be <= max. Min == {:?}, max == {:?}", min, max)));
+        }
         self.restriction_area = [cgmath::Vector3::<f32>::new(min[0], min[1], min[2]), cgmath::Vector3::<f32>::new(max[0], max[1], max[2])];
+        self.dirty = true;
+        Ok(())
     }
 
     pub fn enable_restriction_area(&mut self, enable: bool) {
         self.restriction_area_enabled = enable;
+        self.dirty = true;
     }
 
-    /// Update camera from user input. TODO: create a method for 
-    /// Bezier-curvers and B-splines.
-    pub fn update_from_input(&mut self, queue: &wgpu::Queue, input_cache: &InputCache) {
+    /// Get the collider used in fly mode. Add boxes, a capsule or ground follow to it.
+    pub fn get_collider_mut(&mut self) -> &mut CameraCollider {
+        &mut self.collider
+    }
 
-        // Get the keyboard state (camera movement).
-        let state_forward = input_cache.key_state(&KeyCode::KeyW); //&Key::W);
-        let state_backward = input_cache.key_state(&KeyCode::KeyS);
-        let state_right = input_cache.key_state(&KeyCode::KeyD);
-        let state_left = input_cache.key_state(&KeyCode::KeyA);
-        let state_up = input_cache.key_state(&KeyCode::KeyE);
-        let state_down = input_cache.key_state(&KeyCode::KeyC);
-        let left_mouse_button = input_cache.mouse_button_state(&MouseButton::Left);
-        let left_shift = input_cache.key_state(&KeyCode::ShiftLeft);
+    /// Get the controller settings.
+    pub fn get_controller_settings(&self) -> ControllerSettings {
+        *self.controller.get_settings()
+    }
 
-        // Get the delta time between previous and current tick.
-        let time_delta_nanos = input_cache.get_time_delta();
+    /// Set the acceleration, damping, speed multipliers and mouse-look options. Returns an error
+    /// if the settings are invalid (see ControllerSettings::validate).
+    pub fn set_controller_settings(&mut self, settings: ControllerSettings) -> Result<(), EngineError> {
+        self.controller.set_settings(settings)
+    }
 
-        // Convert time delta to milli seconds.
-        let time_delta_milli_f32 = time_delta_nanos as f32 / 1000000.0;
+    /// Follow the camera path. The user input is ignored until the path ends or stop_path is
+    /// called.
+    pub fn follow_path(&mut self, path: CameraPath, looping: bool) {
+        self.path_player = Some(PathPlayer::init(path, looping));
+        self.controller.reset();
+    }
 
-        // The right vector.
-        let right = self.view.cross(self.up).normalize_to(1.0);
+    /// Stop following the camera path.
+    pub fn stop_path(&mut self) {
+        if self.path_player.take().is_some() {
+            self.sync_angles_from_view();
+            self.orbit_target = self.pos + self.view * self.orbit_distance;
+            self.orbit_target_goal = self.orbit_target;
+        }
+    }
 
-        let mut movement = cgmath::Vector3::new(0.0, 0.0, 0.0);
+    /// Is the camera following a path.
+    pub fn is_following_path(&self) -> bool {
+        self.path_player.is_some()
+    }
 
-        // 1/10 speed if left shift is down.
-        let mut movement_factor = 1.0;
-        if left_shift.is_some() { movement_factor = 0.1; }
+    /// Get the serializable state of the camera.
+    pub fn snapshot(&self) -> CameraSnapshot {
+        CameraSnapshot {
+            position: self.pos.into(),
+            view: self.view.into(),
+            up: self.up.into(),
+            yaw: self.yaw,
+            pitch: self.pitch,
+            fov: self.fov.into(),
+            znear: self.znear,
+            zfar: self.zfar,
+            projection: self.projection,
+            mode: self.mode,
+            movement_sensitivity: self.movement_sensitivity,
+            rotation_sensitivity: self.rotation_sensitivity,
+            zoom_sensitivity: self.zoom_sensitivity,
+            pan_sensitivity: self.pan_sensitivity,
+            restriction_area: [self.restriction_area[0].into(), self.restriction_area[1].into()],
+            restriction_area_enabled: self.restriction_area_enabled,
+            aperture_radius: self.aperture_radius,
+            focal_distance: self.focal_distance,
+            orbit_target: self.orbit_target.into(),
+            orbit_distance: self.orbit_distance,
+        }
+    }
 
-        // Calculate the amount of movement based on user input.
-        if state_forward.is_some() { movement += movement_factor * time_delta_milli_f32 * self.view; }
-        if state_backward.is_some() { movement -= movement_factor * time_delta_milli_f32 * self.view; }
-        if state_right.is_some() { movement += movement_factor * time_delta_milli_f32 * right; }
-        if state_left.is_some() { movement -= movement_factor * time_delta_milli_f32 * right; }
-        if state_up.is_some() { movement += movement_factor * time_delta_milli_f32 * self.up; }
-        if state_down.is_some() { movement -= movement_factor * time_delta_milli_f32 * self.up; }
+    /// Restore the camera state. Stops the camera path and the bookmark transition. The camera
+    /// isn't changed if the snapshot is invalid (see CameraSnapshot::validate).
+    pub fn apply_snapshot(&mut self, snapshot: &CameraSnapshot) -> Result<(), EngineError> {
+        snapshot.validate()?;
+        self.path_player = None;
+        self.transition = None;
+        self.controller.reset();
+        self.set_snapshot(snapshot);
+        Ok(())
+    }
 
-        // log::info!("movement = {:?}", movement);
+    fn set_snapshot(&mut self, snapshot: &CameraSnapshot) {
+        self.pos = snapshot.position.into();
+        self.view = snapshot.view.into();
+        self.up = snapshot.up.into();
+        self.yaw = snapshot.yaw;
+        self.pitch = snapshot.pitch;
+        self.fov = snapshot.fov.into();
+        self.znear = snapshot.znear;
+        self.zfar = snapshot.zfar;
+        self.projection = snapshot.projection;
+        self.mode = snapshot.mode;
+        self.movement_sensitivity = snapshot.movement_sensitivity;
+        self.rotation_sensitivity = snapshot.rotation_sensitivity;
+        self.zoom_sensitivity = snapshot.zoom_sensitivity;
+        self.pan_sensitivity = snapshot.pan_sensitivity;
+        self.restriction_area = [snapshot.restriction_area[0].into(), snapshot.restriction_area[1].into()];
+        self.restriction_area_enabled = snapshot.restriction_area_enabled;
+        self.aperture_radius = snapshot.aperture_radius;
+        self.focal_distance = snapshot.focal_distance;
+        self.orbit_target = snapshot.orbit_target.into();
+        self.orbit_target_goal = self.orbit_target;
+        self.orbit_distance = snapshot.orbit_distance;
+        self.orbit_distance_goal = self.orbit_distance;
+        self.dirty = true;
+    }
+
+    /// Save the camera state to a RON file.
+    pub fn save_state(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        self.snapshot().save(path)
+    }
 
-        let new_pos = self.movement_sensitivity * movement + self.pos;
-        // Update the camera position.
-        if self.restriction_area_enabled &&
-           new_pos.x >= self.restriction_area[0].x &&
-           new_pos.y >= self.restriction_area[0].y &&
-           new_pos.z >= self.restriction_area[0].z &&
-           new_pos.x < self.restriction_area[1].x &&
-           new_pos.y < self.restriction_area[1].y &&
-           new_pos.z < self.restriction_area[1].z {
+    /// Load the camera state from a RON file.
+    pub fn load_state(&mut self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let snapshot = CameraSnapshot::load(path)?;
+        self.apply_snapshot(&snapshot).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
 
-           self.pos = new_pos;
+    /// Move smoothly to the snapshot in duration seconds. The user input is ignored until the
+    /// transition ends. A duration <= 0 applies the snapshot immediately.
+    pub fn transition_to(&mut self, snapshot: CameraSnapshot, duration: f32) -> Result<(), EngineError> {
+        if duration <= 0.0 {
+            return self.apply_snapshot(&snapshot);
         }
-        else if !self.restriction_area_enabled {
-           self.pos = new_pos;
+        snapshot.validate()?;
+        self.path_player = None;
+        self.controller.reset();
+        self.transition = Some(SnapshotTransition::init(self.snapshot(), snapshot, duration));
+        Ok(())
+    }
+
+    /// Is the camera moving to a snapshot.
+    pub fn is_transitioning(&self) -> bool {
+        self.transition.is_some()
+    }
+
+    /// Get the bookmarks.
+    pub fn get_bookmarks(&self) -> &CameraBookmarks {
+        &self.bookmarks
+    }
+
+    /// Replace the bookmarks.
+    pub fn set_bookmarks(&mut self, bookmarks: CameraBookmarks) {
+        self.bookmarks = bookmarks;
+    }
+
+    /// Set the duration of the transition to a recalled bookmark in seconds.
+    pub fn set_bookmark_transition_time(&mut self, seconds: f32) {
+        assert!(seconds >= 0.0, "Transition time must be >= 0.");
+        self.bookmark_transition_time = seconds;
+    }
+
+    /// Load the bookmarks from the file if it exists and save them there whenever a bookmark is
+    /// saved with a hotkey.
+    pub fn use_bookmarks_file(&mut self, path: impl AsRef<Path>) {
+        let path = path.as_ref();
+        if path.exists() {
+            match CameraBookmarks::load(path) {
+                Ok(bookmarks) => self.bookmarks = bookmarks,
+                Err(e) => log::warn!("Failed to load camera bookmarks {}: {}", path.display(), e),
+            }
         }
+        self.bookmarks_file = Some(path.to_path_buf());
+    }
 
-        // Rotation.
-          
-        let md = input_cache.get_mouse_delta();
+    /// Store the current camera state to the bookmark slot.
+    pub fn save_bookmark(&mut self, slot: u8) {
+        self.bookmarks.set(slot, self.snapshot());
+        if let Some(path) = self.bookmarks_file.as_ref() {
+            if let Err(e) = self.bookmarks.save(path) {
+                log::error!("Failed to save camera bookmarks {}: {}", path.display(), e);
+            }
+        }
+    }
 
-        // If left mouse is down update pitch, yaw and view.
-        if let Some(InputState::Down(_,_)) = left_mouse_button {
+    /// Move smoothly to the bookmark. Returns false if the slot is empty or the bookmark is
+    /// invalid.
+    pub fn recall_bookmark(&mut self, slot: u8) -> bool {
+        match self.bookmarks.get(slot).copied() {
+            Some(snapshot) => match self.transition_to(snapshot, self.bookmark_transition_time) {
+                Ok(()) => true,
+                Err(e) => {
+                    log::warn!("Camera bookmark {}: {}", slot, e);
+                    false
+                }
+            },
+            None => false,
+        }
+    }
 
-            self.pitch = clamp(-80.0, 80.0, self.pitch + self.rotation_sensitivity * (md.y * (-1.0)) as f32);
-            self.yaw += self.rotation_sensitivity * md.x as f32 ;
+    /// Save or recall bookmarks with the bookmark actions. Saving takes precedence, because the
+    /// number key of a save chord also triggers the recall action.
+    fn update_bookmarks(&mut self, input_cache: &InputCache) {
+        for slot in 1..=CAMERA_BOOKMARK_SLOTS {
+            if self.actions.is_pressed(&bookmark_save_action(slot), input_cache) {
+                self.save_bookmark(slot);
+                log::info!("Camera bookmark {} saved.", slot);
+            }
+            else if self.actions.is_pressed(&bookmark_recall_action(slot), input_cache) {
+                if !self.recall_bookmark(slot) { log::info!("Camera bookmark {} is empty.", slot); }
+            }
+        }
+    }
 
-            self.view = Vector3::new(
-                self.pitch.to_radians().cos() * self.yaw.to_radians().cos(),
-                self.pitch.to_radians().sin(),
-                self.pitch.to_radians().cos() * self.yaw.to_radians().sin()
-                ).normalize_to(1.0);
+    /// Apply a camera path sample. The fov of the sample is used only by perspective
+    /// projections (also for the ray camera).
+    fn apply_path_sample(&mut self, sample: &PathSample) {
+        self.pos = sample.position.into();
+        let view = Vector3::<f32>::from(sample.look_at) - self.pos;
+        if view.magnitude2() > 0.0 { self.view = view.normalize(); }
+        match &mut self.projection {
+            Projection::Perspective { fov_y } | Projection::ReverseZInfinite { fov_y } => {
+                *fov_y = sample.fov;
+                self.fov = ray_fov(sample.fov, self.aspect);
+            }
+            Projection::Orthographic { .. } => { }
+        }
+    }
+
+    /// Update camera from user input or from the camera path.
+    pub fn update_from_input(&mut self, input_cache: &InputCache) {
+
+        let (old_pos, old_view, old_fov) = (self.pos, self.view, self.fov);
+        let time_delta_secs = input_cache.get_time_delta() as f32 / 1000000000.0;
+
+        if self.path_player.is_none() {
+            self.update_bookmarks(input_cache);
+        }
+
+        if let Some(player) = self.path_player.as_mut() {
+            let sample = player.advance(time_delta_secs);
+            let finished = player.is_finished();
+            self.apply_path_sample(&sample);
+            if finished { self.stop_path(); }
+        }
+        else if let Some(transition) = self.transition.as_mut() {
+            let snapshot = transition.advance(time_delta_secs);
+            let finished = transition.is_finished();
+            self.set_snapshot(&snapshot);
+            if finished { self.transition = None; }
+            else { self.sync_angles_from_view(); }
+        }
+        else {
+            match self.mode {
+                CameraMode::Fly => self.update_fly(input_cache),
+                CameraMode::Orbit => self.update_orbit(input_cache),
+            }
         }
 
-        // Update the camera uniform and the camera uniform buffer.
-        // TODO: refactor.
-        self.update_camera(queue);
-        self.update_ray_camera(queue);
+        if self.pos != old_pos || self.view != old_view || self.fov != old_fov {
+            self.dirty = true;
+        }
     }
 
-    fn update_camera(&self, queue: &wgpu::Queue) {
-        if self.camera_buffer.is_some() {
+    /// Free-fly movement and rotation. The movement accelerates and damps with the controller
+    /// and the speed is movement sensitivity * 1000 units per second.
+    fn update_fly(&mut self, input_cache: &InputCache) {
+
+        // The time delta between previous and current tick in seconds.
+        let time_delta = input_cache.get_time_delta() as f32 / 1000000000.0;
+
+        // The right vector.
+        let right = self.view.cross(self.up).normalize_to(1.0);
+
+        // The wanted movement direction from the camera actions.
+        let mut direction = cgmath::Vector3::new(0.0, 0.0, 0.0);
+        if self.actions.is_active(CAMERA_FORWARD, input_cache) { direction += self.view; }
+        if self.actions.is_active(CAMERA_BACKWARD, input_cache) { direction -= self.view; }
+        if self.actions.is_active(CAMERA_RIGHT, input_cache) { direction += right; }
+        if self.actions.is_active(CAMERA_LEFT, input_cache) { direction -= right; }
+        if self.actions.is_active(CAMERA_UP, input_cache) { direction += self.up; }
+        if self.actions.is_active(CAMERA_DOWN, input_cache) { direction -= self.up; }
+
+        let multiplier = self.controller.speed_multiplier(
+            self.actions.is_active(CAMERA_SPRINT, input_cache),
+            self.actions.is_active(CAMERA_SLOW, input_cache));
+        let movement = self.controller.advance(direction, self.movement_sensitivity * 1000.0 * multiplier, time_delta);
+
+        // Update the camera position. The movement slides along the restriction area and the
+        // collider boxes.
+        let restriction = if self.restriction_area_enabled { Some(self.restriction_area) } else { None };
+        let pos = self.collider.resolve(self.pos, movement, restriction);
+        self.controller.block_axes(movement, pos - self.pos);
+        self.pos = pos;
 
-            // Create camera uniform data. TODO: refactor.
-            let camera_uniform = CameraUniform {
-                view_proj: self.build_projection_matrix(),
-                pos: Vector4::new(self.pos.x, self.pos.y, self.pos.z, 1.0),
-            };
-            queue.write_buffer(
-                self.camera_buffer.as_ref().unwrap(),
-                0,
-                bytemuck::cast_slice(&[camera_uniform]));
+        // Rotation while the rotate action (left mouse) is down.
+        let look = self.filtered_look(input_cache, self.actions.is_down(CAMERA_ROTATE, input_cache), time_delta);
+        if look != [0.0, 0.0] {
+            self.pitch = clamp(-80.0, 80.0, self.pitch - self.rotation_sensitivity * look[1]);
+            self.yaw += self.rotation_sensitivity * look[0];
+            self.view = self.view_from_angles();
         }
     }
 
-    fn update_ray_camera(&self, queue: &wgpu::Queue) {
-        if self.ray_camera_buffer.is_some() {
+    /// The smoothed mouse-look. The mouse movement is taken into account only while rotating,
+    /// but the pending smoothed rotation is applied after the rotation stops.
+    fn filtered_look(&mut self, input_cache: &InputCache, rotating: bool, time_delta: f32) -> [f32; 2] {
+        let md = input_cache.get_mouse_delta();
+        let raw = if rotating { [md.x as f32, md.y as f32] } else { [0.0, 0.0] };
+        self.controller.filter_look(raw, time_delta)
+    }
+
+    /// Orbit rotation, panning and zooming around the orbit target.
+    fn update_orbit(&mut self, input_cache: &InputCache) {
+
+        let md = input_cache.get_mouse_delta();
+        let time_delta = input_cache.get_time_delta() as f32 / 1000000000.0;
+
+        // Rotate around the target.
+        let look = self.filtered_look(input_cache, self.actions.is_down(CAMERA_ROTATE, input_cache), time_delta);
+        self.pitch = clamp(-80.0, 80.0, self.pitch - self.rotation_sensitivity * look[1]);
+        self.yaw += self.rotation_sensitivity * look[0];
+        self.view = self.view_from_angles();
+
+        // Move the target on the view plane.
+        if self.actions.is_down(CAMERA_PAN, input_cache) {
+            let right = self.view.cross(self.up).normalize_to(1.0);
+            let camera_up = right.cross(self.view).normalize_to(1.0);
+            let pan = (right * (-md.x as f32) + camera_up * md.y as f32) * self.pan_sensitivity * self.orbit_distance;
+            self.orbit_target_goal += pan;
+            self.orbit_target += pan;
+        }
+
+        // Zoom. A positive wheel delta moves the camera closer.
+        let zoom = self.actions.axis(CAMERA_ZOOM, input_cache);
+        if zoom != 0.0 {
+            self.orbit_distance_goal = (self.orbit_distance_goal * (1.0 - self.zoom_sensitivity).powf(zoom)).max(self.znear * 2.0);
+        }
+
+        // Approach the target and distance goals exponentially.
+        let time_delta_milli = input_cache.get_time_delta() as f32 / 1000000.0;
+        let t = if self.smoothing_time > 0.0 { 1.0 - (-time_delta_milli / self.smoothing_time).exp() } else { 1.0 };
+        self.orbit_target += (self.orbit_target_goal - self.orbit_target) * t;
+        self.orbit_distance += (self.orbit_distance_goal - self.orbit_distance) * t;
 
-            // Create ray camera uniform data.
-            let ray_camera_uniform = RayCameraUniform {
-                pos: [self.pos.x, self.pos.y, self.pos.z],
-                aperture_radius: self.aperture_radius,
-                view: [self.view.x, self.view.y, self.view.z],
-                focal_distance: self.focal_distance,
-                up: [self.up.x, self.up.y, self.up.z],
-                padding: 0,
-                fov: [self.fov.x, self.fov.y],
-                padding2: [0, 0],
-            };
+        self.pos = self.orbit_target - self.view * self.orbit_distance;
+    }
 
-            queue.write_buffer(
-                self.ray_camera_buffer.as_ref().unwrap(),
-                0,
-                bytemuck::cast_slice(&[ray_camera_uniform]));
+    /// The uniform data of the basic camera.
+    pub fn camera_uniform(&self) -> CameraUniform {
+        CameraUniform {
+            view_proj: self.build_projection_matrix(),
+            pos: Vector4::new(self.pos.x, self.pos.y, self.pos.z, 1.0),
+        }
+    }
+
+    /// The uniform data of the ray tracing camera.
+    pub fn ray_camera_uniform(&self) -> RayCameraUniform {
+        RayCameraUniform {
+            pos: [self.pos.x, self.pos.y, self.pos.z],
+            aperture_radius: self.aperture_radius,
+            view: [self.view.x, self.view.y, self.view.z],
+            focal_distance: self.focal_distance,
+            up: [self.up.x, self.up.y, self.up.z],
+            padding: 0,
+            fov: [self.fov.x, self.fov.y],
+            padding2: [0, 0],
         }
     }
 
@@ -349,10 +839,44 @@ impl Camera {
     pub fn build_projection_matrix(&self) -> cgmath::Matrix4<f32> {
 
         let view = self.build_view_matrix();
-        let proj = cgmath::perspective(cgmath::Rad(std::f32::consts::PI/2.0), self.aspect, self.znear, self.zfar);
+        let proj = self.projection.build_matrix(self.aspect, self.znear, self.zfar);
+
+        proj * view
+    }
+
+    /// Build the view frustum from the projection matrix.
+    pub fn build_frustum(&self) -> Frustum {
+        Frustum::from_matrix(&self.build_projection_matrix())
+    }
+
+    /// Create a ray from the camera through the cursor position. The viewport is [x, y, width,
+    /// height] in pixels and the cursor position is in window pixels (see
+    /// InputCache::get_cursor_position). Returns None for an empty viewport or if the view
+    /// projection matrix isn't invertible.
+    pub fn screen_ray(&self, cursor_pos: [f32; 2], viewport: [f32; 4]) -> Option<Ray> {
+
+        if viewport[2] <= 0.0 || viewport[3] <= 0.0 { return None; }
+
+        let ndc_x = 2.0 * (cursor_pos[0] - viewport[0]) / viewport[2] - 1.0;
+        let ndc_y = 1.0 - 2.0 * (cursor_pos[1] - viewport[1]) / viewport[3];
+
+        // The near plane depth and a depth in the middle of the depth range.
+        let near_depth = match self.get_depth_convention() {
+            DepthConvention::Standard => 0.0,
+            DepthConvention::ReverseZ => 1.0,
+        };
 
-        // Convert "opengl" matrix to wgpu matris.
-        OPENGL_TO_WGPU_MATRIX * proj * view
+        let inverse = self.build_projection_matrix().invert()?;
+        let unproject = |depth: f32| {
+            let p = inverse * Vector4::new(ndc_x, ndc_y, depth, 1.0);
+            p.truncate() / p.w
+        };
+
+        let near = unproject(near_depth);
+        let direction = unproject(0.5) - near;
+
+        if !direction.magnitude2().is_normal() { return None; }
+        Some(Ray::new(near.into(), direction.into()))
     }
 
     /// Build view projection matrix.
@@ -363,3 +887,328 @@ impl Camera {
         cgmath::Matrix4::look_at_rh(pos3, view3, self.up)
     }
 }
+
+/// The name of the action that saves the bookmark slot.
+pub fn bookmark_save_action(slot: u8) -> String {
+    format!("{}{}", CAMERA_BOOKMARK_SAVE, slot)
+}
+
+/// The name of the action that recalls the bookmark slot.
+pub fn bookmark_recall_action(slot: u8) -> String {
+    format!("{}{}", CAMERA_BOOKMARK_RECALL, slot)
+}
+
+/// The gpu buffers of a camera. The buffers are created on demand and written only when the
+/// camera state is dirty.
+#[derive(Default)]
+pub struct CameraBinding {
+    camera_buffer: Option<wgpu::Buffer>, // A buffer to basic camera uniform buffer.
+    ray_camera_buffer: Option<wgpu::Buffer>, // A buffer to ray tracing camear uniform buffer.
+}
+
+impl CameraBinding {
+
+    /// Create a binding without buffers.
+    pub fn init() -> Self {
+        Self {
+            camera_buffer: None,
+            ray_camera_buffer: None,
+        }
+    }
+
+    /// Get a reference to camera uniform buffer. Creates the buffer is it doens't already exist.
+    pub fn get_camera_uniform(&mut self, device: &wgpu::Device, state: &CameraState) -> &wgpu::Buffer {
+        self.camera_buffer.get_or_insert_with(|| {
+            buffer_from_data::<CameraUniform>(
+                device,
+                &[state.camera_uniform()],
+                wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                None)
+        })
+    }
+
+    /// Get a reference to ray tracing camera uniform buffer. Creates the buffer is it doesn't already exist.
+    pub fn get_ray_camera_uniform(&mut self, device: &wgpu::Device, state: &CameraState) -> &wgpu::Buffer {
+        self.ray_camera_buffer.get_or_insert_with(|| {
+            buffer_from_data::<RayCameraUniform>(
+                device,
+                &[state.ray_camera_uniform()],
+                wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                None)
+        })
+    }
+
+    /// Get the camera uniform buffer if it has been created.
+    pub fn get_camera_buffer(&self) -> Option<&wgpu::Buffer> {
+        self.camera_buffer.as_ref()
+    }
+
+    /// Write the uniforms to the existing buffers if the state is dirty. Clears the dirty flag.
+    pub fn upload(&self, queue: &wgpu::Queue, state: &mut CameraState) {
+        if !state.dirty { return; }
+
+        if let Some(buffer) = self.camera_buffer.as_ref() {
+            queue.write_buffer(buffer, 0, bytemuck::cast_slice(&[state.camera_uniform()]));
+        }
+        if let Some(buffer) = self.ray_camera_buffer.as_ref() {
+            queue.write_buffer(buffer, 0, bytemuck::cast_slice(&[state.ray_camera_uniform()]));
+        }
+        state.dirty = false;
+    }
+}
+
+/// A camera for basic rendering and ray tracing purposes. Combines CameraState and CameraBinding.
+/// The state is accessed with get_state and get_state_mut.
+pub struct Camera {
+    state: CameraState,
+    binding: CameraBinding,
+}
+
+impl Camera {
+
+    /// TODO: something better.
+    pub fn new(aspect_width: f32, aspect_height: f32, start_position: (f32, f32, f32), look_at_position: (f32, f32, f32)) -> Self {
+        Self {
+            state: CameraState::new(aspect_width, aspect_height, start_position, look_at_position),
+            binding: CameraBinding::init(),
+        }
+    }
+
+    /// See CameraState::default_bindings.
+    pub fn default_bindings() -> ActionMap {
+        CameraState::default_bindings()
+    }
+
+    /// Get the camera state.
+    pub fn get_state(&self) -> &CameraState {
+        &self.state
+    }
+
+    /// Get the mutable camera state. The uniforms are uploaded on the next update_from_input or
+    /// upload if the state changes.
+    pub fn get_state_mut(&mut self) -> &mut CameraState {
+        &mut self.state
+    }
+
+    /// Get a reference to camera uniform buffer. Creates the buffer is it doens't already exist.
+    pub fn get_camera_uniform(&mut self, device: &wgpu::Device) -> &wgpu::Buffer {
+        self.binding.get_camera_uniform(device, &self.state)
+    }
+
+    /// Get a reference to ray tracing camera uniform buffer. Creates the buffer is it doesn't already exist.
+    pub fn get_ray_camera_uniform(&mut self, device: &wgpu::Device) -> &wgpu::Buffer {
+        self.binding.get_ray_camera_uniform(device, &self.state)
+    }
+
+    /// Get the camera uniform buffer if it has been created (see Camera::get_camera_uniform).
+    pub fn get_camera_buffer(&self) -> Option<&wgpu::Buffer> {
+        self.binding.get_camera_buffer()
+    }
+
+    /// Update camera from user input and upload the uniforms if they have changed.
+    pub fn update_from_input(&mut self, queue: &wgpu::Queue, input_cache: &InputCache) {
+        self.state.update_from_input(input_cache);
+        self.binding.upload(queue, &mut self.state);
+    }
+
+    /// Upload the uniforms if the state has changed.
+    pub fn upload(&mut self, queue: &wgpu::Queue) {
+        self.binding.upload(queue, &mut self.state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input_cache::InputClock;
+    use crate::input_record::InputEvent;
+    use winit::event::ElementState;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn ray_fov_matches_the_projection() {
+        let mut state = CameraState::new(1600.0, 900.0, (0.0, 0.0, 5.0), (0.0, 0.0, 0.0));
+        let Projection::Perspective { fov_y } = state.get_projection() else { panic!("Expected a perspective projection.") };
+        assert_close(state.fov.y, fov_y);
+        assert_close((0.5 * state.fov.x).tan(), (0.5 * fov_y).tan() * 1600.0 / 900.0);
+
+        state.resize(900.0, 900.0);
+        assert_close(state.fov.x, fov_y);
+
+        state.set_projection(Projection::ReverseZInfinite { fov_y: 1.0 }).unwrap();
+        assert_close(state.fov.x, 1.0);
+        assert_close(state.fov.y, 1.0);
+    }
+
+    #[test]
+    fn invalid_projections_are_rejected() {
+        let mut state = state();
+        let invalid = [
+            Projection::Perspective { fov_y: 0.0 },
+            Projection::Perspective { fov_y: std::f32::consts::PI },
+            Projection::ReverseZInfinite { fov_y: f32::NAN },
+            Projection::Orthographic { height: 0.0 },
+            Projection::Orthographic { height: f32::INFINITY },
+        ];
+        for projection in invalid {
+            assert!(matches!(state.set_projection(projection), Err(EngineError::InvalidCameraParameter(_))), "{:?}", projection);
+        }
+        assert_eq!(state.get_projection(), Projection::Perspective { fov_y: DEFAULT_FOV_Y });
+    }
+
+    #[test]
+    fn path_sample_keeps_the_ray_fov_relation() {
+        let sample = PathSample { position: [0.0, 0.0, 5.0], look_at: [0.0, 0.0, 0.0], fov: 0.8 };
+
+        let mut state = CameraState::new(1600.0, 900.0, (0.0, 0.0, 5.0), (0.0, 0.0, 0.0));
+        state.apply_path_sample(&sample);
+        assert_eq!(state.get_projection(), Projection::Perspective { fov_y: 0.8 });
+        assert_close(state.fov.y, 0.8);
+        assert_close((0.5 * state.fov.x).tan(), (0.4f32).tan() * 1600.0 / 900.0);
+
+        // The orthographic projection has no fov.
+        state.set_projection(Projection::Orthographic { height: 2.0 }).unwrap();
+        let fov = state.fov;
+        state.apply_path_sample(&PathSample { fov: 1.2, ..sample });
+        assert_eq!(state.get_projection(), Projection::Orthographic { height: 2.0 });
+        assert_eq!(state.fov, fov);
+    }
+
+    #[test]
+    fn screen_ray_through_the_viewport_center() {
+        let state = CameraState::new(800.0, 600.0, (0.0, 0.0, 5.0), (0.0, 0.0, 0.0));
+        let ray = state.screen_ray([400.0, 300.0], [0.0, 0.0, 800.0, 600.0]).unwrap();
+        assert_close(ray.direction.x, state.view.x);
+        assert_close(ray.direction.y, state.view.y);
+        assert_close(ray.direction.z, state.view.z);
+
+        assert!(state.screen_ray([0.0, 0.0], [0.0, 0.0, 0.0, 600.0]).is_none());
+    }
+
+    /// A camera at (0, 0, 5) looking at the origin.
+    fn state() -> CameraState {
+        let mut state = CameraState::new(800.0, 600.0, (0.0, 0.0, 5.0), (0.0, 0.0, 0.0));
+        state.set_lookat([0.0, 0.0, 0.0]);
+        state
+    }
+
+    /// An input cache with key held from time 0 to time nanos.
+    fn held_key(key: KeyCode, nanos: u128) -> InputCache {
+        let mut input = InputCache::with_clock(InputClock::Manual(0));
+        input.pre_update_with_time(0);
+        input.push_event(&InputEvent::Key { key, state: ElementState::Pressed });
+        input.pre_update_with_time(nanos);
+        input
+    }
+
+    #[test]
+    fn setters_mark_the_state_dirty() {
+        let mut state = state();
+        let setters: [&dyn Fn(&mut CameraState); 5] = [
+            &|s| s.set_mode(CameraMode::Orbit),
+            &|s| s.set_restriction_area([-1.0; 3], [1.0; 3]).unwrap(),
+            &|s| s.enable_restriction_area(true),
+            &|s| s.set_projection(Projection::Orthographic { height: 2.0 }).unwrap(),
+            &|s| s.resize(100.0, 100.0),
+        ];
+        for set in setters {
+            state.dirty = false;
+            set(&mut state);
+            assert!(state.is_dirty());
+        }
+    }
+
+    #[test]
+    fn update_without_input_keeps_the_state_clean() {
+        let mut state = state();
+        state.dirty = false;
+        let mut input = InputCache::with_clock(InputClock::Manual(0));
+        input.pre_update_with_time(16_000_000);
+        state.update_from_input(&input);
+        assert!(!state.is_dirty());
+        assert_eq!(state.get_position(), [0.0, 0.0, 5.0]);
+    }
+
+    #[test]
+    fn forward_moves_along_the_view() {
+        let mut state = state();
+        state.dirty = false;
+        state.update_from_input(&held_key(KeyCode::KeyW, 100_000_000));
+
+        let [x, y, z] = state.get_position();
+        assert_close(x, 0.0);
+        assert_close(y, 0.0);
+        assert!(z < 5.0, "z {}", z);
+        assert!(state.is_dirty());
+    }
+
+    #[test]
+    fn restriction_area_limits_the_movement() {
+        let mut state = state();
+        state.set_restriction_area([-1.0, -1.0, 4.9], [1.0, 1.0, 5.0]).unwrap();
+        state.enable_restriction_area(true);
+        for _ in 0..10 {
+            state.update_from_input(&held_key(KeyCode::KeyW, 100_000_000));
+        }
+        assert_close(state.get_position()[2], 4.9);
+    }
+
+    #[test]
+    fn bookmark_save_chord_doesnt_sprint() {
+        let actions = CameraState::default_bindings();
+        let mut input = held_key(KeyCode::ControlLeft, 1_000_000);
+        input.push_event(&InputEvent::Key { key: KeyCode::Digit1, state: ElementState::Pressed });
+
+        assert!(actions.is_pressed(&bookmark_save_action(1), &input));
+        assert!(!actions.is_active(CAMERA_SPRINT, &input));
+    }
+
+    #[test]
+    fn inverted_restriction_area_is_rejected() {
+        let mut state = state();
+        state.set_restriction_area([-1.0; 3], [1.0; 3]).unwrap();
+        state.dirty = false;
+
+        for (min, max) in [([1.0; 3], [-1.0; 3]), ([0.0, 2.0, 0.0], [1.0, 1.0, 1.0]), ([f32::NAN; 3], [1.0; 3])] {
+            assert!(matches!(state.set_restriction_area(min, max), Err(EngineError::InvalidCameraParameter(_))));
+        }
+        assert!(!state.is_dirty());
+        assert_eq!(state.restriction_area, [Vector3::new(-1.0, -1.0, -1.0), Vector3::new(1.0, 1.0, 1.0)]);
+    }
+
+    #[test]
+    fn invalid_orbit_settings_are_rejected() {
+        let mut state = state();
+        assert!(state.set_orbit_distance(3.0).is_ok());
+        assert_eq!(state.orbit_distance_goal, 3.0);
+
+        for distance in [0.0, -1.0, f32::NAN, f32::INFINITY] {
+            assert!(matches!(state.set_orbit_distance(distance), Err(EngineError::InvalidCameraParameter(_))));
+        }
+        for sensitivity in [0.0, 1.0, f32::NAN] {
+            assert!(state.set_zoom_sensitivity(sensitivity).is_err());
+        }
+        assert!(state.set_pan_sensitivity(-0.1).is_err());
+        assert!(state.set_smoothing_time(-1.0).is_err());
+        assert!(state.set_smoothing_time(0.0).is_ok());
+
+        // The rejected values aren't applied.
+        assert_eq!(state.orbit_distance_goal, 3.0);
+        assert_eq!(state.zoom_sensitivity, 0.1);
+        assert_eq!(state.pan_sensitivity, 0.001);
+    }
+
+    #[test]
+    fn orbit_target_is_in_front_of_the_camera() {
+        let mut state = state();
+        state.orbit_distance = 5.0;
+        state.set_mode(CameraMode::Orbit);
+        let [x, y, z] = state.get_orbit_target();
+        assert_close(x, 0.0);
+        assert_close(y, 0.0);
+        assert_close(z, 0.0);
+    }
+}
@@ -0,0 +1,264 @@
+use cgmath::{prelude::*, Vector3};
+
+use crate::error::EngineError;
+
+/// The settings of the camera controller. The rates are in 1/seconds: the velocity approaches
+/// its target exponentially so that 1 - exp(-rate * t) of the difference is covered in t
+/// seconds. An infinite rate gives an immediate response.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ControllerSettings {
+    /// The rate of approaching the target velocity while a movement action is active.
+    pub acceleration: f32,
+    /// The rate of slowing down when no movement action is active.
+    pub damping: f32,
+    /// The speed multiplier of the sprint action.
+    pub sprint_multiplier: f32,
+    /// The speed multiplier of the slow action.
+    pub slow_multiplier: f32,
+    /// The time constant of the mouse-look smoothing in seconds. 0.0 disables the smoothing.
+    pub mouse_smoothing: f32,
+    /// Invert the vertical mouse-look.
+    pub invert_y: bool,
+}
+
+impl Default for ControllerSettings {
+    fn default() -> Self {
+        Self {
+            acceleration: 12.0,
+            damping: 8.0,
+            sprint_multiplier: 3.0,
+            slow_multiplier: 0.1,
+            mouse_smoothing: 0.02,
+            invert_y: false,
+        }
+    }
+}
+
+impl ControllerSettings {
+
+    /// Check that the rates are > 0, the speed multipliers finite and >= 0 and the mouse
+    /// smoothing finite and >= 0.
+    pub fn validate(&self) -> Result<(), EngineError> {
+        if !(self.acceleration > 0.0 && self.damping > 0.0) {
+            return Err(EngineError::InvalidCameraParameter(format!(
+                "Acceleration and damping must be > 0. Acceleration == {}, damping == {}", self.acceleration, self.damping)));
+        }
+        if !([self.sprint_multiplier, self.slow_multiplier].iter().all(|m| *m >= 0.0 && m.is_finite())) {
+            return Err(EngineError::InvalidCameraParameter(format!(
+                "Speed multipliers must be >= 0. Sprint == {}, slow == {}", self.sprint_multiplier, self.slow_multiplier)));
+        }
+        if !(self.mouse_smoothing >= 0.0 && self.mouse_smoothing.is_finite()) {
+            return Err(EngineError::InvalidCameraParameter(format!("Mouse smoothing must be >= 0. Mouse smoothing == {}", self.mouse_smoothing)));
+        }
+        Ok(())
+    }
+}
+
+/// A frame rate independent camera motion model. The velocity and the filtered mouse-look are
+/// integrated in closed form, so splitting a time step into smaller steps gives the same result.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraController {
+    settings: ControllerSettings,
+    velocity: Vector3<f32>,
+    pending_look: [f32; 2],
+}
+
+impl Default for CameraController {
+    fn default() -> Self {
+        Self::at_rest(ControllerSettings::default())
+    }
+}
+
+impl CameraController {
+
+    /// Create a controller at rest. The settings must be valid (see ControllerSettings::validate).
+    pub fn init(settings: ControllerSettings) -> Result<Self, EngineError> {
+        settings.validate()?;
+        Ok(Self::at_rest(settings))
+    }
+
+    fn at_rest(settings: ControllerSettings) -> Self {
+        Self {
+            settings,
+            velocity: Vector3::zero(),
+            pending_look: [0.0, 0.0],
+        }
+    }
+
+    /// Get the settings.
+    pub fn get_settings(&self) -> &ControllerSettings {
+        &self.settings
+    }
+
+    /// Set the settings. The current motion is kept. The settings aren't changed if the new
+    /// settings are invalid.
+    pub fn set_settings(&mut self, settings: ControllerSettings) -> Result<(), EngineError> {
+        settings.validate()?;
+        self.settings = settings;
+        Ok(())
+    }
+
+    /// Get the velocity in units per second.
+    pub fn get_velocity(&self) -> Vector3<f32> {
+        self.velocity
+    }
+
+    /// Stop the movement and discard the pending mouse-look.
+    pub fn reset(&mut self) {
+        self.velocity = Vector3::zero();
+        self.pending_look = [0.0, 0.0];
+    }
+
+    /// The speed multiplier of the sprint and slow actions. Slow takes precedence.
+    pub fn speed_multiplier(&self, sprint: bool, slow: bool) -> f32 {
+        if slow { self.settings.slow_multiplier }
+        else if sprint { self.settings.sprint_multiplier }
+        else { 1.0 }
+    }
+
+    /// Advance the velocity towards direction * speed over time_delta seconds and return the
+    /// displacement. The direction is normalized if it's longer than one and a zero direction
+    /// slows the camera down with the damping rate.
+    pub fn advance(&mut self, direction: Vector3<f32>, speed: f32, time_delta: f32) -> Vector3<f32> {
+
+        if time_delta <= 0.0 { return Vector3::zero(); }
+
+        let direction = if direction.magnitude2() > 1.0 { direction.normalize() } else { direction };
+        let target = direction * speed;
+        let rate = if direction.magnitude2() > 0.0 { self.settings.acceleration } else { self.settings.damping };
+
+        // v(t) = target + (v0 - target) * exp(-rate * t) and its integral.
+        let decay = (-rate * time_delta).exp();
+        let difference = self.velocity - target;
+        let displacement = target * time_delta + difference * ((1.0 - decay) / rate);
+
+        self.velocity = target + difference * decay;
+        displacement
+    }
+
+    /// Cancel the velocity along the axes where the movement was blocked (e.g. by a collision).
+    pub fn block_axes(&mut self, wanted: Vector3<f32>, resolved: Vector3<f32>) {
+        for axis in 0..3 {
+            if (wanted[axis] - resolved[axis]).abs() > 1e-6 { self.velocity[axis] = 0.0; }
+        }
+    }
+
+    /// Add a raw mouse delta and return the part of the mouse-look applied over time_delta
+    /// seconds. The rest is applied on the following frames, so the total rotation equals the
+    /// total mouse movement. Applies the invert-Y setting.
+    pub fn filter_look(&mut self, mouse_delta: [f32; 2], time_delta: f32) -> [f32; 2] {
+
+        let sign_y = if self.settings.invert_y { -1.0 } else { 1.0 };
+        self.pending_look[0] += mouse_delta[0];
+        self.pending_look[1] += mouse_delta[1] * sign_y;
+
+        let f = if self.settings.mouse_smoothing > 0.0 {
+            1.0 - (-time_delta.max(0.0) / self.settings.mouse_smoothing).exp()
+        }
+        else { 1.0 };
+
+        let applied = [self.pending_look[0] * f, self.pending_look[1] * f];
+        self.pending_look[0] -= applied[0];
+        self.pending_look[1] -= applied[1];
+        applied
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Vector3<f32>, b: Vector3<f32>) {
+        assert!((a - b).magnitude() < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn advance_is_independent_of_the_step_count() {
+        let direction = Vector3::new(1.0, 0.0, 1.0);
+
+        let mut whole = CameraController::default();
+        let whole_displacement = whole.advance(direction, 5.0, 0.5);
+
+        for steps in [2, 7, 60] {
+            let mut split = CameraController::default();
+            let mut displacement = Vector3::zero();
+            for _ in 0..steps {
+                displacement += split.advance(direction, 5.0, 0.5 / steps as f32);
+            }
+            assert_close(split.get_velocity(), whole.get_velocity());
+            assert_close(displacement, whole_displacement);
+        }
+    }
+
+    #[test]
+    fn velocity_decays_at_the_damping_rate() {
+        let mut controller = CameraController::default();
+        controller.advance(Vector3::unit_x(), 4.0, 10.0);
+        assert_close(controller.get_velocity(), Vector3::new(4.0, 0.0, 0.0));
+
+        controller.advance(Vector3::zero(), 4.0, 0.25);
+        let damping = controller.get_settings().damping;
+        assert_close(controller.get_velocity(), Vector3::new(4.0 * (-damping * 0.25).exp(), 0.0, 0.0));
+    }
+
+    #[test]
+    fn slow_takes_precedence_over_sprint() {
+        let controller = CameraController::default();
+        let settings = controller.get_settings();
+        assert_eq!(controller.speed_multiplier(false, false), 1.0);
+        assert_eq!(controller.speed_multiplier(true, false), settings.sprint_multiplier);
+        assert_eq!(controller.speed_multiplier(false, true), settings.slow_multiplier);
+        assert_eq!(controller.speed_multiplier(true, true), settings.slow_multiplier);
+    }
+
+    #[test]
+    fn filtered_look_sums_to_the_raw_delta() {
+        let mut controller = CameraController::default();
+        let mut total = [0.0, 0.0];
+        let deltas = [[10.0, -4.0], [3.0, 8.0], [0.0, 0.0]];
+
+        for i in 0..200 {
+            let applied = controller.filter_look(if i < deltas.len() { deltas[i] } else { [0.0, 0.0] }, 1.0 / 60.0);
+            total[0] += applied[0];
+            total[1] += applied[1];
+        }
+        assert!((total[0] - 13.0).abs() < 1e-3, "{:?}", total);
+        assert!((total[1] - 4.0).abs() < 1e-3, "{:?}", total);
+    }
+
+    #[test]
+    fn invert_y_flips_the_vertical_look() {
+        let settings = ControllerSettings { mouse_smoothing: 0.0, ..ControllerSettings::default() };
+        let mut normal = CameraController::init(settings).unwrap();
+        let mut inverted = CameraController::init(ControllerSettings { invert_y: true, ..settings }).unwrap();
+
+        assert_eq!(normal.filter_look([2.0, 3.0], 0.016), [2.0, 3.0]);
+        assert_eq!(inverted.filter_look([2.0, 3.0], 0.016), [2.0, -3.0]);
+    }
+
+    #[test]
+    fn invalid_settings_are_rejected() {
+        let valid = ControllerSettings::default();
+        let invalid = [
+            ControllerSettings { acceleration: 0.0, ..valid },
+            ControllerSettings { damping: f32::NAN, ..valid },
+            ControllerSettings { sprint_multiplier: -1.0, ..valid },
+            ControllerSettings { slow_multiplier: f32::INFINITY, ..valid },
+            ControllerSettings { mouse_smoothing: -0.5, ..valid },
+        ];
+
+        let mut controller = CameraController::default();
+        controller.advance(Vector3::unit_x(), 4.0, 10.0);
+        for settings in invalid {
+            assert!(matches!(CameraController::init(settings), Err(EngineError::InvalidCameraParameter(_))));
+            assert!(matches!(controller.set_settings(settings), Err(EngineError::InvalidCameraParameter(_))));
+        }
+        assert_eq!(controller.get_settings(), &valid);
+
+        // Infinite rates are allowed and the motion is kept when the settings change.
+        let immediate = ControllerSettings { acceleration: f32::INFINITY, damping: f32::INFINITY, ..valid };
+        controller.set_settings(immediate).unwrap();
+        assert_eq!(controller.get_settings(), &immediate);
+        assert_close(controller.get_velocity(), Vector3::new(4.0, 0.0, 0.0));
+    }
+}
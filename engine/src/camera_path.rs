@@ -0,0 +1,435 @@
+use std::path::Path;
+use serde::{Serialize, Deserialize};
+
+use crate::error::EngineError;
+
+/// The number of arc length samples per curve segment.
+const ARC_LENGTH_SAMPLES: usize = 64;
+
+/// A camera keyframe. The time is in seconds and the fov is the vertical field of view in radians.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Keyframe {
+    pub position: [f32; 3],
+    pub look_at: [f32; 3],
+    pub fov: f32,
+    pub time: f32,
+}
+
+/// The interpolation of the keyframes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Interpolation {
+    /// Piecewise cubic Bezier. The keyframes are control points: 3k+1 keyframes give k segments
+    /// and the curve passes through every third keyframe.
+    Bezier,
+    /// Catmull-Rom spline. The curve passes through all keyframes.
+    CatmullRom,
+    /// Uniform cubic B-spline. The curve is smooth but passes only through the first and the last
+    /// keyframe. The end points are reflected (2 * first - second), so keyframe i is the knot of
+    /// segment i.
+    BSpline,
+}
+
+/// A point on the camera path.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PathSample {
+    pub position: [f32; 3],
+    pub look_at: [f32; 3],
+    pub fov: f32,
+}
+
+/// A camera path through keyframes. With constant speed the camera moves with constant speed
+/// along the curve (arc length parameterisation), otherwise the keyframe times are followed.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CameraPath {
+    keyframes: Vec<Keyframe>,
+    interpolation: Interpolation,
+    constant_speed: bool,
+
+    /// Cumulative arc length at uniformly spaced curve parameters.
+    #[serde(skip)]
+    arc_lengths: Vec<f32>,
+}
+
+/// The arc lengths are derived from the keyframes and aren't compared.
+impl PartialEq for CameraPath {
+    fn eq(&self, other: &Self) -> bool {
+        self.keyframes == other.keyframes &&
+        self.interpolation == other.interpolation &&
+        self.constant_speed == other.constant_speed
+    }
+}
+
+/// Keyframe as [position, look_at, fov] for the curve evaluation.
+type Point = [f32; 7];
+
+fn to_point(k: &Keyframe) -> Point {
+    [k.position[0], k.position[1], k.position[2], k.look_at[0], k.look_at[1], k.look_at[2], k.fov]
+}
+
+fn distance(a: &Point, b: &Point) -> f32 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+}
+
+impl CameraPath {
+
+    /// Create a camera path. The keyframe times must be increasing.
+    pub fn new(keyframes: Vec<Keyframe>, interpolation: Interpolation, constant_speed: bool) -> Result<Self, EngineError> {
+        let mut path = Self {
+            keyframes,
+            interpolation,
+            constant_speed,
+            arc_lengths: Vec::new(),
+        };
+        path.validate()?;
+        path.build_arc_lengths();
+        Ok(path)
+    }
+
+    fn validate(&self) -> Result<(), EngineError> {
+        let n = self.keyframes.len();
+        if n < 2 {
+            return Err(EngineError::InvalidCameraPath("At least 2 keyframes are required.".to_string()));
+        }
+        if self.interpolation == Interpolation::Bezier && (n - 1) % 3 != 0 {
+            return Err(EngineError::InvalidCameraPath(format!("Bezier path requires 3k+1 keyframes, got {}.", n)));
+        }
+        for (i, k) in self.keyframes.iter().enumerate() {
+            if !k.time.is_finite() || !k.position.iter().chain(k.look_at.iter()).all(|v| v.is_finite()) {
+                return Err(EngineError::InvalidCameraPath(format!("Keyframe {} has a non-finite time, position or look at.", i)));
+            }
+            if !(k.fov > 0.0 && k.fov < std::f32::consts::PI) {
+                return Err(EngineError::InvalidCameraPath(format!("Keyframe {} fov must be in range (0, PI). Fov == {}", i, k.fov)));
+            }
+        }
+        if self.keyframes.windows(2).any(|w| w[1].time <= w[0].time) {
+            return Err(EngineError::InvalidCameraPath("Keyframe times must be increasing.".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Get the keyframes.
+    pub fn get_keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    /// Get the interpolation.
+    pub fn get_interpolation(&self) -> Interpolation {
+        self.interpolation
+    }
+
+    /// The duration of the path in seconds.
+    pub fn duration(&self) -> f32 {
+        self.keyframes[self.keyframes.len() - 1].time - self.keyframes[0].time
+    }
+
+    /// The length of the position curve.
+    pub fn length(&self) -> f32 {
+        *self.arc_lengths.last().unwrap_or(&0.0)
+    }
+
+    /// The number of curve segments.
+    fn segment_count(&self) -> usize {
+        let n = self.keyframes.len();
+        match self.interpolation {
+            Interpolation::Bezier => (n - 1) / 3,
+            Interpolation::CatmullRom | Interpolation::BSpline => n - 1,
+        }
+    }
+
+    /// The number of keyframes per segment. The keyframes at multiples of the stride are the
+    /// segment ends (knots) and their times are followed.
+    fn knot_stride(&self) -> usize {
+        (self.keyframes.len() - 1) / self.segment_count()
+    }
+
+    /// The control point i of the curve. Catmull-Rom has the end points duplicated and B-spline
+    /// has them reflected, so the curve starts at the first and ends at the last keyframe.
+    fn control_point(&self, i: isize) -> Point {
+        let n = self.keyframes.len() as isize;
+        let point = |index: isize| to_point(&self.keyframes[index.clamp(0, n - 1) as usize]);
+        match self.interpolation {
+            Interpolation::Bezier => point(i),
+            Interpolation::CatmullRom => point(i - 1),
+            Interpolation::BSpline => {
+                let index = i - 1;
+                if index >= 0 && index < n { return point(index); }
+                let (end, inner) = if index < 0 { (point(0), point(1)) } else { (point(n - 1), point(n - 2)) };
+                let mut p = [0.0; 7];
+                for k in 0..7 { p[k] = 2.0 * end[k] - inner[k]; }
+                p
+            }
+        }
+    }
+
+    /// Evaluate the curve at parameter s in range [0, 1].
+    fn evaluate(&self, s: f32) -> Point {
+
+        let m = self.segment_count();
+        let x = s.clamp(0.0, 1.0) * m as f32;
+        let segment = (x as usize).min(m - 1);
+        let u = x - segment as f32;
+        let (u2, u3) = (u * u, u * u * u);
+
+        let (first, weights) = match self.interpolation {
+            Interpolation::Bezier => (
+                3 * segment,
+                [(1.0 - u).powi(3), 3.0 * u * (1.0 - u).powi(2), 3.0 * u2 * (1.0 - u), u3]),
+            Interpolation::CatmullRom => (
+                segment,
+                [0.5 * (-u3 + 2.0 * u2 - u), 0.5 * (3.0 * u3 - 5.0 * u2 + 2.0), 0.5 * (-3.0 * u3 + 4.0 * u2 + u), 0.5 * (u3 - u2)]),
+            Interpolation::BSpline => (
+                segment,
+                [(1.0 - u).powi(3) / 6.0, (3.0 * u3 - 6.0 * u2 + 4.0) / 6.0, (-3.0 * u3 + 3.0 * u2 + 3.0 * u + 1.0) / 6.0, u3 / 6.0]),
+        };
+
+        let mut result = [0.0; 7];
+        for (j, w) in weights.iter().enumerate() {
+            let p = self.control_point((first + j) as isize);
+            for k in 0..7 { result[k] += w * p[k]; }
+        }
+        result
+    }
+
+    /// Sample the cumulative arc length of the position curve.
+    fn build_arc_lengths(&mut self) {
+        let count = self.segment_count() * ARC_LENGTH_SAMPLES;
+        let mut lengths = Vec::with_capacity(count + 1);
+        let mut total = 0.0;
+        let mut previous = self.evaluate(0.0);
+        lengths.push(0.0);
+
+        for i in 1..=count {
+            let p = self.evaluate(i as f32 / count as f32);
+            total += distance(&previous, &p);
+            lengths.push(total);
+            previous = p;
+        }
+        self.arc_lengths = lengths;
+    }
+
+    /// Get the curve parameter at the given arc length.
+    fn parameter_at_length(&self, length: f32) -> f32 {
+        let count = self.arc_lengths.len() - 1;
+        let index = self.arc_lengths.partition_point(|l| *l < length);
+
+        if index == 0 { return 0.0; }
+        if index > count { return 1.0; }
+
+        let (l0, l1) = (self.arc_lengths[index - 1], self.arc_lengths[index]);
+        let f = if l1 > l0 { (length - l0) / (l1 - l0) } else { 0.0 };
+        (index as f32 - 1.0 + f) / count as f32
+    }
+
+    /// Get the curve parameter at the given time. The time is mapped piecewise linearly through
+    /// the knot times, so the segment ends are reached at their keyframe times. The times of the
+    /// inner Bezier control points are ignored.
+    fn parameter_at_time(&self, time: f32) -> f32 {
+        let m = self.segment_count();
+        let stride = self.knot_stride();
+        let knot_time = |j: usize| self.keyframes[j * stride].time;
+
+        let time = time + knot_time(0);
+        let segment = (0..m).find(|&j| time < knot_time(j + 1)).unwrap_or(m - 1);
+        let (t0, t1) = (knot_time(segment), knot_time(segment + 1));
+        let f = ((time - t0) / (t1 - t0)).clamp(0.0, 1.0);
+        (segment as f32 + f) / m as f32
+    }
+
+    /// Sample the path at time seconds from the beginning of the path.
+    pub fn sample(&self, time: f32) -> PathSample {
+        let s = if self.constant_speed {
+            self.parameter_at_length(time.clamp(0.0, self.duration()) / self.duration() * self.length())
+        }
+        else {
+            self.parameter_at_time(time)
+        };
+
+        let p = self.evaluate(s);
+        PathSample {
+            position: [p[0], p[1], p[2]],
+            look_at: [p[3], p[4], p[5]],
+            fov: p[6],
+        }
+    }
+
+    /// Serialize the path to a RON string.
+    pub fn to_ron(&self) -> Result<String, ron::Error> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+    }
+
+    /// Deserialize the path from a RON string. The path is validated and the arc lengths are
+    /// rebuilt.
+    pub fn from_ron(s: &str) -> Result<Self, EngineError> {
+        let path: Self = ron::from_str(s)?;
+        Self::new(path.keyframes, path.interpolation, path.constant_speed)
+    }
+
+    /// Save the path to a RON file.
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let s = self.to_ron().map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        std::fs::write(path, s)
+    }
+
+    /// Load the path from a RON file.
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let s = std::fs::read_to_string(path)?;
+        Self::from_ron(&s).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// Plays a camera path.
+#[derive(Clone, Debug)]
+pub struct PathPlayer {
+    path: CameraPath,
+    time: f32,
+    looping: bool,
+}
+
+impl PathPlayer {
+
+    /// Create a player at the beginning of the path.
+    pub fn init(path: CameraPath, looping: bool) -> Self {
+        Self { path, time: 0.0, looping }
+    }
+
+    /// Get the path.
+    pub fn get_path(&self) -> &CameraPath {
+        &self.path
+    }
+
+    /// Is the path played to the end. A looping player never finishes.
+    pub fn is_finished(&self) -> bool {
+        !self.looping && self.time >= self.path.duration()
+    }
+
+    /// Advance the player by time_delta seconds and sample the path.
+    pub fn advance(&mut self, time_delta: f32) -> PathSample {
+        self.time += time_delta;
+        if self.looping { self.time %= self.path.duration(); }
+        self.path.sample(self.time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyframe(x: f32, time: f32) -> Keyframe {
+        Keyframe { position: [x, 0.0, 0.0], look_at: [x, 0.0, -1.0], fov: 1.0, time }
+    }
+
+    #[test]
+    fn ron_round_trip_rebuilds_arc_lengths() {
+        let keyframes = vec![keyframe(0.0, 0.0), keyframe(1.0, 1.0), keyframe(3.0, 2.0), keyframe(6.0, 3.0)];
+        let path = CameraPath::new(keyframes, Interpolation::CatmullRom, true).unwrap();
+
+        let loaded = CameraPath::from_ron(&path.to_ron().unwrap()).unwrap();
+        assert_eq!(loaded, path);
+        assert_eq!(loaded.length(), path.length());
+        assert_eq!(loaded.sample(1.5), path.sample(1.5));
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+    }
+
+    /// Keyframes on the x axis at x = 0, 1, 2, ... with uneven times and a linearly changing fov.
+    fn line(count: usize) -> Vec<Keyframe> {
+        (0..count).map(|i| Keyframe {
+            position: [i as f32, 0.0, 0.0],
+            look_at: [i as f32, 0.0, -1.0],
+            fov: 0.5 + 0.1 * i as f32,
+            time: (i * i) as f32 + i as f32,
+        }).collect()
+    }
+
+    #[test]
+    fn bezier_reaches_the_segment_ends_at_their_times() {
+        let mut keyframes = line(7);
+        // Bend the inner control points off the axis.
+        keyframes[1].position[1] = 2.0;
+        keyframes[5].position[1] = -2.0;
+        let path = CameraPath::new(keyframes.clone(), Interpolation::Bezier, false).unwrap();
+        let start = keyframes[0].time;
+
+        for k in [0, 3, 6] {
+            let sample = path.sample(keyframes[k].time - start);
+            for axis in 0..3 { assert_close(sample.position[axis], keyframes[k].position[axis]); }
+            assert_close(sample.fov, keyframes[k].fov);
+        }
+
+        // Half way between the knot times is the middle of the first segment.
+        let middle = path.sample(0.5 * (keyframes[3].time - start));
+        assert_close(middle.position[0], 1.5);
+        assert_close(middle.position[1], 0.75);
+    }
+
+    #[test]
+    fn bspline_follows_the_keyframe_times() {
+        let keyframes = line(5);
+        let path = CameraPath::new(keyframes.clone(), Interpolation::BSpline, false).unwrap();
+
+        // The B-spline reproduces linearly spaced keyframes at the knots.
+        for k in keyframes.iter() {
+            let sample = path.sample(k.time);
+            assert_close(sample.position[0], k.position[0]);
+            assert_close(sample.position[1], 0.0);
+            assert_close(sample.fov, k.fov);
+        }
+        assert_eq!(path.sample(keyframes[4].time + 1.0), path.sample(keyframes[4].time));
+    }
+
+    #[test]
+    fn bspline_passes_through_the_end_points_only() {
+        let mut keyframes = line(4);
+        keyframes[1].position[1] = 3.0;
+        let path = CameraPath::new(keyframes.clone(), Interpolation::BSpline, false).unwrap();
+
+        assert_close(path.sample(0.0).position[1], 0.0);
+        assert_close(path.sample(path.duration()).position[0], 3.0);
+        // The inner keyframe is approximated: (0 + 4 * 3 + 0) / 6.
+        assert_close(path.sample(keyframes[1].time).position[1], 2.0);
+    }
+
+    #[test]
+    fn constant_speed_samples_are_evenly_spaced() {
+        let keyframes = vec![keyframe(0.0, 0.0), keyframe(1.0, 1.0), keyframe(3.0, 2.0), keyframe(6.0, 3.0), keyframe(6.5, 4.0)];
+        for interpolation in [Interpolation::CatmullRom, Interpolation::BSpline] {
+            let path = CameraPath::new(keyframes.clone(), interpolation, true).unwrap();
+            let steps = 40;
+            let step = path.length() / steps as f32;
+
+            let mut previous = path.sample(0.0).position;
+            for i in 1..=steps {
+                let p = path.sample(path.duration() * i as f32 / steps as f32).position;
+                let d = ((p[0] - previous[0]).powi(2) + (p[1] - previous[1]).powi(2) + (p[2] - previous[2]).powi(2)).sqrt();
+                assert!((d - step).abs() < 0.02 * step, "{:?} step {}: {} != {}", interpolation, i, d, step);
+                previous = p;
+            }
+        }
+    }
+
+    #[test]
+    fn non_finite_and_out_of_range_keyframes_are_rejected() {
+        let invalid: [&dyn Fn(&mut Keyframe); 5] = [
+            &|k| k.time = f32::NAN,
+            &|k| k.position[1] = f32::INFINITY,
+            &|k| k.look_at[2] = f32::NAN,
+            &|k| k.fov = 0.0,
+            &|k| k.fov = std::f32::consts::PI,
+        ];
+        for change in invalid {
+            let mut keyframes = line(4);
+            change(&mut keyframes[2]);
+            assert!(matches!(CameraPath::new(keyframes, Interpolation::CatmullRom, false), Err(EngineError::InvalidCameraPath(_))));
+        }
+    }
+
+    #[test]
+    fn from_ron_validates_the_keyframes() {
+        let keyframes = vec![keyframe(0.0, 1.0), keyframe(1.0, 0.0)];
+        let path = CameraPath { keyframes, interpolation: Interpolation::CatmullRom, constant_speed: false, arc_lengths: Vec::new() };
+        assert!(matches!(CameraPath::from_ron(&path.to_ron().unwrap()), Err(EngineError::InvalidCameraPath(_))));
+        assert!(matches!(CameraPath::from_ron("not a path"), Err(EngineError::Ron(_))));
+    }
+}
@@ -0,0 +1,233 @@
+use crate::camera::Camera;
+use crate::error::EngineError;
+use crate::input_cache::InputCache;
+use crate::render_pass::{Viewport, ScissorRect};
+
+/// A registered camera and its viewport rectangle relative to the surface.
+struct CameraEntry {
+    name: String,
+    camera: Camera,
+    rect: [f32; 4],
+}
+
+/// A collection of named cameras for rendering one frame from several views (e.g. split screen
+/// or picture in picture). Each camera has its own uniform buffer and a viewport rectangle given
+/// as fractions [x, y, width, height] of the surface. The cameras are kept in the order they were
+/// added, so an inset view added after the main view is drawn on top of it. Clear the target with
+/// render_pass::clear_target before drawing the views.
+pub struct CameraRegistry {
+    cameras: Vec<CameraEntry>,
+    surface_width: u32,
+    surface_height: u32,
+}
+
+impl CameraRegistry {
+
+    /// Create an empty registry for a surface.
+    pub fn init(surface_width: u32, surface_height: u32) -> Self {
+        Self {
+            cameras: Vec::new(),
+            surface_width,
+            surface_height,
+        }
+    }
+
+    fn find(&self, name: &str) -> Option<&CameraEntry> {
+        self.cameras.iter().find(|e| e.name == name)
+    }
+
+    fn find_mut(&mut self, name: &str) -> Option<&mut CameraEntry> {
+        self.cameras.iter_mut().find(|e| e.name == name)
+    }
+
+    /// Check that the rectangle has a finite position and a positive width and height.
+    fn validate_rect(rect: [f32; 4]) -> Result<(), EngineError> {
+        if rect.iter().all(|v| v.is_finite()) && rect[2] > 0.0 && rect[3] > 0.0 { Ok(()) }
+        else { Err(EngineError::InvalidViewport(rect)) }
+    }
+
+    /// Add a camera with a relative viewport rectangle and set the camera aspect ratio from the
+    /// viewport. Replaces a camera with the same name (the drawing order is kept). The rectangle
+    /// must have a positive width and height.
+    pub fn add(&mut self, name: &str, mut camera: Camera, rect: [f32; 4]) -> Result<(), EngineError> {
+        Self::validate_rect(rect)?;
+        if let Some(viewport) = Viewport::from_relative(rect, self.surface_width, self.surface_height) {
+            camera.get_state_mut().resize(viewport.width, viewport.height);
+        }
+
+        match self.find_mut(name) {
+            Some(entry) => {
+                entry.camera = camera;
+                entry.rect = rect;
+            }
+            None => self.cameras.push(CameraEntry { name: name.to_string(), camera, rect }),
+        }
+        Ok(())
+    }
+
+    /// Remove a camera.
+    pub fn remove(&mut self, name: &str) -> Option<Camera> {
+        let index = self.cameras.iter().position(|e| e.name == name)?;
+        Some(self.cameras.remove(index).camera)
+    }
+
+    /// Get the names of the cameras in the drawing order.
+    pub fn get_names(&self) -> Vec<&str> {
+        self.cameras.iter().map(|e| e.name.as_str()).collect()
+    }
+
+    /// Get a camera.
+    pub fn get(&self, name: &str) -> Option<&Camera> {
+        self.find(name).map(|e| &e.camera)
+    }
+
+    /// Get a mutable camera.
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Camera> {
+        self.find_mut(name).map(|e| &mut e.camera)
+    }
+
+    /// Get the uniform buffer of a camera. Creates the buffer if it doesn't already exist.
+    pub fn get_camera_uniform(&mut self, device: &wgpu::Device, name: &str) -> Option<&wgpu::Buffer> {
+        self.find_mut(name).map(|e| e.camera.get_camera_uniform(device))
+    }
+
+    /// Get the uniform buffer of a camera if it has been created (see get_camera_uniform).
+    pub fn get_camera_buffer(&self, name: &str) -> Option<&wgpu::Buffer> {
+        self.find(name).and_then(|e| e.camera.get_camera_buffer())
+    }
+
+    /// Get the viewport of a camera in pixels. None if the camera doesn't exist or the viewport
+    /// is empty (e.g. the surface is minimized or the rectangle is outside the surface).
+    pub fn get_viewport(&self, name: &str) -> Option<Viewport> {
+        self.find(name).and_then(|e| Viewport::from_relative(e.rect, self.surface_width, self.surface_height))
+    }
+
+    /// Get the scissor rectangle covering the viewport of a camera.
+    pub fn get_scissor(&self, name: &str) -> Option<ScissorRect> {
+        self.get_viewport(name).map(|v| v.scissor())
+    }
+
+    /// Set the relative viewport rectangle of a camera and update the camera aspect ratio. The
+    /// rectangle must have a positive width and height.
+    pub fn set_rect(&mut self, name: &str, rect: [f32; 4]) -> Result<(), EngineError> {
+        Self::validate_rect(rect)?;
+        let (width, height) = (self.surface_width, self.surface_height);
+        if let Some(entry) = self.find_mut(name) {
+            entry.rect = rect;
+            if let Some(viewport) = Viewport::from_relative(rect, width, height) {
+                entry.camera.get_state_mut().resize(viewport.width, viewport.height);
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the name of the topmost camera whose viewport contains the position (in pixels).
+    pub fn get_camera_at(&self, position: [f32; 2]) -> Option<&str> {
+        self.cameras.iter().rev()
+            .find(|e| Viewport::from_relative(e.rect, self.surface_width, self.surface_height).is_some_and(|v| v.contains(position)))
+            .map(|e| e.name.as_str())
+    }
+
+    /// Resize the surface. Updates the aspect ratios of all cameras. The cameras with an empty
+    /// viewport keep their aspect ratio.
+    pub fn resize(&mut self, surface_width: u32, surface_height: u32) {
+        self.surface_width = surface_width;
+        self.surface_height = surface_height;
+
+        for entry in self.cameras.iter_mut() {
+            if let Some(viewport) = Viewport::from_relative(entry.rect, surface_width, surface_height) {
+                entry.camera.get_state_mut().resize(viewport.width, viewport.height);
+            }
+        }
+    }
+
+    /// Update a camera from user input. The other cameras are not moved.
+    pub fn update_from_input(&mut self, name: &str, queue: &wgpu::Queue, input_cache: &InputCache) {
+        if let Some(entry) = self.find_mut(name) {
+            entry.camera.update_from_input(queue, input_cache);
+        }
+    }
+
+    /// Upload the uniforms of all changed cameras.
+    pub fn upload(&mut self, queue: &wgpu::Queue) {
+        for entry in self.cameras.iter_mut() {
+            entry.camera.upload(queue);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera() -> Camera {
+        Camera::new(1.0, 1.0, (0.0, 0.0, 5.0), (0.0, 0.0, 0.0))
+    }
+
+    fn aspect(registry: &CameraRegistry, name: &str) -> f32 {
+        registry.get(name).unwrap().get_state().get_aspect()
+    }
+
+    /// A full screen main view and an inset in the top right corner.
+    fn registry() -> CameraRegistry {
+        let mut registry = CameraRegistry::init(800, 600);
+        registry.add("main", camera(), [0.0, 0.0, 1.0, 1.0]).unwrap();
+        registry.add("inset", camera(), [0.75, 0.0, 0.25, 0.5]).unwrap();
+        registry
+    }
+
+    #[test]
+    fn add_replaces_a_camera_with_the_same_name() {
+        let mut registry = registry();
+        assert_eq!(aspect(&registry, "inset"), 200.0 / 300.0);
+
+        let replacement = Camera::new(1.0, 1.0, (1.0, 2.0, 3.0), (0.0, 0.0, 0.0));
+        registry.add("main", replacement, [0.0, 0.0, 0.5, 1.0]).unwrap();
+
+        assert_eq!(registry.get_names(), vec!["main", "inset"]);
+        assert_eq!(registry.get("main").unwrap().get_state().get_position(), [1.0, 2.0, 3.0]);
+        assert_eq!(registry.get_viewport("main").unwrap().to_array(), [0.0, 0.0, 400.0, 600.0]);
+        assert_eq!(aspect(&registry, "main"), 400.0 / 600.0);
+    }
+
+    #[test]
+    fn topmost_camera_is_found_at_a_position() {
+        let mut registry = registry();
+        assert_eq!(registry.get_camera_at([700.0, 100.0]), Some("inset"));
+        assert_eq!(registry.get_camera_at([700.0, 400.0]), Some("main"));
+        assert_eq!(registry.get_camera_at([100.0, 100.0]), Some("main"));
+        assert_eq!(registry.get_camera_at([900.0, 100.0]), None);
+
+        // The camera added last is on top.
+        registry.remove("main");
+        registry.add("main", camera(), [0.0, 0.0, 1.0, 1.0]).unwrap();
+        assert_eq!(registry.get_camera_at([700.0, 100.0]), Some("main"));
+    }
+
+    #[test]
+    fn resize_and_set_rect_update_the_aspect_ratios() {
+        let mut registry = registry();
+        registry.resize(1000, 500);
+        assert_eq!(aspect(&registry, "main"), 2.0);
+        assert_eq!(aspect(&registry, "inset"), 250.0 / 250.0);
+
+        registry.set_rect("inset", [0.5, 0.5, 0.5, 0.1]).unwrap();
+        assert_eq!(aspect(&registry, "inset"), 500.0 / 50.0);
+
+        // A minimized surface keeps the aspect ratios.
+        registry.resize(0, 0);
+        assert_eq!(aspect(&registry, "main"), 2.0);
+        assert_eq!(registry.get_viewport("main"), None);
+    }
+
+    #[test]
+    fn invalid_rects_are_rejected() {
+        let mut registry = registry();
+        for rect in [[0.0, 0.0, 0.0, 1.0], [0.0, 0.0, 1.0, -1.0], [f32::NAN, 0.0, 1.0, 1.0]] {
+            assert!(matches!(registry.add("other", camera(), rect), Err(EngineError::InvalidViewport(_))));
+            assert!(matches!(registry.set_rect("inset", rect), Err(EngineError::InvalidViewport(_))));
+        }
+        assert_eq!(registry.get_names(), vec!["main", "inset"]);
+        assert_eq!(registry.get_viewport("inset").unwrap().to_array(), [600.0, 0.0, 200.0, 300.0]);
+    }
+}
@@ -0,0 +1,328 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use serde::{Serialize, Deserialize};
+use cgmath::{prelude::*, Vector3};
+
+use crate::camera::{Projection, CameraMode};
+use crate::error::EngineError;
+
+/// The number of camera bookmark slots. The slots are numbered from 1.
+pub const CAMERA_BOOKMARK_SLOTS: u8 = 9;
+
+/// The default file for the camera bookmarks.
+pub const CAMERA_BOOKMARKS_FILE: &str = "camera_bookmarks.ron";
+
+/// The serializable state of a camera. Angles are in degrees like in the camera.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CameraSnapshot {
+    pub position: [f32; 3],
+    pub view: [f32; 3],
+    pub up: [f32; 3],
+    pub yaw: f32,
+    pub pitch: f32,
+    pub fov: [f32; 2],
+    pub znear: f32,
+    pub zfar: f32,
+    pub projection: Projection,
+    pub mode: CameraMode,
+    pub movement_sensitivity: f32,
+    pub rotation_sensitivity: f32,
+    pub zoom_sensitivity: f32,
+    pub pan_sensitivity: f32,
+    pub restriction_area: [[f32; 3]; 2],
+    pub restriction_area_enabled: bool,
+    pub aperture_radius: f32,
+    pub focal_distance: f32,
+    pub orbit_target: [f32; 3],
+    pub orbit_distance: f32,
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+fn lerp3(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    [lerp(a[0], b[0], t), lerp(a[1], b[1], t), lerp(a[2], b[2], t)]
+}
+
+/// Normalized linear interpolation of directions. Falls back to b for opposite directions.
+fn nlerp3(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    let v = Vector3::from(lerp3(a, b, t));
+    if v.magnitude2() < 1e-12 { return b; }
+    v.normalize().into()
+}
+
+impl CameraSnapshot {
+
+    /// Interpolate the pose, the field of view and the ray camera parameters from a to b. The
+    /// settings (sensitivities, mode, restriction area) are taken from b. The angles are not
+    /// interpolated: they should be derived from the interpolated view.
+    pub fn interpolate(a: &CameraSnapshot, b: &CameraSnapshot, t: f32) -> CameraSnapshot {
+        let t = t.clamp(0.0, 1.0);
+
+        let projection = match (a.projection, b.projection) {
+            (Projection::Perspective { fov_y: f0 }, Projection::Perspective { fov_y: f1 }) => Projection::Perspective { fov_y: lerp(f0, f1, t) },
+            (Projection::ReverseZInfinite { fov_y: f0 }, Projection::ReverseZInfinite { fov_y: f1 }) => Projection::ReverseZInfinite { fov_y: lerp(f0, f1, t) },
+            (Projection::Orthographic { height: h0 }, Projection::Orthographic { height: h1 }) => Projection::Orthographic { height: lerp(h0, h1, t) },
+            _ => b.projection,
+        };
+
+        CameraSnapshot {
+            position: lerp3(a.position, b.position, t),
+            view: nlerp3(a.view, b.view, t),
+            up: nlerp3(a.up, b.up, t),
+            fov: [lerp(a.fov[0], b.fov[0], t), lerp(a.fov[1], b.fov[1], t)],
+            znear: lerp(a.znear, b.znear, t),
+            zfar: lerp(a.zfar, b.zfar, t),
+            projection,
+            aperture_radius: lerp(a.aperture_radius, b.aperture_radius, t),
+            focal_distance: lerp(a.focal_distance, b.focal_distance, t),
+            orbit_target: lerp3(a.orbit_target, b.orbit_target, t),
+            orbit_distance: lerp(a.orbit_distance, b.orbit_distance, t),
+            ..*b
+        }
+    }
+
+    /// Serialize the snapshot to a RON string.
+    pub fn to_ron(&self) -> Result<String, ron::Error> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+    }
+
+    /// Check that the snapshot can be applied to a camera. All values must be finite, the view and
+    /// up vectors non-zero, the projection valid, 0 < znear < zfar, the fov, focal distance and
+    /// orbit distance > 0 and the restriction area min <= max on every axis.
+    pub fn validate(&self) -> Result<(), EngineError> {
+        let scalars = [
+            self.yaw, self.pitch, self.movement_sensitivity, self.rotation_sensitivity, self.zoom_sensitivity,
+            self.pan_sensitivity, self.aperture_radius, self.focal_distance, self.orbit_distance,
+        ];
+        let vectors = [self.position, self.orbit_target, self.restriction_area[0], self.restriction_area[1]];
+        if !scalars.iter().chain(self.fov.iter()).chain(vectors.iter().flatten()).all(|v| v.is_finite()) {
+            return Err(EngineError::InvalidCameraSnapshot("Snapshot contains a non-finite value.".to_string()));
+        }
+        self.projection.validate().map_err(|e| EngineError::InvalidCameraSnapshot(e.to_string()))?;
+        if !(self.znear > 0.0 && self.znear < self.zfar && self.zfar.is_finite()) {
+            return Err(EngineError::InvalidCameraSnapshot(format!("Expected 0 < znear < zfar. znear == {}, zfar == {}", self.znear, self.zfar)));
+        }
+        if !(self.fov[0] > 0.0 && self.fov[1] > 0.0) {
+            return Err(EngineError::InvalidCameraSnapshot(format!("Fov must be > 0. Fov == {:?}", self.fov)));
+        }
+        if !(self.focal_distance > 0.0 && self.orbit_distance > 0.0 && self.aperture_radius >= 0.0) {
+            return Err(EngineError::InvalidCameraSnapshot(format!(
+                "Expected focal distance > 0, orbit distance > 0 and aperture radius >= 0. Got {}, {} and {}",
+                self.focal_distance, self.orbit_distance, self.aperture_radius)));
+        }
+        let valid_direction = |v: [f32; 3]| Vector3::from(v).magnitude2().is_normal();
+        if !valid_direction(self.view) {
+            return Err(EngineError::InvalidCameraSnapshot(format!("Invalid view vector {:?}.", self.view)));
+        }
+        if !valid_direction(self.up) {
+            return Err(EngineError::InvalidCameraSnapshot(format!("Invalid up vector {:?}.", self.up)));
+        }
+        let [min, max] = self.restriction_area;
+        if (0..3).any(|i| !(min[i] <= max[i])) {
+            return Err(EngineError::InvalidCameraSnapshot(format!("Restriction area min {:?} isn't <= max {:?}.", min, max)));
+        }
+        Ok(())
+    }
+
+    /// Deserialize and validate the snapshot from a RON string.
+    pub fn from_ron(s: &str) -> Result<Self, EngineError> {
+        let snapshot: Self = ron::from_str(s)?;
+        snapshot.validate()?;
+        Ok(snapshot)
+    }
+
+    /// Save the snapshot to a RON file.
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let s = self.to_ron().map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        std::fs::write(path, s)
+    }
+
+    /// Load the snapshot from a RON file.
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let s = std::fs::read_to_string(path)?;
+        Self::from_ron(&s).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// Numbered camera snapshots.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CameraBookmarks {
+    slots: BTreeMap<u8, CameraSnapshot>,
+}
+
+impl CameraBookmarks {
+
+    /// Create empty bookmarks.
+    pub fn init() -> Self {
+        Self { slots: BTreeMap::new() }
+    }
+
+    /// Store a snapshot to the slot.
+    pub fn set(&mut self, slot: u8, snapshot: CameraSnapshot) {
+        self.slots.insert(slot, snapshot);
+    }
+
+    /// Get the snapshot of the slot.
+    pub fn get(&self, slot: u8) -> Option<&CameraSnapshot> {
+        self.slots.get(&slot)
+    }
+
+    /// Remove the snapshot of the slot.
+    pub fn remove(&mut self, slot: u8) -> Option<CameraSnapshot> {
+        self.slots.remove(&slot)
+    }
+
+    /// Get the used slots in increasing order.
+    pub fn get_slots(&self) -> Vec<u8> {
+        self.slots.keys().copied().collect()
+    }
+
+    /// Serialize the bookmarks to a RON string.
+    pub fn to_ron(&self) -> Result<String, ron::Error> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+    }
+
+    /// Deserialize and validate the bookmarks from a RON string.
+    pub fn from_ron(s: &str) -> Result<Self, EngineError> {
+        let bookmarks: Self = ron::from_str(s)?;
+        for snapshot in bookmarks.slots.values() {
+            snapshot.validate()?;
+        }
+        Ok(bookmarks)
+    }
+
+    /// Save the bookmarks to a RON file.
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let s = self.to_ron().map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        std::fs::write(path, s)
+    }
+
+    /// Load the bookmarks from a RON file.
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let s = std::fs::read_to_string(path)?;
+        Self::from_ron(&s).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// A smooth transition between two snapshots.
+#[derive(Clone, Copy, Debug)]
+pub struct SnapshotTransition {
+    from: CameraSnapshot,
+    to: CameraSnapshot,
+    time: f32,
+    duration: f32,
+}
+
+impl SnapshotTransition {
+
+    /// Create a transition that lasts duration seconds.
+    pub fn init(from: CameraSnapshot, to: CameraSnapshot, duration: f32) -> Self {
+        Self { from, to, time: 0.0, duration }
+    }
+
+    /// Is the transition finished.
+    pub fn is_finished(&self) -> bool {
+        self.time >= self.duration
+    }
+
+    /// Get the target snapshot.
+    pub fn get_target(&self) -> &CameraSnapshot {
+        &self.to
+    }
+
+    /// Advance the transition by time_delta seconds and get the interpolated snapshot. The
+    /// transition eases in and out (smoothstep).
+    pub fn advance(&mut self, time_delta: f32) -> CameraSnapshot {
+        self.time += time_delta;
+        if self.duration <= 0.0 || self.is_finished() { return self.to; }
+
+        let t = self.time / self.duration;
+        CameraSnapshot::interpolate(&self.from, &self.to, t * t * (3.0 - 2.0 * t))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::CameraState;
+
+    fn snapshot(position: [f32; 3]) -> CameraSnapshot {
+        let mut state = CameraState::new(800.0, 600.0, (position[0], position[1], position[2]), (0.0, 0.0, 0.0));
+        state.set_lookat([0.0, 0.0, 0.0]);
+        state.snapshot()
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let a = snapshot([1.0, 2.0, 3.0]);
+        assert_eq!(CameraSnapshot::from_ron(&a.to_ron().unwrap()).unwrap(), a);
+
+        let mut bookmarks = CameraBookmarks::init();
+        bookmarks.set(1, a);
+        bookmarks.set(3, snapshot([0.0, 0.0, 5.0]));
+        assert_eq!(CameraBookmarks::from_ron(&bookmarks.to_ron().unwrap()).unwrap(), bookmarks);
+
+        let mut state = CameraState::new(800.0, 600.0, (0.0, 0.0, 1.0), (0.0, 0.0, 0.0));
+        state.apply_snapshot(&a).unwrap();
+        assert_eq!(state.snapshot(), a);
+    }
+
+    #[test]
+    fn invalid_snapshots_are_rejected() {
+        let valid = snapshot([1.0, 2.0, 3.0]);
+        let invalid = [
+            CameraSnapshot { view: [0.0; 3], ..valid },
+            CameraSnapshot { up: [0.0; 3], ..valid },
+            CameraSnapshot { restriction_area: [[1.0; 3], [0.0; 3]], ..valid },
+            CameraSnapshot { projection: Projection::Perspective { fov_y: 0.0 }, ..valid },
+            CameraSnapshot { projection: Projection::Orthographic { height: -1.0 }, ..valid },
+            CameraSnapshot { znear: 0.0, ..valid },
+            CameraSnapshot { znear: 10.0, zfar: 1.0, ..valid },
+            CameraSnapshot { zfar: f32::INFINITY, ..valid },
+            CameraSnapshot { position: [f32::NAN, 0.0, 0.0], ..valid },
+            CameraSnapshot { fov: [0.0, 0.5], ..valid },
+            CameraSnapshot { yaw: f32::INFINITY, ..valid },
+            CameraSnapshot { orbit_distance: 0.0, ..valid },
+        ];
+
+        let mut state = CameraState::new(800.0, 600.0, (0.0, 0.0, 1.0), (0.0, 0.0, 0.0));
+        let before = state.snapshot();
+        for s in invalid.iter() {
+            assert!(matches!(s.validate(), Err(EngineError::InvalidCameraSnapshot(_))));
+            assert!(CameraSnapshot::from_ron(&s.to_ron().unwrap()).is_err());
+            assert!(state.apply_snapshot(s).is_err());
+        }
+        assert_eq!(state.snapshot(), before);
+    }
+
+    #[test]
+    fn transition_eases_between_the_endpoints() {
+        let (a, b) = (snapshot([0.0, 0.0, 4.0]), snapshot([0.0, 0.0, 8.0]));
+        let mut transition = SnapshotTransition::init(a, b, 2.0);
+
+        assert_eq!(transition.advance(0.0).position, a.position);
+        // Smoothstep is 0.5 in the middle and has zero slope at the ends.
+        assert_eq!(transition.advance(1.0).position, [0.0, 0.0, 6.0]);
+        assert!(!transition.is_finished());
+        assert!(transition.advance(0.99).position[2] > 7.99);
+        assert_eq!(transition.advance(0.01).position, b.position);
+        assert!(transition.is_finished());
+    }
+
+    #[test]
+    fn transition_without_duration_is_immediate() {
+        let (a, b) = (snapshot([0.0, 0.0, 4.0]), snapshot([0.0, 0.0, 8.0]));
+        for duration in [0.0, -1.0] {
+            let mut transition = SnapshotTransition::init(a, b, duration);
+            assert_eq!(transition.advance(0.0), b);
+            assert!(transition.is_finished());
+        }
+
+        let mut state = CameraState::new(800.0, 600.0, (0.0, 0.0, 1.0), (0.0, 0.0, 0.0));
+        state.transition_to(b, 0.0).unwrap();
+        assert!(!state.is_transitioning());
+        assert_eq!(state.snapshot(), b);
+    }
+}
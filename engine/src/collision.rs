@@ -0,0 +1,295 @@
+use cgmath::Vector3;
+use crate::gpu_debugger::primitive_processor::AABB;
+use crate::misc::uvec3_to_index;
+
+/// A vertical capsule. The height includes the caps and the camera is at the center of the top
+/// cap. A capsule with zero radius and height is a point.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Capsule {
+    pub radius: f32,
+    pub height: f32,
+}
+
+impl Capsule {
+
+    /// Create a capsule. The height must be at least 2 * radius.
+    pub fn new(radius: f32, height: f32) -> Self {
+        assert!(radius >= 0.0, "Capsule radius must be >= 0.");
+        assert!(height >= 2.0 * radius, "Capsule height must be >= 2 * radius.");
+        Self { radius, height }
+    }
+
+    /// A point volume.
+    pub fn point() -> Self {
+        Self { radius: 0.0, height: 0.0 }
+    }
+
+    /// Does the capsule with the camera at pos overlap the box.
+    pub fn overlaps(&self, pos: Vector3<f32>, min: [f32; 3], max: [f32; 3]) -> bool {
+
+        // The distance from the vertical core segment to the box.
+        let (y0, y1) = (pos.y - (self.height - 2.0 * self.radius), pos.y);
+        let dx = (min[0] - pos.x).max(pos.x - max[0]).max(0.0);
+        let dy = (min[1] - y1).max(y0 - max[1]).max(0.0);
+        let dz = (min[2] - pos.z).max(pos.z - max[2]).max(0.0);
+
+        if self.radius == 0.0 {
+            // Points on the box surface don't collide, so the camera can slide along the walls.
+            return pos.x > min[0] && pos.x < max[0] && y1 > min[1] && y0 < max[1] && pos.z > min[2] && pos.z < max[2];
+        }
+        dx * dx + dy * dy + dz * dz < self.radius * self.radius
+    }
+
+    /// The open interval of camera coordinates along axis where the capsule overlaps the box.
+    /// The other coordinates are taken from pos. None if the capsule never overlaps the box
+    /// when moving along the axis.
+    pub fn blocked_interval(&self, pos: Vector3<f32>, axis: usize, min: [f32; 3], max: [f32; 3]) -> Option<(f32, f32)> {
+
+        let core_length = self.height - 2.0 * self.radius;
+        let (y0, y1) = (pos.y - core_length, pos.y);
+
+        // The distances to the box along the other axes.
+        let mut distance2 = 0.0;
+        for other in (0..3).filter(|a| *a != axis) {
+            let (lo, hi) = if other == 1 { (y0, y1) } else { (pos[other], pos[other]) };
+            if self.radius == 0.0 {
+                if !(hi > min[other] && lo < max[other]) { return None; }
+            }
+            else {
+                let d = (min[other] - hi).max(lo - max[other]).max(0.0);
+                distance2 += d * d;
+            }
+        }
+
+        let remaining = self.radius * self.radius - distance2;
+        if self.radius > 0.0 && remaining <= 0.0 { return None; }
+        let e = remaining.max(0.0).sqrt();
+
+        // The camera is at the top of the core segment.
+        let extra = if axis == 1 { core_length } else { 0.0 };
+        Some((min[axis] - e, max[axis] + extra + e))
+    }
+}
+
+/// A source of ground heights.
+pub trait HeightField {
+    /// The ground height at (x, z). None if there is no ground.
+    fn height_at(&self, x: f32, z: f32) -> Option<f32>;
+}
+
+impl<F: Fn(f32, f32) -> Option<f32>> HeightField for F {
+    fn height_at(&self, x: f32, z: f32) -> Option<f32> {
+        self(x, z)
+    }
+}
+
+/// A 3d scalar field (e.g. a noise buffer read back from the gpu) addressed by uvec3_to_index.
+/// Values >= iso_value are solid.
+#[derive(Clone, Debug)]
+pub struct ScalarField {
+    pub origin: [f32; 3],
+    pub voxel_size: f32,
+    pub dimensions: [u32; 3],
+    pub iso_value: f32,
+    pub values: Vec<f32>,
+}
+
+impl ScalarField {
+
+    /// Create a scalar field. The number of values must match the dimensions.
+    pub fn new(origin: [f32; 3], voxel_size: f32, dimensions: [u32; 3], iso_value: f32, values: Vec<f32>) -> Self {
+        assert!(voxel_size > 0.0, "Voxel size must be > 0.");
+        assert_eq!(values.len(), (dimensions[0] * dimensions[1] * dimensions[2]) as usize, "Value count doesn't match the dimensions.");
+        Self { origin, voxel_size, dimensions, iso_value, values }
+    }
+
+    fn value(&self, x: u32, y: u32, z: u32) -> f32 {
+        self.values[uvec3_to_index(x, y, z, self.dimensions[0], self.dimensions[1]) as usize]
+    }
+}
+
+impl HeightField for ScalarField {
+
+    /// Scan the column nearest to (x, z) from top to bottom and return the height of the first
+    /// crossing from empty to solid. The height is interpolated between the samples.
+    fn height_at(&self, x: f32, z: f32) -> Option<f32> {
+
+        let gx = ((x - self.origin[0]) / self.voxel_size).round();
+        let gz = ((z - self.origin[2]) / self.voxel_size).round();
+        if gx < 0.0 || gz < 0.0 || gx >= self.dimensions[0] as f32 || gz >= self.dimensions[2] as f32 {
+            return None;
+        }
+        let (gx, gz) = (gx as u32, gz as u32);
+
+        for y in (0..self.dimensions[1]).rev() {
+            let below = self.value(gx, y, gz);
+            if below < self.iso_value { continue; }
+            if y + 1 == self.dimensions[1] { return Some(self.origin[1] + y as f32 * self.voxel_size); }
+
+            let above = self.value(gx, y + 1, gz);
+            let f = (below - self.iso_value) / (below - above);
+            return Some(self.origin[1] + (y as f32 + f) * self.voxel_size);
+        }
+        None
+    }
+}
+
+/// Resolves camera movement against boxes and the restriction area. The movement is resolved
+/// one axis at a time, so a blocked axis doesn't stop the movement along the other axes.
+pub struct CameraCollider {
+    boxes: Vec<AABB>,
+    volume: Capsule,
+    ground: Option<(Box<dyn HeightField>, f32)>,
+}
+
+impl Default for CameraCollider {
+    fn default() -> Self {
+        Self::init()
+    }
+}
+
+impl CameraCollider {
+
+    /// Create a collider without boxes. The camera is a point.
+    pub fn init() -> Self {
+        Self {
+            boxes: Vec::new(),
+            volume: Capsule::point(),
+            ground: None,
+        }
+    }
+
+    /// Set the solid boxes.
+    pub fn set_boxes(&mut self, boxes: &[AABB]) {
+        self.boxes = boxes.to_vec();
+    }
+
+    /// Add a solid box.
+    pub fn add_box(&mut self, aabb: AABB) {
+        self.boxes.push(aabb);
+    }
+
+    /// Get the solid boxes.
+    pub fn get_boxes(&self) -> &[AABB] {
+        &self.boxes
+    }
+
+    /// Set the player volume. None makes the camera a point.
+    pub fn set_capsule(&mut self, capsule: Option<Capsule>) {
+        self.volume = capsule.unwrap_or(Capsule::point());
+    }
+
+    /// Keep the camera eye_height above the ground. The vertical movement is ignored while ground
+    /// follow is enabled and there is ground below the camera.
+    pub fn set_ground_follow(&mut self, field: impl HeightField + 'static, eye_height: f32) {
+        self.ground = Some((Box::new(field), eye_height));
+    }
+
+    /// Disable ground follow.
+    pub fn disable_ground_follow(&mut self) {
+        self.ground = None;
+    }
+
+    /// Does the volume at pos overlap any box.
+    pub fn is_blocked(&self, pos: Vector3<f32>) -> bool {
+        self.boxes.iter().any(|b| self.volume.overlaps(pos, [b.min[0], b.min[1], b.min[2]], [b.max[0], b.max[1], b.max[2]]))
+    }
+
+    /// Move from pos by movement and return the resolved position. The position is clamped to the
+    /// restriction area [min, max] and the movement along each axis stops at the first box. Boxes
+    /// the camera is already inside don't block, so the camera can get out.
+    pub fn resolve(&self, pos: Vector3<f32>, movement: Vector3<f32>, restriction: Option<[Vector3<f32>; 2]>) -> Vector3<f32> {
+
+        let mut p = pos;
+
+        for axis in 0..3 {
+            if movement[axis] == 0.0 { continue; }
+
+            let start = p[axis];
+            let mut target = start + movement[axis];
+            if let Some([min, max]) = restriction {
+                // Not clamp: an inverted restriction area must not panic.
+                target = target.max(min[axis]).min(max[axis]);
+            }
+
+            for b in self.boxes.iter() {
+                let interval = self.volume.blocked_interval(p, axis, [b.min[0], b.min[1], b.min[2]], [b.max[0], b.max[1], b.max[2]]);
+                if let Some((lo, hi)) = interval {
+                    if target > start && start <= lo && target > lo { target = lo; }
+                    if target < start && start >= hi && target < hi { target = hi; }
+                }
+            }
+            p[axis] = target;
+        }
+
+        if let Some((field, eye_height)) = self.ground.as_ref() {
+            if let Some(h) = field.height_at(p.x, p.z) {
+                p.y = h + eye_height;
+            }
+        }
+
+        p
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_box(min: [f32; 3]) -> AABB {
+        AABB { min: [min[0], min[1], min[2], 0.0], max: [min[0] + 1.0, min[1] + 1.0, min[2] + 1.0, 0.0] }
+    }
+
+    #[test]
+    fn movement_slides_along_a_wall() {
+        let mut collider = CameraCollider::init();
+        collider.add_box(unit_box([1.0, 0.0, 0.0]));
+
+        // Move diagonally into the wall at x = 1: x stops at the wall, z keeps moving.
+        let p = collider.resolve(Vector3::new(0.5, 0.5, 0.5), Vector3::new(1.0, 0.0, 2.0), None);
+        assert_eq!(p, Vector3::new(1.0, 0.5, 2.5));
+    }
+
+    #[test]
+    fn capsule_stops_at_its_radius() {
+        let mut collider = CameraCollider::init();
+        collider.add_box(unit_box([1.0, 0.0, 0.0]));
+        collider.set_capsule(Some(Capsule::new(0.25, 0.5)));
+
+        let p = collider.resolve(Vector3::new(0.0, 0.5, 0.5), Vector3::new(2.0, 0.0, 0.0), None);
+        assert!((p.x - 0.75).abs() < 1e-6, "{:?}", p);
+    }
+
+    #[test]
+    fn restriction_area_clamps_the_movement() {
+        let collider = CameraCollider::init();
+        let area = [Vector3::new(-1.0, -1.0, -1.0), Vector3::new(1.0, 1.0, 1.0)];
+        let p = collider.resolve(Vector3::new(0.0, 0.0, 0.0), Vector3::new(5.0, -5.0, 0.5), Some(area));
+        assert_eq!(p, Vector3::new(1.0, -1.0, 0.5));
+
+        // An inverted area doesn't panic.
+        let inverted = [Vector3::new(1.0, 1.0, 1.0), Vector3::new(-1.0, -1.0, -1.0)];
+        collider.resolve(Vector3::new(0.0, 0.0, 0.0), Vector3::new(5.0, 5.0, 5.0), Some(inverted));
+    }
+
+    #[test]
+    fn ground_follow_keeps_the_eye_height() {
+        let mut collider = CameraCollider::init();
+        collider.set_ground_follow(|x: f32, _z: f32| if x < 10.0 { Some(0.5 * x) } else { None }, 2.0);
+
+        let p = collider.resolve(Vector3::new(0.0, 2.0, 0.0), Vector3::new(4.0, 1.0, 0.0), None);
+        assert_eq!(p, Vector3::new(4.0, 4.0, 0.0));
+
+        // Without ground below, the vertical movement is kept.
+        let p = collider.resolve(Vector3::new(10.0, 2.0, 0.0), Vector3::new(1.0, 1.0, 0.0), None);
+        assert_eq!(p, Vector3::new(11.0, 3.0, 0.0));
+    }
+
+    #[test]
+    fn scalar_field_height_is_interpolated() {
+        // A 1x3x1 column: solid at y = 0 and y = 1 (value 1.0 and 0.75), empty at y = 2.
+        let field = ScalarField::new([0.0, 0.0, 0.0], 1.0, [1, 3, 1], 0.5, vec![1.0, 0.75, 0.25]);
+        assert_eq!(field.height_at(0.0, 0.0), Some(1.5));
+        assert_eq!(field.height_at(5.0, 0.0), None);
+    }
+}
@@ -1,5 +1,3 @@
-use crate::impl_convert;
-use crate::misc::Convert2Vec;
 use bytemuck::{Pod, Zeroable};
 
 #[repr(C)]
@@ -18,6 +16,3 @@ pub struct DispatchIndirect {
     pub y: u32,
     pub z: u32,
 }
-
-impl_convert!{DrawIndirect}
-impl_convert!{DispatchIndirect}
@@ -1,9 +1,11 @@
 use wgpu::TextureView;
 use crate::input_cache::InputCache;
+use crate::error::EngineError;
+use crate::frame_stats::FrameStats;
 use std::sync::Arc;
 use winit::{
     dpi::PhysicalSize,
-    event::{Event,StartCause}, // KeyEvent, , WindowEvent},
+    event::{Event,StartCause,WindowEvent}, // KeyEvent},
     event_loop::{EventLoop}, //, EventLoopWindowTarget},
     // keyboard::{Key, NamedKey},
     window::Window,
@@ -23,7 +25,13 @@ pub struct WGPUContext {
 /// A trait for event loops.
 pub trait Loop: Sized + 'static {
     fn init() -> Self;
-    fn start<A: Application>(title: &str, context: WGPUContext, surface: SurfaceWrapper, window_loop: EventLoopWrapper); //, wgpu_context: WGPUContext);
+    fn start<A: Application>(title: &str, context: WGPUContext, surface: SurfaceWrapper, window_loop: Option<EventLoopWrapper>); //, wgpu_context: WGPUContext);
+
+    /// The size of the offscreen render target. If this is Some, the loop is started without a
+    /// window and a surface.
+    fn headless_size() -> Option<PhysicalSize<u32>> {
+        None
+    }
 }
 
 /// A trait for application wgpu-features.
@@ -50,6 +58,64 @@ pub trait WGPUFeatures: Sized + 'static {
     fn required_limits() -> wgpu::Limits {
         wgpu::Limits::downlevel_webgl2_defaults()
     }
+
+    /// A feature set that is used if the adapter doesn't meet the requirements above.
+    fn fallback() -> Option<FeatureSet> {
+        None
+    }
+}
+
+/// Features, downlevel capabilities and limits for creating a device.
+#[derive(Clone, Debug)]
+pub struct FeatureSet {
+    pub optional_features: wgpu::Features,
+    pub required_features: wgpu::Features,
+    pub required_downlevel_capabilities: wgpu::DownlevelCapabilities,
+    pub required_limits: wgpu::Limits,
+}
+
+impl FeatureSet {
+
+    /// Create a feature set from application wgpu-features.
+    pub fn from_features<F: WGPUFeatures>() -> Self {
+        Self {
+            optional_features: F::optional_features(),
+            required_features: F::required_features(),
+            required_downlevel_capabilities: F::required_downlevel_capabilities(),
+            required_limits: F::required_limits(),
+        }
+    }
+
+    /// Check that the adapter supports this feature set.
+    pub fn check(&self, adapter: &wgpu::Adapter) -> Result<(), EngineError> {
+
+        let adapter_features = adapter.features();
+        if !adapter_features.contains(self.required_features) {
+            return Err(EngineError::MissingFeatures(self.required_features - adapter_features));
+        }
+
+        let downlevel_capabilities = adapter.get_downlevel_capabilities();
+        if downlevel_capabilities.shader_model < self.required_downlevel_capabilities.shader_model {
+            return Err(EngineError::UnsupportedShaderModel {
+                required: self.required_downlevel_capabilities.shader_model,
+                supported: downlevel_capabilities.shader_model,
+            });
+        }
+        if !downlevel_capabilities.flags.contains(self.required_downlevel_capabilities.flags) {
+            return Err(EngineError::MissingDownlevelFlags(
+                self.required_downlevel_capabilities.flags - downlevel_capabilities.flags));
+        }
+
+        let mut missing = None;
+        self.required_limits.check_limits_with_fail_fn(&adapter.limits(), true, |limit, required, supported| {
+            missing = Some(EngineError::MissingLimits { limit, required, supported });
+        });
+        if let Some(e) = missing {
+            return Err(e);
+        }
+
+        Ok(())
+    }
 }
 
 pub struct EventLoopWrapper {
@@ -58,8 +124,8 @@ pub struct EventLoopWrapper {
  }
  
  impl EventLoopWrapper {
-     pub fn new(title: &str) -> Self {
-         let event_loop = EventLoop::new().unwrap();
+     pub fn new(title: &str) -> Result<Self, EngineError> {
+         let event_loop = EventLoop::new()?;
          let mut builder = winit::window::WindowBuilder::new();
          #[cfg(target_arch = "wasm32")]
          {
@@ -76,9 +142,9 @@ pub struct EventLoopWrapper {
              builder = builder.with_canvas(Some(canvas));
          }
          builder = builder.with_title(title);
-         let window = Arc::new(builder.build(&event_loop).unwrap());
+         let window = Arc::new(builder.build(&event_loop)?);
  
-         Self { event_loop, window }
+         Ok(Self { event_loop, window })
      }
  }
  
@@ -95,6 +161,29 @@ pub struct SurfaceWrapper {
              config: None,
          }
      }
+
+     /// Create a surface wrapper for offscreen rendering. There is no surface, only the
+     /// configuration that describes the offscreen render target.
+     pub fn new_headless(size: PhysicalSize<u32>, format: wgpu::TextureFormat) -> Self {
+         Self {
+             surface: None,
+             config: Some(wgpu::SurfaceConfiguration {
+                 usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+                 format,
+                 width: size.width.max(1),
+                 height: size.height.max(1),
+                 present_mode: wgpu::PresentMode::AutoNoVsync,
+                 desired_maximum_frame_latency: 2,
+                 alpha_mode: wgpu::CompositeAlphaMode::Auto,
+                 view_formats: vec![format],
+             }),
+         }
+     }
+
+     /// Is this wrapper used for offscreen rendering.
+     pub fn is_headless(&self) -> bool {
+         self.surface.is_none() && self.config.is_some()
+     }
  
      /// Called after the instance is created, but before we request an adapter.
      ///
@@ -103,10 +192,11 @@ pub struct SurfaceWrapper {
      ///
      /// We cannot unconditionally create a surface here, as Android requires
      /// us to wait until we recieve the `Resumed` event to do so.
-     pub fn pre_adapter(&mut self, instance: &Instance, window: Arc<Window>) {
+     pub fn pre_adapter(&mut self, instance: &Instance, window: Arc<Window>) -> Result<(), EngineError> {
          if cfg!(target_arch = "wasm32") {
-             self.surface = Some(instance.create_surface(window).unwrap());
+             self.surface = Some(instance.create_surface(window)?);
          }
+         Ok(())
      }
  
      /// Check if the event is the start condition for the surface.
@@ -125,7 +215,7 @@ pub struct SurfaceWrapper {
      /// On all native platforms, this is where we create the surface.
      ///
      /// Additionally, we configure the surface based on the (now valid) window size.
-     pub fn resume(&mut self, context: &WGPUContext, window: Arc<Window>, srgb: bool) {
+     pub fn resume(&mut self, context: &WGPUContext, window: Arc<Window>, srgb: bool) -> Result<(), EngineError> {
          // Window size is only actually valid after we enter the event loop.
          let window_size = window.inner_size();
          let width = window_size.width.max(1);
@@ -135,7 +225,7 @@ pub struct SurfaceWrapper {
  
          // We didn't create the surface in pre_adapter, so we need to do so now.
          if !cfg!(target_arch = "wasm32") {
-             self.surface = Some(context.instance.create_surface(window).unwrap());
+             self.surface = Some(context.instance.create_surface(window)?);
          }
  
          // From here on, self.surface should be Some.
@@ -145,7 +235,7 @@ pub struct SurfaceWrapper {
          // Get the default configuration,
          let mut config = surface
              .get_default_config(&context.adapter, width, height)
-             .expect("Surface isn't supported by the adapter.");
+             .ok_or(EngineError::UnsupportedSurface)?;
          if srgb {
              // Not all platforms (WebGPU) support sRGB swapchains, so we need to use view formats
              let view_format = config.format.add_srgb_suffix();
@@ -159,6 +249,7 @@ pub struct SurfaceWrapper {
  
          surface.configure(&context.device, &config);
          self.config = Some(config);
+         Ok(())
      }
  
      /// Resize the surface, making sure to not resize to zero.
@@ -168,20 +259,22 @@ pub struct SurfaceWrapper {
          let config = self.config.as_mut().unwrap();
          config.width = size.width.max(1);
          config.height = size.height.max(1);
-         let surface = self.surface.as_ref().unwrap();
-         surface.configure(&context.device, config);
+
+         // There is nothing to configure in headless mode.
+         if let Some(surface) = self.surface.as_ref() {
+             surface.configure(&context.device, config);
+         }
      }
  
-     /// Acquire the next surface texture.
-     pub fn acquire(&mut self, context: &WGPUContext) -> wgpu::SurfaceTexture {
-         let surface = self.surface.as_ref().unwrap();
+     /// Acquire the next surface texture. Returns EngineError::NoSurface in headless mode or
+     /// before the surface is created.
+     pub fn acquire(&mut self, context: &WGPUContext) -> Result<wgpu::SurfaceTexture, EngineError> {
+         let surface = self.surface.as_ref().ok_or(EngineError::NoSurface)?;
  
          match surface.get_current_texture() {
-             Ok(frame) => frame,
+             Ok(frame) => Ok(frame),
              // If we timed out, just try again
-             Err(wgpu::SurfaceError::Timeout) => surface
-                 .get_current_texture()
-                 .expect("Failed to acquire next surface texture!"),
+             Err(wgpu::SurfaceError::Timeout) => Ok(surface.get_current_texture()?),
              Err(
                  // If the surface is outdated, or was lost, reconfigure it.
                  wgpu::SurfaceError::Outdated
@@ -190,9 +283,7 @@ pub struct SurfaceWrapper {
                  | wgpu::SurfaceError::OutOfMemory,
              ) => {
                  surface.configure(&context.device, self.config());
-                 surface
-                     .get_current_texture()
-                     .expect("Failed to acquire next surface texture!")
+                 Ok(surface.get_current_texture()?)
              }
          }
      }
@@ -222,6 +313,12 @@ pub struct SurfaceWrapper {
                                                        
      /// Rendering of the application.
      fn render(&mut self, context: &WGPUContext, view: &TextureView, surface: &SurfaceWrapper);
+
+     /// Rendering of the application with an interpolation factor [0, 1) between the previous
+     /// and the current simulation state. Called by fixed timestep loops.
+     fn render_interpolated(&mut self, context: &WGPUContext, view: &TextureView, surface: &SurfaceWrapper, _alpha: f32) {
+         self.render(context, view, surface);
+     }
  
      /// Resizing of the application.
      fn resize(&mut self, wgpu_context: &WGPUContext, surface_configuration: &wgpu::SurfaceConfiguration, new_size: winit::dpi::PhysicalSize<u32>);
@@ -229,12 +326,50 @@ pub struct SurfaceWrapper {
      /// Updating of the application.
      fn update(&mut self, wgpu_context: &WGPUContext, input_cache: &InputCache);
                                                          
-     /// Closing of the application.
+     /// Closing of the application. Called when the loop exits.
      fn close(&mut self, wgpu_context: &WGPUContext);
+
+     /// A raw window event. Called for every window event before the other hooks.
+     fn window_event(&mut self, _wgpu_context: &WGPUContext, _event: &WindowEvent) { }
+
+     /// The window gained focus.
+     fn focus_gained(&mut self, _wgpu_context: &WGPUContext) { }
+
+     /// The window lost focus.
+     fn focus_lost(&mut self, _wgpu_context: &WGPUContext) { }
+
+     /// The application was suspended.
+     fn suspended(&mut self, _wgpu_context: &WGPUContext) { }
+
+     /// The application was resumed.
+     fn resumed(&mut self, _wgpu_context: &WGPUContext) { }
+
+     /// A file was dropped into the window.
+     fn dropped_file(&mut self, _wgpu_context: &WGPUContext, _path: &std::path::Path) { }
+
+     /// The scale factor of the window changed.
+     fn scale_factor_changed(&mut self, _wgpu_context: &WGPUContext, _scale_factor: f64) { }
+
+     /// Frame statistics of the previous frames. Called once per frame before updating.
+     fn frame_stats(&mut self, _wgpu_context: &WGPUContext, _stats: &FrameStats) { }
  }
+
+/// Forward a window event to the lifecycle hooks of the application.
+pub(crate) fn forward_window_event<A: Application>(application: &mut A, context: &WGPUContext, event: &WindowEvent) {
+    application.window_event(context, event);
+    match event {
+        WindowEvent::Focused(true) => application.focus_gained(context),
+        WindowEvent::Focused(false) => application.focus_lost(context),
+        WindowEvent::DroppedFile(path) => application.dropped_file(context, path),
+        WindowEvent::ScaleFactorChanged { scale_factor, .. } => application.scale_factor_changed(context, *scale_factor),
+        _ => { }
+    }
+}
  
-/// Create wgpu core elements for application.
-pub async fn setup<F: WGPUFeatures>(surface: &mut SurfaceWrapper, window: Arc<Window>) -> WGPUContext {
+/// Create wgpu core elements for application. If window is None, the software/fallback adapter is
+/// preferred. If the adapter doesn't meet the requirements of F, the fallback feature set of F is
+/// tried.
+pub async fn setup<F: WGPUFeatures>(surface: &mut SurfaceWrapper, window: Option<Arc<Window>>) -> Result<WGPUContext, EngineError> {
     log::info!("Initializing wgpu...");
 
     let backends = wgpu::util::backend_bits_from_env().unwrap_or_default();
@@ -247,64 +382,89 @@ pub async fn setup<F: WGPUFeatures>(surface: &mut SurfaceWrapper, window: Arc<Wi
         dx12_shader_compiler,
         gles_minor_version,
     });
-    surface.pre_adapter(&instance, window);
-    let adapter = wgpu::util::initialize_adapter_from_env_or_default(&instance, surface.get())
-        .await
-        .expect("No suitable GPU adapters found on the system!");
+    let adapter = match window {
+        Some(window) => {
+            surface.pre_adapter(&instance, window)?;
+            wgpu::util::initialize_adapter_from_env_or_default(&instance, surface.get())
+                .await
+                .ok_or(EngineError::NoAdapter)?
+        }
+        None => {
+            // Use the fallback adapter if there is one.
+            let fallback = instance.request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                force_fallback_adapter: true,
+                compatible_surface: None,
+            }).await;
+            match fallback {
+                Some(adapter) => adapter,
+                None => wgpu::util::initialize_adapter_from_env_or_default(&instance, None)
+                    .await
+                    .ok_or(EngineError::NoAdapter)?,
+            }
+        }
+    };
 
     let adapter_info = adapter.get_info();
     log::info!("Using {} ({:?})", adapter_info.name, adapter_info.backend);
 
-    let optional_features = F::optional_features();
-    let required_features = F::required_features();
-    let adapter_features = adapter.features();
-    assert!(
-        adapter_features.contains(required_features),
-        "Adapter does not support required features for this example: {:?}",
-        required_features - adapter_features
-        );
-
-    let required_downlevel_capabilities = F::required_downlevel_capabilities();
-    let downlevel_capabilities = adapter.get_downlevel_capabilities();
-    assert!(
-        downlevel_capabilities.shader_model >= required_downlevel_capabilities.shader_model,
-        "Adapter does not support the minimum shader model required to run this example: {:?}",
-        required_downlevel_capabilities.shader_model
-        );
-    assert!(
-        downlevel_capabilities
-        .flags
-        .contains(required_downlevel_capabilities.flags),
-        "Adapter does not support the downlevel capabilities required to run this example: {:?}",
-        required_downlevel_capabilities.flags - downlevel_capabilities.flags
-        );
+    let feature_set = FeatureSet::from_features::<F>();
+    let feature_set = match (feature_set.check(&adapter), F::fallback()) {
+        (Ok(()), _) => feature_set,
+        (Err(e), Some(fallback)) if e.is_requirement_error() => {
+            log::warn!("{} Trying the fallback feature set.", e);
+            match fallback.check(&adapter) {
+                Ok(()) => fallback,
+                Err(fallback_error) => return Err(EngineError::FallbackFailed {
+                    original: Box::new(e),
+                    fallback: Box::new(fallback_error),
+                }),
+            }
+        }
+        (Err(e), _) => return Err(e),
+    };
 
     // Make sure we use the texture resolution limits from the adapter, so we can support images the size of the surface.
-    let needed_limits = F::required_limits().using_resolution(adapter.limits());
+    let needed_limits = feature_set.required_limits.using_resolution(adapter.limits());
 
     let trace_dir = std::env::var("WGPU_TRACE");
     let (device, queue) = adapter
         .request_device(
             &wgpu::DeviceDescriptor {
                 label: None,
-                required_features: (optional_features & adapter_features) | required_features,
+                required_features: (feature_set.optional_features & adapter.features()) | feature_set.required_features,
                 required_limits: needed_limits,
                 memory_hints: wgpu::MemoryHints::Performance,
             },
             trace_dir.ok().as_ref().map(std::path::Path::new),
             )
-        .await
-        .expect("Unable to find a suitable GPU adapter!");
+        .await?;
 
-    WGPUContext {
+    Ok(WGPUContext {
         instance,
         adapter,
         device,
         queue,
-    }
+    })
 }
 
-pub fn run<F: WGPUFeatures, L: Loop, A: Application>(title: &'static str) {
+/// Set up the engine and run the application with the loop L. Returns when the loop exits. The
+/// setup errors are returned. On wasm the setup is asynchronous: run returns immediately and the
+/// setup errors are logged.
+pub fn run<F: WGPUFeatures, L: Loop, A: Application>(title: &'static str) -> Result<(), EngineError> {
+
+    // Headless mode. No window, no event loop.
+    if let Some(size) = L::headless_size() {
+        log::info!("Creating headless surface wrapper...");
+        let mut surface = SurfaceWrapper::new_headless(size, wgpu::TextureFormat::Rgba8UnormSrgb);
+
+        log::info!("Creating WGPUContext...");
+        let context = pollster::block_on(setup::<F>(&mut surface, None))?;
+
+        log::info!("Staring headless loop...");
+        L::start::<A>(title, context, surface, None);
+        return Ok(());
+    }
 
     // Create surface.
 
@@ -313,26 +473,52 @@ pub fn run<F: WGPUFeatures, L: Loop, A: Application>(title: &'static str) {
 
     // Event loop.
     log::info!("Creating eventloop wrapper...");
-    let window_loop = EventLoopWrapper::new(title);
+    let window_loop = EventLoopWrapper::new(title)?;
 
     cfg_if::cfg_if! {
         if #[cfg(target_arch = "wasm32")] {
             wasm_bindgen_futures::spawn_local(async move {
 
                 log::info!("Creating WGPUContext...");
-                let context = setup::<F>(&mut surface, window_loop.window.clone()).await.unwrap();
+                let context = match setup::<F>(&mut surface, Some(window_loop.window.clone())).await {
+                    Ok(context) => context,
+                    Err(e) => { log::error!("{}", e); return; }
+                };
 
                 log::info!("Staring event loop...");
-                L::start::<A>(&self, title: &str, context, surface, window_loop);
-            })
+                L::start::<A>(title, context, surface, Some(window_loop));
+            });
+            Ok(())
         } else {
 
             log::info!("Creating WGPUContext...");
-            let context = pollster::block_on(setup::<F>(&mut surface, window_loop.window.clone()));
+            let context = pollster::block_on(setup::<F>(&mut surface, Some(window_loop.window.clone())))?;
 
             log::info!("Staring event loop...");
-            L::start::<A>(title, context, surface, window_loop);
+            L::start::<A>(title, context, surface, Some(window_loop));
+            Ok(())
         }
     }
 }
 
+
+/// Application wgpu-features for tests.
+#[cfg(test)]
+pub(crate) struct TestFeatures;
+
+#[cfg(test)]
+impl WGPUFeatures for TestFeatures { }
+
+/// Create a headless context on the fallback (or default) adapter for gpu tests. Returns None
+/// and prints a note if the system has no suitable adapter, so the calling test is skipped.
+#[cfg(test)]
+pub(crate) fn test_context(size: PhysicalSize<u32>) -> Option<(WGPUContext, SurfaceWrapper)> {
+    let mut surface = SurfaceWrapper::new_headless(size, wgpu::TextureFormat::Rgba8UnormSrgb);
+    match pollster::block_on(setup::<TestFeatures>(&mut surface, None)) {
+        Ok(context) => Some((context, surface)),
+        Err(e) => {
+            eprintln!("Skipping gpu test: {}", e);
+            None
+        }
+    }
+}
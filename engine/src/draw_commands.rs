@@ -1,9 +1,23 @@
 use std::ops::Range;
 use crate::texture::Texture;
-use crate::render_pass::create_render_pass;
-use wgpu::util::RenderEncoder;
+use crate::render_pass::{create_render_pass, set_viewport_and_scissor, Viewport, ScissorRect};
 
-/// A basic draw command.
+/// A load clear would wipe the whole target, also the other viewports. So clear is ignored when
+/// a viewport or a scissor rectangle is set (see render_pass::clear_target).
+fn clears_whole_target(clear: bool, viewport: &Option<Viewport>, scissor: &Option<ScissorRect>) -> bool {
+    clear && viewport.is_none() && scissor.is_none()
+}
+
+/// Set the bind groups in order. The dynamic offsets of bind group i are dynamic_offsets[i] (empty
+/// if missing).
+fn set_bind_groups<'a>(render_pass: &mut wgpu::RenderPass<'a>, bind_groups: &Vec<&'a wgpu::BindGroup>, dynamic_offsets: &[&[u32]]) {
+    for (e, bgs) in bind_groups.iter().enumerate() {
+        render_pass.set_bind_group(e as u32, bgs, dynamic_offsets.get(e).copied().unwrap_or(&[]));
+    }
+}
+
+/// A basic draw command. The viewport and the scissor rectangle default to the whole target. The
+/// target is cleared only without a viewport and a scissor rectangle.
 pub fn draw(encoder: &mut wgpu::CommandEncoder,
             view: &wgpu::TextureView,
             depth_texture: Option<&Texture>,
@@ -12,23 +26,42 @@ pub fn draw(encoder: &mut wgpu::CommandEncoder,
             draw_buffer: &wgpu::Buffer,
             range: Range<u32>,
             clear_color: &Option<wgpu::Color>,
-            clear: bool) {
+            clear: bool,
+            viewport: Option<Viewport>,
+            scissor: Option<ScissorRect>) {
+
+    draw_with_offsets(encoder, view, depth_texture, bind_groups, &[], pipeline, draw_buffer, range, clear_color, clear, viewport, scissor);
+}
+
+/// A basic draw command with dynamic offsets for the bind groups (e.g. UniformRing slices).
+pub fn draw_with_offsets(
+            encoder: &mut wgpu::CommandEncoder,
+            view: &wgpu::TextureView,
+            depth_texture: Option<&Texture>,
+            bind_groups: &Vec<&wgpu::BindGroup>,
+            dynamic_offsets: &[&[u32]],
+            pipeline: &wgpu::RenderPipeline,
+            draw_buffer: &wgpu::Buffer,
+            range: Range<u32>,
+            clear_color: &Option<wgpu::Color>,
+            clear: bool,
+            viewport: Option<Viewport>,
+            scissor: Option<ScissorRect>) {
 
     let mut render_pass = create_render_pass(
                           encoder,
                           view,
                           depth_texture,
-                          clear,
+                          clears_whole_target(clear, &viewport, &scissor),
                           clear_color,
                           &None
     );
-    
+
+    set_viewport_and_scissor(&mut render_pass, viewport, scissor);
     render_pass.set_pipeline(&pipeline);
 
     // Set bind groups.
-    for (e, bgs) in bind_groups.iter().enumerate() {
-        render_pass.set_bind_group(e as u32, bgs, &[]);
-    }
+    set_bind_groups(&mut render_pass, bind_groups, dynamic_offsets);
     
     // Set vertex buffer.
     render_pass.set_vertex_buffer(
@@ -39,7 +72,8 @@ pub fn draw(encoder: &mut wgpu::CommandEncoder,
     render_pass.draw(range, 0..1);
 }
 
-/// A basic draw command for indirect buffer.
+/// A basic draw command for indirect buffer. The viewport and the scissor rectangle default to
+/// the whole target. The target is cleared only without a viewport and a scissor rectangle.
 pub fn draw_indirect(
             encoder: &mut wgpu::CommandEncoder,
             view: &wgpu::TextureView,
@@ -50,23 +84,44 @@ pub fn draw_indirect(
             indirect_buffer: &wgpu::Buffer,
             offset: wgpu::BufferAddress,
             clear_color: &Option<wgpu::Color>,
-            clear: bool) {
+            clear: bool,
+            viewport: Option<Viewport>,
+            scissor: Option<ScissorRect>) {
+
+    draw_indirect_with_offsets(encoder, view, depth_texture, bind_groups, &[], pipeline, draw_buffer, indirect_buffer, offset, clear_color, clear, viewport, scissor);
+}
+
+/// A draw command for indirect buffer with dynamic offsets for the bind groups (e.g. UniformRing
+/// slices).
+pub fn draw_indirect_with_offsets(
+            encoder: &mut wgpu::CommandEncoder,
+            view: &wgpu::TextureView,
+            depth_texture: Option<&Texture>,
+            bind_groups: &Vec<&wgpu::BindGroup>,
+            dynamic_offsets: &[&[u32]],
+            pipeline: &wgpu::RenderPipeline,
+            draw_buffer: &wgpu::Buffer,
+            indirect_buffer: &wgpu::Buffer,
+            offset: wgpu::BufferAddress,
+            clear_color: &Option<wgpu::Color>,
+            clear: bool,
+            viewport: Option<Viewport>,
+            scissor: Option<ScissorRect>) {
 
     let mut render_pass = create_render_pass(
                           encoder,
                           view,
                           depth_texture,
-                          clear,
+                          clears_whole_target(clear, &viewport, &scissor),
                           clear_color,
                           &None
     );
 
+    set_viewport_and_scissor(&mut render_pass, viewport, scissor);
     render_pass.set_pipeline(&pipeline);
 
     // Set bind groups.
-    for (e, bgs) in bind_groups.iter().enumerate() {
-        render_pass.set_bind_group(e as u32, &bgs, &[]);
-    }
+    set_bind_groups(&mut render_pass, bind_groups, dynamic_offsets);
 
     // Set vertex buffer.
     render_pass.set_vertex_buffer(
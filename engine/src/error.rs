@@ -0,0 +1,114 @@
+use thiserror::Error;
+
+/// Errors that can happen while setting up and running the engine.
+#[derive(Error, Debug)]
+pub enum EngineError {
+    #[error("No suitable GPU adapters found on the system.")]
+    NoAdapter,
+
+    #[error("Adapter does not support the required features: {0:?}")]
+    MissingFeatures(wgpu::Features),
+
+    #[error("Adapter does not support the minimum shader model {required:?} (supported: {supported:?}).")]
+    UnsupportedShaderModel {
+        required: wgpu::ShaderModel,
+        supported: wgpu::ShaderModel,
+    },
+
+    #[error("Adapter does not support the required downlevel capabilities: {0:?}")]
+    MissingDownlevelFlags(wgpu::DownlevelFlags),
+
+    #[error("Adapter does not support the required limit {limit} (required: {required}, supported: {supported}).")]
+    MissingLimits {
+        limit: &'static str,
+        required: u64,
+        supported: u64,
+    },
+
+    #[error("{original} The fallback feature set isn't supported either: {fallback}")]
+    FallbackFailed {
+        original: Box<EngineError>,
+        fallback: Box<EngineError>,
+    },
+
+    #[error("Unable to create device: {0}")]
+    RequestDevice(#[from] wgpu::RequestDeviceError),
+
+    #[error("Unable to create event loop: {0}")]
+    EventLoop(#[from] winit::error::EventLoopError),
+
+    #[error("Unable to create window: {0}")]
+    CreateWindow(#[from] winit::error::OsError),
+
+    #[error("Unable to create surface: {0}")]
+    CreateSurface(#[from] wgpu::CreateSurfaceError),
+
+    #[error("Surface isn't supported by the adapter.")]
+    UnsupportedSurface,
+
+    #[error("There is no surface. Headless loops render into an offscreen target.")]
+    NoSurface,
+
+    #[error("Failed to acquire next surface texture: {0}")]
+    SurfaceTexture(#[from] wgpu::SurfaceError),
+
+    #[error("Failed to decode image: {0}")]
+    ImageDecoding(#[from] png::DecodingError),
+
+    #[error("Unsupported image: {0}")]
+    UnsupportedImage(String),
+
+    #[error("Invalid camera path: {0}")]
+    InvalidCameraPath(String),
+
+    #[error("Invalid camera parameter: {0}")]
+    InvalidCameraParameter(String),
+
+    #[error("Invalid camera snapshot: {0}")]
+    InvalidCameraSnapshot(String),
+
+    #[error("Failed to parse RON: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+
+    #[error("Viewport rectangle {0:?} must have a positive width and height.")]
+    InvalidViewport([f32; 4]),
+
+    #[error("Buffer range {start}..{end} is out of bounds (length {len}).")]
+    BufferOutOfBounds { start: usize, end: usize, len: usize },
+
+    #[error("Buffer range of {range} elements doesn't match the data length {data}.")]
+    BufferLengthMismatch { range: usize, data: usize },
+
+    #[error("Failed to map buffer: {0}")]
+    BufferMapping(#[from] wgpu::BufferAsyncError),
+
+    #[error("Blocking buffer readback isn't supported on this platform. Use the asynchronous readback.")]
+    BlockingReadback,
+
+    #[error("Byte length {len} isn't a multiple of the element size {element_size}.")]
+    ByteLengthMismatch { len: usize, element_size: usize },
+
+    #[error("WGSL layout mismatch: {0}")]
+    WgslLayout(String),
+
+    #[error("Uniform ring is full: {requested} bytes requested with {used} of {capacity} bytes used.")]
+    UniformRingFull { requested: u64, used: u64, capacity: u64 },
+
+    #[error("Uniform of {size} bytes is larger than the binding size {binding_size}.")]
+    UniformTooLarge { size: u64, binding_size: u64 },
+
+    #[error("Invalid uniform ring: {0}")]
+    InvalidUniformRing(String),
+}
+
+impl EngineError {
+
+    /// Is this error caused by the adapter not meeting the WGPUFeatures requirements.
+    pub fn is_requirement_error(&self) -> bool {
+        matches!(self,
+            EngineError::MissingFeatures(_) |
+            EngineError::UnsupportedShaderModel { .. } |
+            EngineError::MissingDownlevelFlags(_) |
+            EngineError::MissingLimits { .. })
+    }
+}
@@ -0,0 +1,224 @@
+use crate::frame_stats::{FrameStats, FrameSample};
+use crate::basic_loop::{WindowLoopState, run_event_loop};
+use crate::core::{
+    Loop,
+    WGPUContext,
+    SurfaceWrapper,
+    EventLoopWrapper,
+    Application,
+};
+
+use winit::{
+    event::Event,
+    event_loop::EventLoopWindowTarget,
+    };
+
+/// A scheduler for fixed timestep simulation. Accumulates the frame time and tells how many
+/// simulation ticks should be run. All times are in nano seconds (see InputCache).
+#[derive(Clone, Copy, Debug)]
+pub struct FixedTimestep {
+    /// The length of a single tick.
+    tick_length: u128,
+    /// The maximum number of ticks per frame.
+    max_ticks: u32,
+    /// The time that hasn't been simulated yet.
+    accumulator: u128,
+}
+
+impl FixedTimestep {
+
+    /// Create a scheduler for the given tick rate. At most max_ticks catch-up ticks are run per frame.
+    pub fn init(ticks_per_second: u32, max_ticks: u32) -> Self {
+
+        assert!(ticks_per_second > 0, "Ticks per second must be > 0.");
+        assert!(max_ticks > 0, "Max ticks must be > 0.");
+
+        Self {
+            tick_length: 1_000_000_000 / ticks_per_second as u128,
+            max_ticks,
+            accumulator: 0,
+        }
+    }
+
+    /// Get the length of a single tick.
+    pub fn get_tick_length(&self) -> u128 {
+        self.tick_length
+    }
+
+    /// Add time_delta to the accumulator and return the number of ticks to run. If the number of
+    /// ticks would exceed max_ticks, the excess time is dropped so the simulation doesn't spiral.
+    pub fn advance(&mut self, time_delta: u128) -> u32 {
+        self.accumulator += time_delta;
+        let ticks = self.accumulator / self.tick_length;
+
+        if ticks > self.max_ticks as u128 {
+            self.accumulator %= self.tick_length;
+            self.max_ticks
+        }
+        else {
+            self.accumulator -= ticks * self.tick_length;
+            ticks as u32
+        }
+    }
+
+    /// The interpolation factor [0, 1) between the previous and the current tick.
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator as f64 / self.tick_length as f64) as f32
+    }
+}
+
+/// A loop that runs Application::update at a fixed tick rate and renders once per frame with an
+/// interpolation factor.
+pub struct FixedStepLoop<const TICKS_PER_SECOND: u32 = 60, const MAX_TICKS: u32 = 5> { }
+
+impl<const TICKS_PER_SECOND: u32, const MAX_TICKS: u32> Loop for FixedStepLoop<TICKS_PER_SECOND, MAX_TICKS> {
+
+    fn init() -> Self {
+        FixedStepLoop {}
+    }
+
+    fn start<A: Application>(_title: &str, context: WGPUContext, surface: SurfaceWrapper, window_loop: Option<EventLoopWrapper>) {
+
+        let window_loop = window_loop.expect("FixedStepLoop requires a window.");
+        let mut state = WindowLoopState::<A>::init(context, surface, window_loop.window.clone());
+        let mut timestep = FixedTimestep::init(TICKS_PER_SECOND, MAX_TICKS);
+        let mut previous_frame_time = 0;
+        let mut frame_stats = FrameStats::init(120);
+
+        log::info!("Entering fixed step event loop...");
+
+        run_event_loop(
+            window_loop.event_loop,
+            move |event: Event<()>, target: &EventLoopWindowTarget<()>| {
+
+                let Some((frame, view)) = state.handle_event(event, target) else { return };
+                let application = state.application.as_mut().unwrap();
+
+                application.frame_stats(&state.context, &frame_stats);
+
+                // Run the simulation ticks for the time elapsed since the previous frame.
+                let frame_time = state.input_cache.get_time() - previous_frame_time;
+                let ticks = timestep.advance(frame_time);
+                previous_frame_time = state.input_cache.get_time();
+
+                let update_start = instant::Instant::now();
+                for i in 0..ticks {
+                    state.input_cache.prepare_tick(timestep.get_tick_length(), i == 0);
+                    application.update(&state.context, &state.input_cache);
+                }
+                if ticks == 0 { state.input_cache.hold_edges(); }
+                let update_time = update_start.elapsed().as_nanos();
+
+                let render_start = instant::Instant::now();
+                application.render_interpolated(&state.context, &view, &state.surface, timestep.alpha());
+                let render_time = render_start.elapsed().as_nanos();
+
+                frame_stats.push(FrameSample { frame_time, update_time, render_time });
+
+                frame.present();
+
+                state.window.request_redraw();
+            }, // move
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input_cache::{InputCache, InputClock, InputState, Key};
+    use crate::input_record::InputEvent;
+    use winit::event::ElementState;
+
+    const TICK: u128 = 1_000_000_000 / 50;
+
+    #[test]
+    fn advance_runs_whole_ticks_and_keeps_the_remainder() {
+        let mut timestep = FixedTimestep::init(50, 5);
+        assert_eq!(timestep.get_tick_length(), TICK);
+
+        assert_eq!(timestep.advance(TICK / 2), 0);
+        assert!((timestep.alpha() - 0.5).abs() < 1e-6);
+
+        assert_eq!(timestep.advance(TICK), 1);
+        assert!((timestep.alpha() - 0.5).abs() < 1e-6);
+
+        assert_eq!(timestep.advance(TICK / 2 + 2 * TICK), 3);
+        assert_eq!(timestep.alpha(), 0.0);
+    }
+
+    #[test]
+    fn alpha_stays_below_one() {
+        let mut timestep = FixedTimestep::init(60, 5);
+        for delta in [1, 7_000_000, 16_666_666, 16_666_667, 33_333_334] {
+            timestep.advance(delta);
+            let alpha = timestep.alpha();
+            assert!((0.0..1.0).contains(&alpha), "alpha {}", alpha);
+        }
+    }
+
+    #[test]
+    fn long_frame_is_capped_to_max_ticks() {
+        let mut timestep = FixedTimestep::init(50, 5);
+
+        // A one second hitch would need 50 ticks.
+        assert_eq!(timestep.advance(1_000_000_000 + TICK / 4), 5);
+
+        // The excess time is dropped, only the fraction of a tick remains.
+        assert!((timestep.alpha() - 0.25).abs() < 1e-6);
+        assert_eq!(timestep.advance(0), 0);
+    }
+
+    #[test]
+    fn spiral_of_death_is_avoided() {
+        let mut timestep = FixedTimestep::init(100, 3);
+
+        // Every frame takes longer than max_ticks ticks. The tick count doesn't grow.
+        for _ in 0..100 {
+            assert_eq!(timestep.advance(10 * timestep.get_tick_length()), 3);
+            assert!(timestep.alpha() < 1.0);
+        }
+    }
+
+    fn press(input: &mut InputCache, key: Key, state: ElementState) {
+        input.push_event(&InputEvent::Key { key, state });
+    }
+
+    #[test]
+    fn pressed_edge_is_seen_by_one_tick() {
+        let mut input = InputCache::with_clock(InputClock::Manual(0));
+        input.pre_update();
+        press(&mut input, Key::Space, ElementState::Pressed);
+
+        input.prepare_tick(TICK, true);
+        assert!(matches!(input.key_state(&Key::Space), Some(InputState::Pressed(_))));
+        input.prepare_tick(TICK, false);
+        assert!(matches!(input.key_state(&Key::Space), Some(InputState::Down(_,_))));
+        input.prepare_tick(TICK, false);
+        assert!(matches!(input.key_state(&Key::Space), Some(InputState::Down(_,_))));
+    }
+
+    #[test]
+    fn edges_are_carried_over_frames_without_ticks() {
+        let mut input = InputCache::with_clock(InputClock::Manual(0));
+        input.pre_update();
+        press(&mut input, Key::Space, ElementState::Pressed);
+        press(&mut input, Key::KeyA, ElementState::Pressed);
+        press(&mut input, Key::KeyA, ElementState::Released);
+
+        // No tick on this frame.
+        input.hold_edges();
+        input.clock_mut().advance(TICK / 2);
+        input.pre_update();
+
+        input.prepare_tick(TICK, true);
+        assert!(matches!(input.key_state(&Key::Space), Some(InputState::Pressed(_))));
+        assert!(matches!(input.key_state(&Key::KeyA), Some(InputState::Released(_,_))));
+
+        // The next frame advances normally.
+        input.clock_mut().advance(TICK);
+        input.pre_update();
+        assert!(matches!(input.key_state(&Key::Space), Some(InputState::Down(_,_))));
+        assert_eq!(input.key_state(&Key::KeyA), None);
+    }
+}
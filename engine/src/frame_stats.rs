@@ -0,0 +1,211 @@
+use std::collections::VecDeque;
+use crate::gpu_debugger::char_generator::Char;
+
+/// Timing information of a single frame. All times are in nano seconds.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameSample {
+    /// The time between the previous and the current frame.
+    pub frame_time: u128,
+    /// The cpu time spent in Application::update.
+    pub update_time: u128,
+    /// The cpu time spent in Application::render.
+    pub render_time: u128,
+}
+
+/// Statistics of a series of durations. All values are in milli seconds.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DurationSummary {
+    pub min: f64,
+    pub avg: f64,
+    pub max: f64,
+    pub p50: f64,
+    pub p95: f64,
+    pub p99: f64,
+}
+
+impl DurationSummary {
+
+    /// Create a summary from durations given in nano seconds. Returns a zeroed summary for an
+    /// empty series.
+    pub fn from_nanos(durations: impl Iterator<Item = u128>) -> Self {
+
+        let mut values: Vec<f64> = durations.map(|d| d as f64 / 1000000.0).collect();
+
+        if values.is_empty() { return Self::default(); }
+
+        values.sort_by(|a, b| a.total_cmp(b));
+
+        Self {
+            min: values[0],
+            avg: values.iter().sum::<f64>() / values.len() as f64,
+            max: values[values.len() - 1],
+            p50: percentile(&values, 50.0),
+            p95: percentile(&values, 95.0),
+            p99: percentile(&values, 99.0),
+        }
+    }
+}
+
+/// Nearest-rank percentile of sorted values. p is clamped to range [0, 100]. Returns 0.0 for an
+/// empty series.
+pub fn percentile(sorted_values: &[f64], p: f64) -> f64 {
+
+    if sorted_values.is_empty() { return 0.0; }
+
+    let p = if p.is_nan() { 0.0 } else { p.clamp(0.0, 100.0) };
+    let rank = (p / 100.0 * sorted_values.len() as f64).ceil() as usize;
+    sorted_values[rank.max(1) - 1]
+}
+
+/// Keeps a rolling window of frame samples and computes frame statistics.
+#[derive(Clone, Debug)]
+pub struct FrameStats {
+    samples: VecDeque<FrameSample>,
+    window_size: usize,
+}
+
+impl FrameStats {
+
+    /// Create frame statistics that keep window_size latest samples.
+    pub fn init(window_size: usize) -> Self {
+        assert!(window_size > 0, "Window size must be > 0.");
+
+        Self {
+            samples: VecDeque::with_capacity(window_size),
+            window_size,
+        }
+    }
+
+    /// Add a new sample. The oldest sample is dropped if the window is full.
+    pub fn push(&mut self, sample: FrameSample) {
+        if self.samples.len() == self.window_size {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    /// Get the number of samples.
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Get the latest sample.
+    pub fn latest(&self) -> Option<FrameSample> {
+        self.samples.back().copied()
+    }
+
+    /// Statistics of the frame times.
+    pub fn frame_times(&self) -> DurationSummary {
+        DurationSummary::from_nanos(self.samples.iter().map(|s| s.frame_time))
+    }
+
+    /// Statistics of the update times.
+    pub fn update_times(&self) -> DurationSummary {
+        DurationSummary::from_nanos(self.samples.iter().map(|s| s.update_time))
+    }
+
+    /// Statistics of the render times.
+    pub fn render_times(&self) -> DurationSummary {
+        DurationSummary::from_nanos(self.samples.iter().map(|s| s.render_time))
+    }
+
+    /// Frames per second over the window.
+    pub fn fps(&self) -> f64 {
+        let total: u128 = self.samples.iter().map(|s| s.frame_time).sum();
+        if total == 0 { 0.0 } else { self.samples.len() as f64 * 1000000000.0 / total as f64 }
+    }
+
+    /// Create chars for GpuDebugger. The rows are fps, frame time (min, avg, max, p99), update
+    /// time (avg, max) and render time (avg, max). The rows go downwards from position.
+    pub fn to_chars(&self, position: [f32; 3], font_size: f32, color: u32) -> Vec<Char> {
+
+        let frame = self.frame_times();
+        let update = self.update_times();
+        let render = self.render_times();
+
+        let rows = [
+            ([self.fps() as f32, 0.0, 0.0, 0.0], 1, 1),
+            ([frame.min as f32, frame.avg as f32, frame.max as f32, frame.p99 as f32], 4, 2),
+            ([update.avg as f32, update.max as f32, 0.0, 0.0], 2, 2),
+            ([render.avg as f32, render.max as f32, 0.0, 0.0], 2, 2),
+        ];
+
+        rows.iter().enumerate().map(|(i, (value, vec_dim_count, decimal_count))| {
+            Char::new(
+                [position[0], position[1] - i as f32 * font_size * 1.5, position[2]],
+                font_size,
+                *value,
+                *vec_dim_count,
+                color,
+                *decimal_count)
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(frame_time_ms: u128) -> FrameSample {
+        FrameSample { frame_time: frame_time_ms * 1000000, update_time: 0, render_time: 0 }
+    }
+
+    #[test]
+    fn summary_min_avg_max() {
+        let summary = DurationSummary::from_nanos([3000000, 1000000, 2000000].into_iter());
+        assert_eq!(summary.min, 1.0);
+        assert_eq!(summary.avg, 2.0);
+        assert_eq!(summary.max, 3.0);
+    }
+
+    #[test]
+    fn summary_of_empty_series_is_zero() {
+        let summary = DurationSummary::from_nanos(std::iter::empty());
+        assert_eq!(summary.min, 0.0);
+        assert_eq!(summary.avg, 0.0);
+        assert_eq!(summary.p99, 0.0);
+    }
+
+    #[test]
+    fn nearest_rank_percentiles() {
+        let values: Vec<f64> = (1..=100).map(|v| v as f64).collect();
+        assert_eq!(percentile(&values, 0.0), 1.0);
+        assert_eq!(percentile(&values, 50.0), 50.0);
+        assert_eq!(percentile(&values, 95.0), 95.0);
+        assert_eq!(percentile(&values, 99.0), 99.0);
+        assert_eq!(percentile(&values, 100.0), 100.0);
+
+        let values = [1.0, 2.0, 3.0, 4.0];
+        assert_eq!(percentile(&values, 50.0), 2.0);
+        assert_eq!(percentile(&values, 51.0), 3.0);
+    }
+
+    #[test]
+    fn percentile_clamps_input() {
+        let values = [1.0, 2.0, 3.0];
+        assert_eq!(percentile(&[], 50.0), 0.0);
+        assert_eq!(percentile(&values, -10.0), 1.0);
+        assert_eq!(percentile(&values, 150.0), 3.0);
+        assert_eq!(percentile(&values, f64::NAN), 1.0);
+    }
+
+    #[test]
+    fn window_drops_the_oldest_sample() {
+        let mut stats = FrameStats::init(3);
+        for ms in [100, 1, 2, 3] {
+            stats.push(sample(ms));
+        }
+        assert_eq!(stats.len(), 3);
+        assert_eq!(stats.latest().unwrap().frame_time, 3000000);
+
+        let frame = stats.frame_times();
+        assert_eq!(frame.min, 1.0);
+        assert_eq!(frame.max, 3.0);
+        assert_eq!(frame.avg, 2.0);
+        assert_eq!(stats.fps(), 500.0);
+    }
+}
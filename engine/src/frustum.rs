@@ -0,0 +1,154 @@
+use cgmath::{prelude::*, Matrix4, Vector3, Vector4};
+use crate::gpu_debugger::primitive_processor::AABB;
+
+/// A plane n.p + d = 0. Points with a positive signed distance are on the inner side.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Plane {
+    pub normal: Vector3<f32>,
+    pub distance: f32,
+}
+
+impl Plane {
+
+    /// Create a normalized plane from a (a, b, c, d) vector. A degenerate plane (e.g. the far
+    /// plane of an infinite projection) has a zero normal and contains everything.
+    pub fn from_vector(v: Vector4<f32>) -> Self {
+        let normal = v.truncate();
+        let length = normal.magnitude();
+
+        if length < 1e-12 {
+            return Self { normal: Vector3::zero(), distance: 0.0 };
+        }
+        Self { normal: normal / length, distance: v.w / length }
+    }
+
+    /// The signed distance from the plane to the point.
+    pub fn signed_distance(&self, point: Vector3<f32>) -> f32 {
+        self.normal.dot(point) + self.distance
+    }
+}
+
+/// A view frustum as six planes pointing inwards (left, right, bottom, top, near, far).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frustum {
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+
+    /// Extract the planes from a view projection matrix with wgpu depth range [0, 1]
+    /// (see Camera::build_projection_matrix).
+    pub fn from_matrix(m: &Matrix4<f32>) -> Self {
+        let (r0, r1, r2, r3) = (m.row(0), m.row(1), m.row(2), m.row(3));
+
+        Self {
+            planes: [
+                Plane::from_vector(r3 + r0),
+                Plane::from_vector(r3 - r0),
+                Plane::from_vector(r3 + r1),
+                Plane::from_vector(r3 - r1),
+                Plane::from_vector(r2),
+                Plane::from_vector(r3 - r2),
+            ]
+        }
+    }
+
+    /// Is the point inside the frustum.
+    pub fn contains_point(&self, point: [f32; 3]) -> bool {
+        let p = Vector3::from(point);
+        self.planes.iter().all(|plane| plane.signed_distance(p) >= 0.0)
+    }
+
+    /// Does the sphere intersect the frustum.
+    pub fn intersects_sphere(&self, center: [f32; 3], radius: f32) -> bool {
+        let c = Vector3::from(center);
+        self.planes.iter().all(|plane| plane.signed_distance(c) >= -radius)
+    }
+
+    /// Does the box intersect the frustum. Conservative: a box near a frustum corner may be
+    /// reported visible even if it's outside.
+    pub fn intersects_aabb(&self, min: [f32; 3], max: [f32; 3]) -> bool {
+        self.planes.iter().all(|plane| {
+            // The box corner furthest along the plane normal.
+            let p = Vector3::new(
+                if plane.normal.x >= 0.0 { max[0] } else { min[0] },
+                if plane.normal.y >= 0.0 { max[1] } else { min[1] },
+                if plane.normal.z >= 0.0 { max[2] } else { min[2] },
+            );
+            plane.signed_distance(p) >= 0.0
+        })
+    }
+
+    /// Get the indices of the visible boxes.
+    pub fn cull_aabbs(&self, aabbs: &[AABB]) -> Vec<usize> {
+        aabbs.iter().enumerate().filter_map(|(i, aabb)| {
+            let min = [aabb.min[0], aabb.min[1], aabb.min[2]];
+            let max = [aabb.max[0], aabb.max[1], aabb.max[2]];
+            if self.intersects_aabb(min, max) { Some(i) } else { None }
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::Point3;
+    use crate::camera::Projection;
+
+    /// A camera at the origin looking down -Z.
+    fn frustum(projection: Projection) -> Frustum {
+        let view = Matrix4::look_at_rh(Point3::new(0.0, 0.0, 0.0), Point3::new(0.0, 0.0, -1.0), Vector3::unit_y());
+        Frustum::from_matrix(&(projection.build_matrix(1.0, 0.1, 100.0) * view))
+    }
+
+    #[test]
+    fn perspective_culls_boxes_behind_the_camera() {
+        let f = frustum(Projection::Perspective { fov_y: std::f32::consts::FRAC_PI_2 });
+
+        // In front of the camera.
+        assert!(f.intersects_aabb([-1.0, -1.0, -11.0], [1.0, 1.0, -9.0]));
+        // Behind the camera.
+        assert!(!f.intersects_aabb([-1.0, -1.0, 9.0], [1.0, 1.0, 11.0]));
+        // Beyond the far plane.
+        assert!(!f.intersects_aabb([-1.0, -1.0, -111.0], [1.0, 1.0, -109.0]));
+        // Straddles the right plane (x = -z at 90 degrees).
+        assert!(f.intersects_aabb([9.0, -1.0, -11.0], [11.0, 1.0, -9.0]));
+        // Outside the right plane.
+        assert!(!f.intersects_aabb([12.0, -1.0, -11.0], [14.0, 1.0, -9.0]));
+    }
+
+    #[test]
+    fn cull_aabbs_returns_the_visible_indices() {
+        let f = frustum(Projection::Perspective { fov_y: std::f32::consts::FRAC_PI_2 });
+        let aabbs = [
+            AABB { min: [-1.0, -1.0, 9.0, 0.0], max: [1.0, 1.0, 11.0, 0.0] },
+            AABB { min: [-1.0, -1.0, -11.0, 0.0], max: [1.0, 1.0, -9.0, 0.0] },
+        ];
+        assert_eq!(f.cull_aabbs(&aabbs), vec![1]);
+    }
+
+    #[test]
+    fn orthographic_volume() {
+        let f = frustum(Projection::Orthographic { height: 4.0 });
+
+        assert!(f.contains_point([1.9, -1.9, -50.0]));
+        assert!(!f.contains_point([2.1, 0.0, -50.0]));
+        assert!(!f.contains_point([0.0, 0.0, 1.0]));
+
+        assert!(f.intersects_sphere([2.5, 0.0, -10.0], 1.0));
+        assert!(!f.intersects_sphere([3.5, 0.0, -10.0], 1.0));
+
+        assert!(f.intersects_aabb([1.5, 1.5, -10.0], [3.0, 3.0, -5.0]));
+        assert!(!f.intersects_aabb([2.5, -1.0, -10.0], [3.0, 1.0, -5.0]));
+    }
+
+    #[test]
+    fn reverse_z_infinite_far_plane_contains_distant_points() {
+        let f = frustum(Projection::ReverseZInfinite { fov_y: std::f32::consts::FRAC_PI_2 });
+
+        assert!(f.contains_point([0.0, 0.0, -1.0e6]));
+        assert!(f.intersects_sphere([0.0, 0.0, -1.0e7], 1.0));
+        assert!(!f.contains_point([0.0, 0.0, 1.0]));
+        assert!(!f.contains_point([0.0, 0.0, -0.05]));
+    }
+}
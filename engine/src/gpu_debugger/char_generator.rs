@@ -5,7 +5,6 @@ use crate::texture::{
 use bytemuck::Pod;
 use bytemuck::Zeroable;
 use std::borrow::Cow;
-use std::mem::size_of;
 use crate::pipelines::{
     ComputePipelineWrapper,
     BindGroupMapper,
@@ -15,14 +14,17 @@ use crate::bindgroups::{
     create_uniform_bindgroup_layout,
 };
 use crate::draw_commands::draw_indirect;
+use crate::render_pass::{Viewport, ScissorRect};
 use crate::common_structs::{
     DispatchIndirect,
     DrawIndirect,
 };
-use crate::buffer::to_vec;
+use crate::buffer::GpuBuffer;
+use crate::readback::ReadbackPool;
 use crate::buffer::buffer_from_data;
-use crate::misc::Convert2Vec;
-use crate::impl_convert;
+use crate::error::EngineError;
+use crate::check_wgsl_layout;
+use crate::wgsl_layout::AddressSpace;
 
 use wgpu::TextureView;
 
@@ -49,8 +51,27 @@ pub struct Char {
     auxiliary_data: u32,
 }
 
-impl_convert!{CharParams}
-impl_convert!{Char}
+impl Char {
+
+    /// Create a char element. vec_dim_count tells how many components of value are rendered.
+    pub fn new(start_pos: [f32; 3], font_size: f32, value: [f32; 4], vec_dim_count: u32, color: u32, decimal_count: u32) -> Self {
+        Self {
+            start_pos,
+            font_size,
+            value,
+            vec_dim_count,
+            color,
+            decimal_count,
+            auxiliary_data: 0,
+        }
+    }
+
+    /// Check the layout against the Char struct of the char preprocessor shader.
+    pub fn check_layout() -> Result<(), EngineError> {
+        check_wgsl_layout!(Char, include_str!("wgsl/char_preprocessor.wgsl"), AddressSpace::Storage,
+                           start_pos, font_size, value, vec_dim_count, color, decimal_count, auxiliary_data)
+    }
+}
 
 pub struct CharProcessor {
 
@@ -59,10 +80,11 @@ pub struct CharProcessor {
     char_pipeline_bind_groups: wgpu::BindGroup,
     pre_processor_bind_groups: wgpu::BindGroup,
     indirect_dispatch_buffer: wgpu::Buffer,
-    char_param_buffer: wgpu::Buffer,
-    chars_buffer: wgpu::Buffer,
+    char_param_buffer: GpuBuffer<CharParams>,
+    chars_buffer: GpuBuffer<Char>,
     indirect_draw_buffer: wgpu::Buffer,
     dispatch_counter_histogram: Histogram, 
+    readback: ReadbackPool,
 }
 
 impl CharProcessor {
@@ -95,7 +117,7 @@ impl CharProcessor {
         );
 
         // Create char params buffer here.
-        let char_param_buffer = buffer_from_data::<CharParams>(
+        let char_param_buffer = GpuBuffer::<CharParams>::from_data(
             &device,
             &vec![
                 CharParams{ vertices_so_far: 0,
@@ -109,13 +131,11 @@ impl CharProcessor {
             );
 
         // Create char buffer.
-        let chars_buffer = 
-            device.create_buffer(&wgpu::BufferDescriptor{
-                label: Some("char buffer"),
-                size: (max_number_of_chars * std::mem::size_of::<Char>() as u32) as u64,
-                usage: wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
-                mapped_at_creation: false,
-            });
+        let chars_buffer = GpuBuffer::<Char>::zeroed(
+                device,
+                max_number_of_chars as usize,
+                wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+                Some("char buffer"));
 
         // Create here pipeline and bind group mapper for numbers.
         let mut bind_group_mapper = BindGroupMapper::init(device);
@@ -184,7 +204,7 @@ impl CharProcessor {
             &vec![
                 &indirect_draw_buffer.as_entire_binding(),
                 &histogram.get_histogram_buffer().as_entire_binding(),
-                &chars_buffer.as_entire_binding(),
+                &chars_buffer.get_buffer().as_entire_binding(),
                 &render_buffer.as_entire_binding(),
             ],
             0);
@@ -194,10 +214,10 @@ impl CharProcessor {
             device,
             &vec![
                 &camera_buffer.as_entire_binding(),
-                &char_param_buffer.as_entire_binding(), // TODO: keep char params information in this struct.
+                &char_param_buffer.get_buffer().as_entire_binding(), // TODO: keep char params information in this struct.
                 &indirect_dispatch_buffer.as_entire_binding(), // ADD histogram
                 &indirect_draw_buffer.as_entire_binding(),
-                &chars_buffer.as_entire_binding(),
+                &chars_buffer.get_buffer().as_entire_binding(),
             ],
             0);
 
@@ -211,9 +231,21 @@ impl CharProcessor {
             chars_buffer: chars_buffer,
             indirect_draw_buffer: indirect_draw_buffer,
             dispatch_counter_histogram: histogram,
+            readback: ReadbackPool::init(),
         }
     }
 
+    /// Write chars to the char buffer starting from element offset. Logs an error if the chars
+    /// don't fit in the buffer.
+    pub fn append_chars(&self, queue: &wgpu::Queue, chars: &[Char], offset: u32) {
+        let start = offset as usize;
+        if let Err(e) = self.chars_buffer.write(queue, start..start + chars.len(), chars) {
+            log::error!("Failed to append chars: {}", e);
+        }
+    }
+
+    /// Render the chars. Reads the char params back with a blocking read, so this returns
+    /// EngineError::BlockingReadback on wasm (see ReadbackPool::read).
     pub fn render(&self,
                   device: &wgpu::Device,
                   queue: &wgpu::Queue,
@@ -225,15 +257,11 @@ impl CharProcessor {
                   number_of_chars: u32,
                   max_number_of_vertices: u32,
                   clear_color: Option<wgpu::Color>, 
-                  clear: bool) {
+                  clear: bool,
+                  viewport: Option<Viewport>,
+                  scissor: Option<ScissorRect>) -> Result<(), EngineError> {
 
-        let charparams_result = to_vec::<CharParams>(
-            &device,
-            &queue,
-            &self.char_param_buffer,
-            0,
-            (size_of::<CharParams>()) as wgpu::BufferAddress
-            ).unwrap();
+        let charparams_result = self.char_param_buffer.read_with(&self.readback, device, queue, 0..1)?;
 
         // Can we avoid this. Should we use some other parameter than vertices_so_far (we have
         // padding for future usage)?
@@ -250,11 +278,7 @@ impl CharProcessor {
                 padding: [1,2,3],
             };
 
-            queue.write_buffer(
-                &self.char_param_buffer,
-                0,
-                bytemuck::cast_slice(&[cp])
-                );
+            self.char_param_buffer.write(queue, 0..1, &[cp]).expect("The char params buffer holds one element.");
 
             self.dispatch_counter_histogram.reset_all_cpu_version(device, queue, 0);
 
@@ -292,10 +316,23 @@ impl CharProcessor {
                     &self.indirect_draw_buffer, // Should we have a own draw_buffer?
                     (i * std::mem::size_of::<DrawIndirect>() as u32) as wgpu::BufferAddress,
                     if clear && clear_color.is_none() { &Some(wgpu::Color { r: 0.1, g: 0.0, b: 0.0, a: 1.0, }) } else { &clear_color },
-                    clear
+                    clear,
+                    viewport,
+                    scissor
                     );
             }
             queue.submit(Some(encoder_char.finish()));
         }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn char_layout_matches_the_shader() {
+        Char::check_layout().unwrap();
     }
 }
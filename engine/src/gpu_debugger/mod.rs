@@ -1,4 +1,3 @@
-use crate::histogram::Histogram;
 use crate::texture::Texture as Tex;
 use crate::lights::LightBuffer;
 use crate::gpu_debugger::char_generator::CharProcessor;
@@ -7,15 +6,15 @@ use crate::gpu_debugger::primitive_processor::PrimitiveProcessor;
 use crate::gpu_debugger::primitive_processor::Arrow;
 use crate::gpu_debugger::primitive_processor::AABB;
 use crate::buffer::buffer_from_data;
+use crate::render_pass::{Viewport, ScissorRect};
 use std::mem::size_of;
 use std::collections::HashMap;
-use crate::misc::Convert2Vec;
-use crate::impl_convert;
 use crate::common_structs::{
     DrawIndirect,
     DispatchIndirect,
 };
 use crate::pipelines::RenderPipelineWrapper;
+use crate::error::EngineError;
 use crate::pipeline_stuff::custom_pipelines::{
     RenderParamBuffer,
     default_render_shader_v3c1,
@@ -33,6 +32,13 @@ struct OtherRenderParams {
     scale_factor: f32,
 }
 
+/// The render bind groups of GpuDebugger for a camera uniform buffer. Used for rendering the
+/// primitives from another camera than the one given to GpuDebugger::init.
+pub struct DebugCameraBindGroups {
+    v3c1_bind_group: wgpu::BindGroup,
+    v4n4_bind_group: wgpu::BindGroup,
+}
+
 /// GpuDebugger can be used to render some basic primitives like numbers, arrows, aabbs and aabb
 /// wires. 
 pub struct GpuDebugger {
@@ -57,9 +63,12 @@ pub struct GpuDebugger {
     render_params: RenderParamBuffer,
     /// Light buffer for rendering shaders. 
     light: LightBuffer,
-    /// This histogram stores the count of all renderable elements. 
+    /// The count of all renderable elements: chars, arrows, aabbs and aabb wires. The counts
+    /// are kept on the cpu so that appending doesn't need a gpu readback.
     /// TODO: impove this to use tags.
-    histogram_element_counter: Histogram,
+    element_counts: [u32; 4],
+    /// The maximum number of chars, arrows, aabbs and aabb wires.
+    max_element_counts: [u32; 4],
 }
         // ADD these to gpu_debugger.
         // Get the total number of elements.
@@ -135,7 +144,20 @@ impl GpuDebugger {
             v4n4_bind_group: v4n4_bind_group,
             render_params: render_param_buffer,
             light: light,
-            histogram_element_counter: Histogram::init(device, &vec![0; 4]),
+            element_counts: [0; 4],
+            max_element_counts: [max_number_of_chars, max_number_of_arrows, max_number_of_aabbs, max_number_of_aabb_wires],
+        }
+    }
+
+    /// Create the render bind groups for a camera uniform buffer. The char geometry is still
+    /// generated with the camera given to GpuDebugger::init (see GpuDebugger::render).
+    pub fn create_camera_bind_groups(&self, device: &wgpu::Device, camera_buffer: &wgpu::Buffer) -> DebugCameraBindGroups {
+        DebugCameraBindGroups {
+            v3c1_bind_group: self.v3c1_pipeline_wrapper.create_bind_group(device, &vec![&camera_buffer.as_entire_binding()], 0),
+            v4n4_bind_group: self.v4n4_pipeline_wrapper.create_bind_group(
+                device,
+                &vec![&camera_buffer.as_entire_binding(), &self.light.get_buffer().as_entire_binding(), &self.render_params.get_buffer().as_entire_binding()],
+                0),
         }
     }
 
@@ -144,65 +166,95 @@ impl GpuDebugger {
         &self.primitive_processor.get_aabb_buffer()
     }
 
-    /// Append aabb. TODO: rename this function to append_aabb. Also check that the appending
-    /// succeeded.
-    pub fn add_aabb(&self, device: &wgpu::Device, queue: &wgpu::Queue, aabb: &AABB) {
+    /// Reserve room for count elements of the given kind. Returns the offset of the first
+    /// element and the number of elements that fit. The elements that don't fit are dropped.
+    fn reserve(&mut self, kind: usize, count: usize) -> (u32, usize) {
+        let offset = self.element_counts[kind];
+        let free = (self.max_element_counts[kind] - offset) as usize;
+        if count > free {
+            log::warn!("GpuDebugger: the maximum number of elements ({}) exceeded. {} elements dropped.",
+                       self.max_element_counts[kind], count - free);
+        }
+        let accepted = count.min(free);
+        self.element_counts[kind] += accepted as u32;
+        (offset, accepted)
+    }
 
-        let mut histogram_values = self.histogram_element_counter.get_values(device, queue);
-        histogram_values[2] += 1;
-        self.histogram_element_counter.set_values_cpu_version(device, queue, &histogram_values);
-        self.primitive_processor.append_aabb(device, queue, aabb, histogram_values[2]-1);
+    /// Append aabb. TODO: rename this function to append_aabb. The aabb is dropped if the
+    /// maximum number of aabbs is exceeded.
+    pub fn add_aabb(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, aabb: &AABB) {
+        self.add_aabbs(device, queue, &vec![*aabb]);
     }
 
-    /// Append multiple aabbs at once. TODO: add checking. Rename function. 
-    pub fn add_aabbs(&self, device: &wgpu::Device, queue: &wgpu::Queue, aabb: &Vec<AABB>) {
-        let mut histogram_values = self.histogram_element_counter.get_values(device, queue);
-        let count_now = histogram_values[2];
-        histogram_values[2] += aabb.len() as u32;
-        self.histogram_element_counter.set_values_cpu_version(device, queue, &histogram_values);
-        self.primitive_processor.append_aabbs(device, queue, aabb, count_now);
+    /// Append multiple aabbs at once. The aabbs that exceed the maximum number of aabbs are
+    /// dropped. TODO: Rename function. 
+    pub fn add_aabbs(&mut self, _device: &wgpu::Device, queue: &wgpu::Queue, aabb: &Vec<AABB>) {
+        let (offset, accepted) = self.reserve(2, aabb.len());
+        if accepted > 0 {
+            self.primitive_processor.append_aabbs(queue, &aabb[..accepted], offset);
+        }
     }
 
-    /// Append arrow. TODO: rename this function to append_arrow. Also check that the appending
-    /// succeeded.
-    pub fn add_arrow(&self, device: &wgpu::Device, queue: &wgpu::Queue, arrow: &Arrow) {
+    /// Append arrow. TODO: rename this function to append_arrow. The arrow is dropped if the
+    /// maximum number of arrows is exceeded.
+    pub fn add_arrow(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, arrow: &Arrow) {
+        self.add_arrows(device, queue, &vec![*arrow]);
+    }
 
-        let mut histogram_values = self.histogram_element_counter.get_values(device, queue);
-        histogram_values[1] += 1;
-        self.histogram_element_counter.set_values_cpu_version(device, queue, &histogram_values);
-        self.primitive_processor.insert_arrow(device, queue, arrow, histogram_values[1]-1);
+    /// Append multiple arros at once. The arrows that exceed the maximum number of arrows are
+    /// dropped. TODO: Rename function. 
+    pub fn add_arrows(&mut self, _device: &wgpu::Device, queue: &wgpu::Queue, arrows: &Vec<Arrow>) {
+        let (offset, accepted) = self.reserve(1, arrows.len());
+        if accepted > 0 {
+            self.primitive_processor.insert_arrows(queue, &arrows[..accepted], offset);
+        }
     }
 
-    /// Append multiple arros at once. TODO: add checking. Rename function. 
-    pub fn add_arrows(&self, device: &wgpu::Device, queue: &wgpu::Queue, arrows: &Vec<Arrow>) {
+    /// Append multiple chars at once. The chars that exceed the maximum number of chars are
+    /// dropped.
+    pub fn add_chars(&mut self, _device: &wgpu::Device, queue: &wgpu::Queue, chars: &Vec<Char>) {
+        let (offset, accepted) = self.reserve(0, chars.len());
+        if accepted > 0 {
+            self.char_processor.append_chars(queue, &chars[..accepted], offset);
+        }
+    }
 
-        assert!(arrows.len() > 0);
-        let mut histogram_values = self.histogram_element_counter.get_values(device, queue);
-        let count_now = histogram_values[1];
-        histogram_values[1] += arrows.len() as u32;
-        self.histogram_element_counter.set_values_cpu_version(device, queue, &histogram_values);
-        self.primitive_processor.insert_arrows(device, queue, arrows, count_now);
+    /// Remove all chars. Chars that change every frame (e.g. frame statistics) should be reset
+    /// before adding them again.
+    pub fn reset_chars(&mut self, _device: &wgpu::Device, _queue: &wgpu::Queue) {
+        self.element_counts[0] = 0;
     }
 
-    /// Render all primives and numbers. Make some validation for draw buffer. TODO: Should this function
-    /// return a failure? TODO: finish the number rendering.
+    /// Render all primives and numbers. Make some validation for draw buffer. TODO: finish the
+    /// number rendering. The camera defaults to the camera given
+    /// to GpuDebugger::init and the viewport and the scissor rectangle to the whole target. The
+    /// char geometry is always generated with the camera given to GpuDebugger::init: the point
+    /// density of the chars depends on their distance to that camera, not to the given camera.
+    /// The char rendering reads the char params back with a blocking read, so this returns
+    /// EngineError::BlockingReadback on wasm.
     pub fn render(&mut self,
                   device: &wgpu::Device,
                   queue: &wgpu::Queue,
                   view: &wgpu::TextureView,
                   draw_buffer: &wgpu::Buffer,
                   depth_texture: &Tex,
-                  clear: &mut bool) {
+                  clear: &mut bool,
+                  camera: Option<&DebugCameraBindGroups>,
+                  viewport: Option<Viewport>,
+                  scissor: Option<ScissorRect>) -> Result<(), EngineError> {
 
 
         // log::info!("GpugDebugger::Rendering");
-        // Check the total number of elements.
-        let elem_counter = self.histogram_element_counter.get_values(device, queue);
+        // The total number of elements.
+        let [total_number_of_chars,
+             total_number_of_arrows,
+             total_number_of_aabbs,
+             total_number_of_aabb_wires] = self.element_counts;
 
-        let total_number_of_chars = elem_counter[0];
-        let total_number_of_arrows = elem_counter[1];
-        let total_number_of_aabbs = elem_counter[2];
-        let total_number_of_aabb_wires = elem_counter[3];
+        let (v3c1_bind_group, v4n4_bind_group) = match camera {
+            Some(c) => (&c.v3c1_bind_group, &c.v4n4_bind_group),
+            None => (&self.v3c1_bind_group, &self.v4n4_bind_group),
+        };
 
         self.primitive_processor.render(
             device,
@@ -210,7 +262,7 @@ impl GpuDebugger {
             view,
             depth_texture,
             draw_buffer,
-            &self.v4n4_bind_group, //: &wgpu::BindGroup,
+            v4n4_bind_group, //: &wgpu::BindGroup,
             self.v4n4_pipeline_wrapper.get_pipeline(), //: &wgpu::RenderPipeline,
             total_number_of_arrows,
             total_number_of_aabbs,
@@ -218,23 +270,28 @@ impl GpuDebugger {
             self.max_number_of_vertices,
             64,
             Some(wgpu::Color { r: 0.0, g: 0.0, b: 0.0, a: 1.0, }),
-            clear);
+            clear,
+            viewport,
+            scissor);
 
         self.char_processor.render(
                   device,
                   queue,
                   draw_buffer,
-                  &self.v3c1_bind_group, //render_bindgroup: &wgpu::BindGroup,
+                  v3c1_bind_group, //render_bindgroup: &wgpu::BindGroup,
                   &self.v3c1_pipeline_wrapper.get_pipeline(),
                   view,
                   depth_texture,
                   total_number_of_chars,
                   self.max_number_of_vertices,
                   Some(wgpu::Color { r: 0.0, g: 0.0, b: 0.0, a: 1.0, }),
-                  *clear);
+                  *clear,
+                  viewport,
+                  scissor)?;
 
         // Reset element counters.
         // let elem_counter = self.histogram_element_counter.reset_all_cpu_version(queue, 0);
+        Ok(())
     }
 
     // pub fn get_output_chars_buffer(&self) -> &wgpu::Buffer {
@@ -1,4 +1,4 @@
-use crate::buffer::add_data;
+use crate::buffer::GpuBuffer;
 use crate::misc::udiv_up_safe32;
 use crate::texture::{
     Texture as Tex,
@@ -16,14 +16,16 @@ use crate::bindgroups::{
     create_uniform_bindgroup_layout,
 };
 use crate::draw_commands::draw;
+use crate::render_pass::{Viewport, ScissorRect};
 use crate::common_structs::{
     DispatchIndirect,
     DrawIndirect,
 };
 use crate::buffer::to_vec;
 use crate::buffer::buffer_from_data;
-use crate::misc::Convert2Vec;
-use crate::impl_convert;
+use crate::error::EngineError;
+use crate::check_wgsl_layout;
+use crate::wgsl_layout::AddressSpace;
 
 /// Vertex data that is used for rendering.
 #[repr(C)]
@@ -61,6 +63,16 @@ pub struct Arrow {
     pub _padding: [u32; 2]
 }
 
+impl Arrow {
+
+    /// Check the layout against the Arrow struct of the arrow_aabb shader. The padding is
+    /// implicit in the shader.
+    pub fn check_layout() -> Result<(), EngineError> {
+        check_wgsl_layout!(Arrow, include_str!("wgsl/arrow_aabb.wgsl"), AddressSpace::Storage,
+                           start_pos, end_pos, color, size)
+    }
+}
+
 /// A internal data structure for generating and rendering arrays, aabbs ana aabb-wires.
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Pod, Zeroable)]
@@ -76,8 +88,6 @@ pub struct ArrowAabbParams{
     element_type: u32,
 }
 
-impl_convert!{Arrow}
-
 /// A sub system for gpu debugger. Creates from Arrow, AABB and AABBWires triangle mesh for
 /// rendering and performs the rendering. 
 pub struct PrimitiveProcessor {
@@ -87,11 +97,11 @@ pub struct PrimitiveProcessor {
     /// Bind groups for aabb pipeline wrapper. TODO: add some checking for pipeline.
     aabb_bind_group: wgpu::BindGroup,
     /// A buffer that stores the arrow data.
-    arrow_buffer: wgpu::Buffer,
+    arrow_buffer: GpuBuffer<Arrow>,
     /// A buffer that stores the AABB data.
-    aabb_buffer: wgpu::Buffer,
+    aabb_buffer: GpuBuffer<AABB>,
     /// A buffer that stores the AABB-wire data.
-    aabb_wire_buffer: wgpu::Buffer,
+    aabb_wire_buffer: GpuBuffer<AABB>,
     /// Explain what this is later...
     arrow_params_buffer: wgpu::Buffer,
     /// Explain what this is later...
@@ -114,26 +124,23 @@ impl PrimitiveProcessor {
             element_type: 0,
         };
 
-        let arrow_buffer = device.create_buffer(&wgpu::BufferDescriptor{
-            label: Some("array buffer"),
-            size: (max_number_of_arrows * std::mem::size_of::<Arrow>() as u32) as u64,
-            usage: wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
+        let arrow_buffer = GpuBuffer::<Arrow>::zeroed(
+            device,
+            max_number_of_arrows as usize,
+            wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            Some("array buffer"));
 
-        let aabb_buffer = device.create_buffer(&wgpu::BufferDescriptor{
-            label: Some("aabb buffer"),
-            size: (max_number_of_aabbs * std::mem::size_of::<AABB>() as u32) as u64,
-            usage: wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
+        let aabb_buffer = GpuBuffer::<AABB>::zeroed(
+            device,
+            max_number_of_aabbs as usize,
+            wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            Some("aabb buffer"));
 
-        let aabb_wire_buffer = device.create_buffer(&wgpu::BufferDescriptor{
-            label: Some("output_aabbs"),
-            size: (max_number_of_aabb_wires * std::mem::size_of::<AABB>() as u32) as u64,
-            usage: wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
+        let aabb_wire_buffer = GpuBuffer::<AABB>::zeroed(
+            device,
+            max_number_of_aabb_wires as usize,
+            wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            Some("output_aabbs"));
 
         let arrow_params_buffer = buffer_from_data::<ArrowAabbParams>(
             &device,
@@ -178,9 +185,9 @@ impl PrimitiveProcessor {
             device,
             &vec![
             &arrow_params_buffer.as_entire_binding(),
-            &arrow_buffer.as_entire_binding(), // TODO: keep char params information in this struct.
-            &aabb_buffer.as_entire_binding(),
-            &aabb_wire_buffer.as_entire_binding(),
+            &arrow_buffer.get_buffer().as_entire_binding(), // TODO: keep char params information in this struct.
+            &aabb_buffer.get_buffer().as_entire_binding(),
+            &aabb_wire_buffer.get_buffer().as_entire_binding(),
             &render_buffer.as_entire_binding(),
             ],
             0);
@@ -210,7 +217,9 @@ impl PrimitiveProcessor {
                   max_number_of_vertices: u32,
                   thread_count: u32,
                   clear_color: Option<wgpu::Color>,
-                  clear: &mut bool
+                  clear: &mut bool,
+                  viewport: Option<Viewport>,
+                  scissor: Option<ScissorRect>
                  ) {
 
         const vertices_per_element_arrow: u32 = 72;
@@ -316,7 +325,9 @@ impl PrimitiveProcessor {
                      draw_buffer,
                      0..draw_count,
                      if *clear && clear_color.is_none() { &Some(wgpu::Color { r: 0.1, g: 0.0, b: 0.0, a: 1.0, }) } else { &clear_color }, // Wrong place for this. Add to draw.
-                     *clear
+                     *clear,
+                     viewport,
+                     scissor
                     );
 
                 if *clear { *clear = false; }
@@ -337,41 +348,32 @@ impl PrimitiveProcessor {
         }
     }
     pub fn get_aabb_buffer(&self) -> &wgpu::Buffer {
-        &self.aabb_buffer
+        self.aabb_buffer.get_buffer()
     }
 
-    pub fn append_aabb(&self, device: &wgpu::Device, queue: &wgpu::Queue, aabb: &AABB, offset: u32) {
-        add_data::<AABB>(
-            device,
-            queue,
-            &[*aabb],
-            &self.aabb_buffer,
-            (offset * std::mem::size_of::<AABB>() as u32).into());
+    /// Write aabbs starting from element offset. Logs an error if the aabbs don't fit in the buffer.
+    pub fn append_aabbs(&self, queue: &wgpu::Queue, aabbs: &[AABB], offset: u32) {
+        let start = offset as usize;
+        if let Err(e) = self.aabb_buffer.write(queue, start..start + aabbs.len(), aabbs) {
+            log::error!("Failed to append aabbs: {}", e);
+        }
     }
 
-    pub fn append_aabbs(&self, device: &wgpu::Device, queue: &wgpu::Queue, aabb: &Vec<AABB>, offset: u32) {
-        add_data::<AABB>(
-            device,
-            queue,
-            &aabb,
-            &self.aabb_buffer,
-            (offset * std::mem::size_of::<AABB>() as u32).into());
-    }
-    pub fn insert_arrow(&self, device: &wgpu::Device, queue: &wgpu::Queue, arrow: &Arrow, offset: u32) {
-        add_data::<Arrow>(
-            device,
-            queue,
-            &[*arrow],
-            &self.arrow_buffer,
-            (offset * std::mem::size_of::<Arrow>() as u32).into());
+    /// Write arrows starting from element offset. Logs an error if the arrows don't fit in the buffer.
+    pub fn insert_arrows(&self, queue: &wgpu::Queue, arrows: &[Arrow], offset: u32) {
+        let start = offset as usize;
+        if let Err(e) = self.arrow_buffer.write(queue, start..start + arrows.len(), arrows) {
+            log::error!("Failed to append arrows: {}", e);
+        }
     }
-    pub fn insert_arrows(&self, device: &wgpu::Device, queue: &wgpu::Queue, arrows: &Vec<Arrow>, offset: u32) {
-        add_data::<Arrow>(
-            device,
-            queue,
-            &arrows,
-            &self.arrow_buffer,
-            (offset * std::mem::size_of::<Arrow>() as u32).into());
-            //0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arrow_layout_matches_the_shader() {
+        Arrow::check_layout().unwrap();
     }
 }
@@ -0,0 +1,191 @@
+use crate::input_cache::InputCache;
+use crate::input_record;
+use crate::frame_stats::{FrameStats, FrameSample};
+use crate::core::{
+    Loop,
+    WGPUContext,
+    SurfaceWrapper,
+    EventLoopWrapper,
+    Application,
+};
+
+use winit::dpi::PhysicalSize;
+
+/// A loop without a window and a surface. Renders FRAMES frames into an offscreen render target
+/// texture of size WIDTH x HEIGHT and exits. Useful for CI and batch jobs.
+pub struct HeadlessLoop<const FRAMES: u32 = 1, const WIDTH: u32 = 512, const HEIGHT: u32 = 512> { }
+
+impl<const FRAMES: u32, const WIDTH: u32, const HEIGHT: u32> HeadlessLoop<FRAMES, WIDTH, HEIGHT> {
+
+    /// Create the offscreen render target described by the surface configuration.
+    pub fn create_render_target(context: &WGPUContext, surface: &SurfaceWrapper) -> wgpu::Texture {
+        let config = surface.config();
+        context.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Headless render target"),
+            size: wgpu::Extent3d {
+                width: config.width,
+                height: config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: config.format,
+            usage: config.usage,
+            view_formats: &config.view_formats,
+        })
+    }
+
+    /// Run FRAMES frames of the application into the render target. Returns the frame
+    /// statistics.
+    pub fn run_frames<A: Application>(context: &WGPUContext,
+                                      surface: &SurfaceWrapper,
+                                      application: &mut A,
+                                      input_cache: &mut InputCache,
+                                      render_target: &wgpu::Texture) -> FrameStats {
+
+        let mut frame_stats = FrameStats::init(120);
+        let view = render_target.create_view(&wgpu::TextureViewDescriptor {
+            format: Some(surface.config().view_formats[0]),
+            ..wgpu::TextureViewDescriptor::default()
+        });
+
+        log::info!("Running {} headless frames...", FRAMES);
+        for _ in 0..FRAMES {
+            input_cache.pre_update();
+            application.frame_stats(context, &frame_stats);
+
+            let update_start = instant::Instant::now();
+            application.update(context, input_cache);
+            let update_time = update_start.elapsed().as_nanos();
+
+            let render_start = instant::Instant::now();
+            application.render(context, &view, surface);
+
+            // Make sure the frame is finished before the next one.
+            context.device.poll(wgpu::Maintain::Wait);
+            let render_time = render_start.elapsed().as_nanos();
+
+            frame_stats.push(FrameSample {
+                frame_time: input_cache.get_time_delta(),
+                update_time,
+                render_time,
+            });
+        }
+        frame_stats
+    }
+}
+
+impl<const FRAMES: u32, const WIDTH: u32, const HEIGHT: u32> Loop for HeadlessLoop<FRAMES, WIDTH, HEIGHT> {
+
+    fn init() -> Self {
+        HeadlessLoop {}
+    }
+
+    fn headless_size() -> Option<PhysicalSize<u32>> {
+        Some(PhysicalSize::new(WIDTH, HEIGHT))
+    }
+
+    fn start<A: Application>(_title: &str, context: WGPUContext, surface: SurfaceWrapper, _window_loop: Option<EventLoopWrapper>) {
+
+        let mut input_cache = InputCache::init();
+        #[cfg(not(target_arch = "wasm32"))]
+        input_record::setup_from_env(&mut input_cache);
+
+        let render_target = Self::create_render_target(&context, &surface);
+
+        log::info!("Initializing application...");
+        let mut application = A::init(&context, &surface);
+
+        let frame_stats = Self::run_frames(&context, &surface, &mut application, &mut input_cache, &render_target);
+
+        let frame_times = frame_stats.frame_times();
+        log::info!("Frame times (ms): min {:.3}, avg {:.3}, max {:.3}, p99 {:.3}",
+                   frame_times.min, frame_times.avg, frame_times.max, frame_times.p99);
+
+        application.close(&context);
+        #[cfg(not(target_arch = "wasm32"))]
+        input_record::save_to_env(&mut input_cache);
+        log::info!("Headless loop finished.");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::test_context;
+    use crate::readback::ReadbackPool;
+
+    /// Counts the hooks and clears the target to green on the last frame, red before it.
+    struct CountingApp {
+        updates: u32,
+        renders: u32,
+    }
+
+    impl Application for CountingApp {
+        fn init(_context: &WGPUContext, _surface: &SurfaceWrapper) -> Self {
+            Self { updates: 0, renders: 0 }
+        }
+
+        fn render(&mut self, context: &WGPUContext, view: &wgpu::TextureView, _surface: &SurfaceWrapper) {
+            self.renders += 1;
+            let color = if self.renders == 3 { wgpu::Color::GREEN } else { wgpu::Color::RED };
+            let mut encoder = context.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+            encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations { load: wgpu::LoadOp::Clear(color), store: wgpu::StoreOp::Store },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            context.queue.submit(Some(encoder.finish()));
+        }
+
+        fn resize(&mut self, _context: &WGPUContext, _config: &wgpu::SurfaceConfiguration, _new_size: PhysicalSize<u32>) { }
+
+        fn update(&mut self, _context: &WGPUContext, _input_cache: &InputCache) {
+            self.updates += 1;
+        }
+
+        fn close(&mut self, _context: &WGPUContext) { }
+    }
+
+    #[test]
+    fn frames_are_rendered_into_the_offscreen_target() {
+        type Headless = HeadlessLoop<3, 64, 4>;
+        let Some((context, surface)) = test_context(Headless::headless_size().unwrap()) else { return };
+
+        let mut application = CountingApp::init(&context, &surface);
+        let mut input_cache = InputCache::init();
+        let render_target = Headless::create_render_target(&context, &surface);
+        let frame_stats = Headless::run_frames(&context, &surface, &mut application, &mut input_cache, &render_target);
+
+        assert_eq!((application.updates, application.renders), (3, 3));
+        assert_eq!(frame_stats.len(), 3);
+
+        // 64 texels of 4 bytes is a valid row pitch (256 bytes) for the copy.
+        let buffer = context.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: 64 * 4 * 4,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let mut encoder = context.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_texture_to_buffer(
+            render_target.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(256), rows_per_image: Some(4) },
+            },
+            wgpu::Extent3d { width: 64, height: 4, depth_or_array_layers: 1 },
+        );
+        context.queue.submit(Some(encoder.finish()));
+
+        let data = ReadbackPool::init().read(&context.device, &context.queue, &buffer, 0, buffer.size()).unwrap();
+        assert!(data.chunks_exact(4).all(|texel| texel == [0, 255, 0, 255]));
+    }
+}
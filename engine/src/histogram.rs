@@ -1,9 +1,12 @@
-use crate::buffer::{buffer_from_data, to_vec};
+use std::future::Future;
+use crate::buffer::GpuBuffer;
+use crate::error::EngineError;
+use crate::readback::ReadbackPool;
 
-/// Histogram struct for GPU purposes. 
+/// Histogram struct for GPU purposes.
 pub struct Histogram {
-    histogram: wgpu::Buffer,
-    data: Vec<u32>,
+    histogram: GpuBuffer<u32>,
+    readback: ReadbackPool,
 }
 
 impl Histogram {
@@ -13,7 +16,7 @@ impl Histogram {
 
         assert!(initial_values.len() > 0, "{}", format!("{} > 0", initial_values.len()));
 
-        let histogram = buffer_from_data::<u32>(
+        let histogram = GpuBuffer::<u32>::from_data(
             &device,
             &initial_values,
             wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::STORAGE,
@@ -21,56 +24,40 @@ impl Histogram {
 
         Self {
             histogram: histogram,
-            data: initial_values.to_owned(),
+            readback: ReadbackPool::init(),
         }
     }
 
-    /// TODO: implement wasm version! 
-    pub fn get_values(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<u32> {
+    /// Read the values back with a blocking read. Returns EngineError::BlockingReadback on
+    /// wasm, where blocking reads aren't supported (use get_values_async).
+    pub fn get_values(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Vec<u32>, EngineError> {
+        self.histogram.read_with(&self.readback, device, queue, 0..self.histogram.len())
+    }
 
-        // log::info!("get_values: BEGIN");
-        let result = to_vec::<u32>(&device,
-                                   &queue,
-                                   &self.histogram,
-                                   0 as wgpu::BufferAddress,
-                                   (std::mem::size_of::<u32>() * self.data.len()) as wgpu::BufferAddress
-        );
-        // log::info!("get_values: {:?}", result);
-        // log::info!("get_values: DONE");
-        // TODO: validation?
-        result.unwrap()
+    /// Read the values back asynchronously. Works on wasm.
+    pub fn get_values_async(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> impl Future<Output = Result<Vec<u32>, EngineError>> {
+        self.histogram.read_async(&self.readback, device, queue, 0..self.histogram.len())
     }
-    
+
     pub fn get_histogram_buffer(&self) -> &wgpu::Buffer {
-        &self.histogram
+        self.histogram.get_buffer()
     }
 
     pub fn set_values_cpu_version(&self, device: &wgpu::Device, queue: &wgpu::Queue, value: &Vec<u32>)
     {
         // Make sure the updated values are the same size as old values.
-        assert!(value.len() == self.data.len(), "{}", format!("{} > {}", self.data.len(), value.len()));
+        assert!(value.len() == self.histogram.len(), "{}", format!("{} > {}", self.histogram.len(), value.len()));
 
-        // log::info!("write_value: BEGIN HISTOGRAM ENCODER");
         // Necessery?
-        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Histogram set values encoder") });
-        // log::info!("VVAAAALLLUUUUUUEEEEEEEEEE {:?}", value);
-        queue.write_buffer(
-            &self.histogram,
-            0,
-            bytemuck::cast_slice(&value)
-        );
+        let encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Histogram set values encoder") });
+        self.histogram.write(queue, 0..value.len(), value).expect("The length was checked above.");
         queue.submit(Some(encoder.finish()));
-        // log::info!("write_value: END HISTOGRAM ENCODER");
     }
 
     pub fn reset_all_cpu_version(&self, device: &wgpu::Device, queue: &wgpu::Queue, value: u32) {
         // Necessery?
-        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Histogram reset all values encoder") });
-        queue.write_buffer(
-            &self.histogram,
-            0,
-            bytemuck::cast_slice(&vec![value ; self.data.len() as usize])
-        );
+        let encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Histogram reset all values encoder") });
+        self.histogram.write(queue, 0..self.histogram.len(), &vec![value ; self.histogram.len()]).expect("The range is the whole histogram.");
         queue.submit(Some(encoder.finish()));
     }
 }
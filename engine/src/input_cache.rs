@@ -1,16 +1,17 @@
 use std::collections::HashMap;
 use instant;
 
-use winit::keyboard::PhysicalKey;
 use winit::event as ev;
 use winit::keyboard as kb;
 pub use kb::KeyCode as Key;
 pub use ev::KeyEvent as KeyEv;
 
 use winit::dpi::PhysicalPosition;
+use crate::input_record::{InputEvent, InputLog, InputReplay, RecordEntry};
+use crate::text_input::{TextEvent, Preedit};
 
 /// An enum for mouse and keyboard button states.
-#[derive(Clone,Copy,Debug)]
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
 pub enum InputState {
     Pressed(u128),
     Down(u128,u128),
@@ -18,51 +19,86 @@ pub enum InputState {
 }
 
 impl InputState {
-    /// Updates the state depending on given ElementState and the current state.
+    /// Updates the state depending on given ElementState and the current state. Duplicate
+    /// events are tolerated: a press of a pressed or down button keeps it down and a release
+    /// of a released button keeps the original release.
     pub fn update(&mut self, state: &ev::ElementState, time_now: u128) -> InputState {
-        match state {
-            ev::ElementState::Pressed => {
-                match std::mem::replace(self, InputState::Pressed(666)) {
-                    InputState::Pressed(start_time) => {
-                        let state = InputState::Down(start_time,time_now);
-                        *self = state; //InputState::Down(start_time,time_now)
-                        state                        
-                    }
-                    // This is updated in InputCache::pre_update function for mouse buttons.
-                    // This won't never happen for mouse events.
-                    InputState::Down(start_time, _) => {
-                        let state = InputState::Down(start_time,time_now);
-                        *self = state; //InputState::Down(start_time,time_now);
-                        state
-                    }
-                    InputState::Released(_,_) => {
-                        let state = InputState::Pressed(time_now);
-                        *self = state; // InputState::Pressed(time_now)
-                        state
-                    }
-                }
-            }
-            ev::ElementState::Released => {
-                match std::mem::replace(self, InputState::Pressed(777)) {
-                    InputState::Pressed(start_time) => {
-                        let state = InputState::Released(start_time,time_now);
-                        *self = state; //InputState::Released(start_time,time_now)
-                        state
-                    }
-                    InputState::Down(start_time, _) => {
-                        let state = InputState::Released(start_time,time_now);
-                        *self = state; //InputState::Released(start_time,time_now)
-                        state
-                    }
-                    InputState::Released(_,_) => {
-                        panic!("Already released.")
-                    }
-                }
-            }
+        *self = match (*self, state) {
+            (InputState::Pressed(start_time), ev::ElementState::Pressed) => InputState::Down(start_time, time_now),
+            (InputState::Down(start_time, _), ev::ElementState::Pressed) => InputState::Down(start_time, time_now),
+            (InputState::Released(_,_), ev::ElementState::Pressed) => InputState::Pressed(time_now),
+            (InputState::Pressed(start_time), ev::ElementState::Released) => InputState::Released(start_time, time_now),
+            (InputState::Down(start_time, _), ev::ElementState::Released) => InputState::Released(start_time, time_now),
+            (released @ InputState::Released(_,_), ev::ElementState::Released) => released,
+        };
+        *self
+    }
+
+    /// The next state of a button with an optional current state. A release of a button
+    /// without a state is ignored (e.g. the button was pressed before the window got focus).
+    pub fn next(current: Option<InputState>, state: &ev::ElementState, time_now: u128) -> Option<InputState> {
+        match (current, state) {
+            (Some(mut s), _) => Some(s.update(state, time_now)),
+            (None, ev::ElementState::Pressed) => Some(InputState::Pressed(time_now)),
+            (None, ev::ElementState::Released) => None,
+        }
+    }
+
+    /// The state at the beginning of the next frame. Released states are cleared, pressed
+    /// states become down and down states are updated to time_now.
+    pub fn advance(current: Option<InputState>, time_now: u128) -> Option<InputState> {
+        match current {
+            Some(InputState::Released(_,_)) | None => None,
+            Some(InputState::Pressed(start_time)) | Some(InputState::Down(start_time, _)) => Some(InputState::Down(start_time, time_now)),
         }
     }
 }
 
+/// The time source of InputCache. All times are in nano seconds.
+#[derive(Clone, Copy, Debug)]
+pub enum InputClock {
+    /// The time elapsed since the creation of the clock.
+    Timer(instant::Instant),
+    /// A manually advanced time. Useful for tests and tools.
+    Manual(u128),
+}
+
+impl InputClock {
+
+    /// Create a timer clock.
+    pub fn timer() -> Self {
+        InputClock::Timer(instant::Instant::now())
+    }
+
+    /// Get the current time.
+    pub fn now(&self) -> u128 {
+        match self {
+            InputClock::Timer(start) => start.elapsed().as_nanos(),
+            InputClock::Manual(time) => *time,
+        }
+    }
+
+    /// Advance a manual clock. Does nothing for a timer clock.
+    pub fn advance(&mut self, nanos: u128) {
+        if let InputClock::Manual(time) = self {
+            *time += nanos;
+        }
+    }
+
+    /// Set the time of a manual clock. Does nothing for a timer clock.
+    pub fn set(&mut self, nanos: u128) {
+        if let InputClock::Manual(time) = self {
+            *time = nanos;
+        }
+    }
+}
+
+/// The number of pixels in a single mouse wheel line. Used to convert pixel deltas to lines.
+pub const PIXELS_PER_LINE: f64 = 20.0;
+
+/// The default maximum time between successive clicks of a double or triple click (500 ms).
+pub const DEFAULT_CLICK_INTERVAL: u128 = 500_000_000;
+
 /// A struct for a single mouse button.
 #[derive(Clone)]
 pub struct MouseButton {
@@ -70,75 +106,119 @@ pub struct MouseButton {
 
     #[allow(dead_code)]
     tag: ev::MouseButton,
+
+    /// The time of the previous press.
+    last_press: Option<u128>,
+
+    /// The number of successive clicks (1 = single, 2 = double, 3 = triple).
+    click_count: u32,
 }
 
-/// A struct for mouse buttons (left, middle, right).
+impl MouseButton {
+
+    /// Create a mouse button without a state.
+    pub fn init(tag: ev::MouseButton) -> Self {
+        Self {
+            state: None,
+            tag,
+            last_press: None,
+            click_count: 0,
+        }
+    }
+
+    /// Update the button. A press within click_interval from the previous press increases the
+    /// click count. The count starts over after a triple click. Duplicate presses aren't counted.
+    fn update(&mut self, state: &ev::ElementState, time_now: u128, click_interval: u128) {
+
+        self.state = InputState::next(self.state, state, time_now);
+
+        if let Some(InputState::Pressed(_)) = self.state {
+            let successive = matches!(self.last_press, Some(t) if time_now.saturating_sub(t) <= click_interval);
+            self.click_count = if successive && self.click_count < 3 { self.click_count + 1 } else { 1 };
+            self.last_press = Some(time_now);
+        }
+    }
+
+    /// Released buttons are cleared, pressed buttons become down and down buttons stay down.
+    fn pre_update(&mut self, time_now: u128) {
+        self.state = InputState::advance(self.state, time_now);
+    }
+}
+
+/// A struct for mouse buttons (left, middle, right, back, forward and other buttons).
 #[derive(Clone)]
 pub struct MouseButtons {
-    left: MouseButton,
-    middle: MouseButton,
-    right: MouseButton,
+    buttons: HashMap<ev::MouseButton, MouseButton>,
+
+    /// The maximum time between successive clicks.
+    click_interval: u128,
 }
 
 impl MouseButtons {
     pub fn init() -> Self {
         Self {
-            left: MouseButton   { state: None , tag: ev::MouseButton::Left},
-            middle: MouseButton { state: None , tag: ev::MouseButton::Middle},
-            right: MouseButton  { state: None , tag: ev::MouseButton::Right},
+            buttons: HashMap::with_capacity(8),
+            click_interval: DEFAULT_CLICK_INTERVAL,
         }
     }
 
     /// Update mouse button.
     pub fn update(&mut self, button: &ev::MouseButton, state: &ev::ElementState, time_now: u128) {
-        match button {
-            ev::MouseButton::Left => {
-                match &mut self.left.state {
-                    Some(s) => {
-                        s.update(state, time_now);
-                    }
-                    None => {
-                        self.left.state = Some(InputState::Pressed(time_now));
-                    }
-                }
-            }
-            ev::MouseButton::Middle => {
-                match &mut self.middle.state {
-                    Some(s) => {
-                        s.update(state, time_now);
-                    }
-                    None => {
-                        self.middle.state = Some(InputState::Pressed(time_now));
-                    }
-                }
-            }
-            ev::MouseButton::Right => {
-                match &mut self.right.state {
-                    Some(s) => {
-                        s.update(state, time_now);
-                    }
-                    None => {
-                        self.right.state = Some(InputState::Pressed(time_now));
-                    }
-                }
-            }
-            _ => { /* Some other mouse button clicked. */ }
+        let click_interval = self.click_interval;
+        self.buttons
+            .entry(*button)
+            .or_insert_with(|| MouseButton::init(*button))
+            .update(state, time_now, click_interval);
+    }
+
+    /// Update the states of all buttons. See MouseButton::pre_update.
+    pub fn pre_update(&mut self, time_now: u128) {
+        for button in self.buttons.values_mut() {
+            button.pre_update(time_now);
+        }
+    }
+
+    /// Set the maximum time between successive clicks of a double or triple click.
+    pub fn set_click_interval(&mut self, click_interval: u128) {
+        self.click_interval = click_interval;
+    }
+
+    /// Get the state of a mouse button.
+    pub fn get(&self, button: &ev::MouseButton) -> Option<InputState> {
+        self.buttons.get(button).and_then(|b| b.state)
+    }
+
+    /// Get the click count of a mouse button. The count is 0 if the button isn't pressed or down.
+    pub fn get_click_count(&self, button: &ev::MouseButton) -> u32 {
+        match self.buttons.get(button) {
+            Some(b) if matches!(b.state, Some(InputState::Pressed(_)) | Some(InputState::Down(_,_))) => b.click_count,
+            _ => 0,
         }
     }
 
     /// Get the state of the left mouse button.
     pub fn get_left(&self) -> Option<InputState> {
-        self.left.state
+        self.get(&ev::MouseButton::Left)
     }
 
     /// Get the state of the middle mouse button.
     pub fn get_middle(&self) -> Option<InputState> {
-        self.middle.state
+        self.get(&ev::MouseButton::Middle)
     }
     
     /// Get the state of the right mouse button.
     pub fn get_right(&self) -> Option<InputState> {
-        self.right.state
+        self.get(&ev::MouseButton::Right)
+    }
+
+    /// Get the state of the back mouse button.
+    pub fn get_back(&self) -> Option<InputState> {
+        self.get(&ev::MouseButton::Back)
+    }
+
+    /// Get the state of the forward mouse button.
+    pub fn get_forward(&self) -> Option<InputState> {
+        self.get(&ev::MouseButton::Forward)
     }
 }
 
@@ -176,21 +256,41 @@ pub struct InputCache {
     /// The delta for the current and previous mouse position.
     mouse_delta: PhysicalPosition::<f64>,
 
-    /// The delta for the mouse scroll.
-    #[allow(dead_code)]
-    scroll_delta: f32,
+    /// The mouse wheel line delta of the current frame (horizontal, vertical).
+    scroll_line_delta: [f32; 2],
+
+    /// The mouse wheel pixel delta of the current frame (touchpads).
+    scroll_pixel_delta: PhysicalPosition::<f64>,
 
-    /// Time now in micro seconds.
+    /// Time now in nano seconds.
     time_now: u128,
 
     /// Delta for the current time and previous tick.
     time_delta: u128,
 
-    /// The timer instance.
-    timer: instant::Instant,
+    /// The time source.
+    clock: InputClock,
 
     /// Mouse move event happened.
     mouse_moved: bool,
+
+    /// The logical keys pressed (or repeated) on the current frame.
+    logical_keys: Vec<kb::Key>,
+
+    /// The typed text and editing keys of the current frame.
+    text_events: Vec<TextEvent>,
+
+    /// The current IME composition.
+    preedit: Option<Preedit>,
+
+    /// The recorded input if recording is on.
+    recording: Option<InputLog>,
+
+    /// The replay source. While replaying, the times and events come from the replay.
+    replay: Option<InputReplay>,
+
+    /// No simulation tick saw the pressed and released states of the previous frame.
+    hold_edges: bool,
 }
 
 impl InputCache {
@@ -200,21 +300,64 @@ impl InputCache {
         let keyboard = HashMap::<Key, InputState>::with_capacity(128);
         let mouse_buttons = MouseButtons::init();
         let mouse_position = CursorPosition::init();
-        let timer = instant::Instant::now();
 
         Self {
             keyboard,
             mouse_buttons,
             mouse_position,
             mouse_delta: PhysicalPosition::<f64>::new(0.0, 0.0),
-            scroll_delta: 0.0,
+            scroll_line_delta: [0.0, 0.0],
+            scroll_pixel_delta: PhysicalPosition::<f64>::new(0.0, 0.0),
             time_now: 0,
             time_delta: 0,
-            timer,
+            clock: InputClock::timer(),
             mouse_moved: false,
+            logical_keys: Vec::new(),
+            text_events: Vec::new(),
+            preedit: None,
+            recording: None,
+            replay: None,
+            hold_edges: false,
         }
     }
 
+    /// Create InputCache with the given clock.
+    pub fn with_clock(clock: InputClock) -> Self {
+        let mut input_cache = Self::init();
+        input_cache.clock = clock;
+        input_cache
+    }
+
+    /// Get the clock. Use this to advance a manual clock.
+    pub fn clock_mut(&mut self) -> &mut InputClock {
+        &mut self.clock
+    }
+
+    /// Start recording the consumed events and pre_update times. The log starts with a frame
+    /// marker at the current time, so the events consumed before the next pre_update are
+    /// replayed before that frame.
+    pub fn start_recording(&mut self) {
+        let mut log = InputLog::init();
+        log.entries.push(RecordEntry::Frame(self.time_now));
+        self.recording = Some(log);
+    }
+
+    /// Stop recording and return the recorded log.
+    pub fn take_recording(&mut self) -> Option<InputLog> {
+        self.recording.take()
+    }
+
+    /// Start replaying the log. The window events given to update are ignored until the log is
+    /// finished.
+    pub fn start_replay(&mut self, log: InputLog) {
+        self.replay = Some(InputReplay::init(log));
+    }
+
+    /// Is a replay running.
+    pub fn is_replaying(&self) -> bool {
+        self.replay.is_some()
+    }
+
     /// Get the current time.
     pub fn get_time(&self) -> u128 {
         self.time_now
@@ -225,78 +368,150 @@ impl InputCache {
         self.time_delta
     }
 
+    /// Prepare the cache for a fixed simulation tick. The time delta is replaced with the tick
+    /// length. The mouse delta and the pressed and released states are only visible on the first
+    /// tick of the frame.
+    pub(crate) fn prepare_tick(&mut self, tick_length: u128, first_tick: bool) {
+        self.time_delta = tick_length;
+        if !first_tick {
+            self.mouse_moved = false;
+            self.advance_states();
+        }
+    }
+
+    /// No simulation tick was run on this frame. The pressed and released states are kept for
+    /// the first tick of the next frame.
+    pub(crate) fn hold_edges(&mut self) {
+        self.hold_edges = true;
+    }
+
     /// Get the difference between the current and previous mouse position.
     pub fn get_mouse_delta(&self) -> PhysicalPosition::<f64> {
         if self.mouse_moved { self.mouse_delta }
         else { PhysicalPosition::<f64>::new(0.0, 0.0) }
     }
 
+    /// Get the vertical mouse wheel delta of the current frame in lines. Pixel deltas are
+    /// converted to lines with PIXELS_PER_LINE.
+    pub fn get_scroll_delta(&self) -> f32 {
+        self.scroll_line_delta[1] + (self.scroll_pixel_delta.y / PIXELS_PER_LINE) as f32
+    }
+
+    /// Get the mouse wheel line delta (horizontal, vertical) of the current frame.
+    pub fn get_scroll_line_delta(&self) -> [f32; 2] {
+        self.scroll_line_delta
+    }
+
+    /// Get the mouse wheel pixel delta of the current frame.
+    pub fn get_scroll_pixel_delta(&self) -> PhysicalPosition::<f64> {
+        self.scroll_pixel_delta
+    }
+
+    /// Get the absolute cursor position. None if the cursor hasn't moved over the window yet.
+    pub fn get_cursor_position(&self) -> Option<PhysicalPosition::<f64>> {
+        self.mouse_position.pos
+    }
+
+    /// Is the cursor inside the window.
+    pub fn is_cursor_inside(&self) -> bool {
+        self.mouse_position.inside
+    }
+
     /// This should be called before the actual update to ensure the all events takes effect even
     /// winit doesn't produce any events.
     pub fn pre_update(&mut self) {
-        
+
+        // Take the time and the events of the frame from the replay.
+        if let Some(replay) = self.replay.as_mut() {
+            if let Some((now, events)) = replay.next_frame() {
+                self.pre_update_with_time(now);
+                for e in events.iter() {
+                    self.apply(e);
+                }
+                return;
+            }
+            log::info!("Input replay finished.");
+            self.replay = None;
+        }
+
+        let now = self.clock.now();
+        self.pre_update_with_time(now);
+    }
+
+    /// Same as pre_update but the current time is given instead of read from the timer.
+    pub fn pre_update_with_time(&mut self, now: u128) {
+
+        if let Some(recording) = self.recording.as_mut() {
+            recording.entries.push(RecordEntry::Frame(now));
+        }
+
         self.mouse_moved = false;
+        self.logical_keys.clear();
+        self.text_events.clear();
+        self.scroll_line_delta = [0.0, 0.0];
+        self.scroll_pixel_delta = PhysicalPosition::<f64>::new(0.0, 0.0);
 
         // Update timer.
-        let now = self.timer.elapsed().as_nanos();
-        self.time_delta = now - self.time_now;
+        self.time_delta = now.saturating_sub(self.time_now);
         self.time_now = now;
 
-        // If mouse buttons were released previously, apply None to those states.
-        // TODO: loop.
-        if let Some(InputState::Released(_,_)) = self.mouse_buttons.left.state   { self.mouse_buttons.left.state = None }
-        if let Some(InputState::Released(_,_)) = self.mouse_buttons.middle.state { self.mouse_buttons.middle.state = None }
-        if let Some(InputState::Released(_,_)) = self.mouse_buttons.right.state  { self.mouse_buttons.right.state = None }
+        // The edges that no tick has seen are kept.
+        if self.hold_edges { self.hold_edges = false; }
+        else { self.advance_states(); }
+    }
 
-        // If left mouse button was pressed in previous tick, change the state to down.
-        if let Some(InputState::Pressed(start_time)) = self.mouse_buttons.left.state {
-            self.mouse_buttons.left.state = Some(InputState::Down(start_time,self.time_now));
-        }
-        if let Some(InputState::Pressed(start_time)) = self.mouse_buttons.middle.state {
-            self.mouse_buttons.middle.state = Some(InputState::Down(start_time,self.time_now));
-        }
-        if let Some(InputState::Pressed(start_time)) = self.mouse_buttons.right.state {
-            self.mouse_buttons.right.state = Some(InputState::Down(start_time,self.time_now));
-        }
+    /// Advance the button and key states to the current time.
+    fn advance_states(&mut self) {
 
-        // If the buttons are down, they stay down.
-        // TODO: loop
-        if let Some(InputState::Down(start_time, _)) = self.mouse_buttons.left.state {
-            self.mouse_buttons.left.state = Some(InputState::Down(start_time,self.time_now));
-        }
-        if let Some(InputState::Down(start_time, _)) = self.mouse_buttons.middle.state {
-            self.mouse_buttons.middle.state = Some(InputState::Down(start_time,self.time_now));
-        }
-        if let Some(InputState::Down(start_time, _)) = self.mouse_buttons.right.state {
-            self.mouse_buttons.right.state = Some(InputState::Down(start_time,self.time_now));
-        }
+        // Released mouse buttons are cleared and pressed buttons become down.
+        self.mouse_buttons.pre_update(self.time_now);
 
-        // If key is pressed, change it to down. If it's down, update the value.
-        for (_, val) in self.keyboard.iter_mut() {
-            match *val {
-                InputState::Pressed(v) => { *val = InputState::Down(v, self.time_now) },
-                InputState::Down(s, e) => { *val = InputState::Down(s, e + self.time_now) },
-                _ => { }
-            }
+        // If key is pressed, change it to down. If it's down, update the value. Remove key from
+        // hashmap if its previous state was 'released'.
+        let time_now = self.time_now;
+        self.keyboard.retain(|_, state| match InputState::advance(Some(*state), time_now) {
+            Some(s) => { *state = s; true }
+            None => false,
+        });
+    }
+
+    /// Process the new inputs. The events are ignored while replaying.
+    pub fn update(&mut self, event: &ev::WindowEvent) {
+        if self.replay.is_some() { return; }
 
+        for e in InputEvent::from_window_event(event).iter() {
+            self.apply(e);
         }
+    }
 
-        // Remove key from hashmap if its previous state was 'released'.
-        self.keyboard.retain(|_, state| match state { InputState::Released(_,_) => false, _ => true }); 
+    /// Push a synthetic input event. The event is handled like a window event, also while
+    /// replaying.
+    pub fn push_event(&mut self, event: &InputEvent) {
+        self.apply(event);
     }
 
-    /// Process the new inputs.
-    pub fn update(&mut self, event: &ev::WindowEvent) {
-        use ev::WindowEvent::*;
+    /// Process a single input event.
+    fn apply(&mut self, event: &InputEvent) {
+
+        if let Some(recording) = self.recording.as_mut() {
+            recording.entries.push(RecordEntry::Event(event.clone()));
+        }
 
         match event {
-            KeyboardInput { event, ..} => self.track_keyboard((*event).clone()),
-            MouseInput { button, state, ..} => self.track_mouse_button(*button, *state),
-            MouseWheel { delta, ..} => self.track_mouse_wheel(*delta),
-            CursorMoved { position, ..} => self.track_cursor_movement(*position),
-            CursorEntered { ..} => self.track_cursor_enter(),
-            CursorLeft { ..} => self.track_cursor_leave(),
-            _ => (),
+            InputEvent::Key { key, state } => self.track_keyboard(*key, *state),
+            InputEvent::MouseButton { button, state } => self.track_mouse_button(*button, *state),
+            InputEvent::MouseWheel { delta } => self.track_mouse_wheel(*delta),
+            InputEvent::CursorMoved { position } => self.track_cursor_movement(*position),
+            InputEvent::CursorEntered => self.track_cursor_enter(),
+            InputEvent::CursorLeft => self.track_cursor_leave(),
+            InputEvent::LogicalKey { key, state } => {
+                if *state == ev::ElementState::Pressed { self.logical_keys.push(key.clone()); }
+            }
+            InputEvent::Text(text) => self.text_events.push(TextEvent::Insert(text.clone())),
+            InputEvent::Edit(edit) => self.text_events.push(TextEvent::Edit(*edit)),
+            InputEvent::ImePreedit(preedit) => {
+                self.preedit = if preedit.text.is_empty() { None } else { Some(preedit.clone()) };
+            }
         }
     }
     /// Get the InputState of keyboard key.
@@ -304,31 +519,63 @@ impl InputCache {
         self.keyboard.get(key).copied()
     }
 
+    /// Get the logical keys pressed (or repeated) on the current frame. The logical key depends
+    /// on the keyboard layout.
+    pub fn get_logical_keys(&self) -> &[kb::Key] {
+        &self.logical_keys
+    }
+
+    /// Get the typed text and editing keys of the current frame. See TextBuffer::update. Call
+    /// Window::set_ime_allowed(true) to receive IME commits.
+    pub fn get_text_events(&self) -> &[TextEvent] {
+        &self.text_events
+    }
+
+    /// Get the typed text of the current frame without the editing keys.
+    pub fn get_text(&self) -> String {
+        self.text_events.iter().filter_map(|e| match e {
+            TextEvent::Insert(s) => Some(s.as_str()),
+            _ => None,
+        }).collect()
+    }
+
+    /// Get the current IME composition. None if there is no composition.
+    pub fn get_preedit(&self) -> Option<&Preedit> {
+        self.preedit.as_ref()
+    }
+
     /// Get the InputState of mouse button.
     pub fn mouse_button_state(&self, button: &ev::MouseButton) -> Option<InputState> {
-        match button {
-            ev::MouseButton::Left => { self.mouse_buttons.left.state } 
-            ev::MouseButton::Middle => { self.mouse_buttons.middle.state } 
-            ev::MouseButton::Right => { self.mouse_buttons.right.state } 
-            _ => None
-        }
+        self.mouse_buttons.get(button)
+    }
+
+    /// Get the number of successive clicks of a pressed mouse button (1 = single, 2 = double,
+    /// 3 = triple). Returns 0 if the button isn't pressed or down.
+    pub fn click_count(&self, button: &ev::MouseButton) -> u32 {
+        self.mouse_buttons.get_click_count(button)
+    }
+
+    /// Was the mouse button double clicked on this frame.
+    pub fn double_click(&self, button: &ev::MouseButton) -> bool {
+        matches!(self.mouse_button_state(button), Some(InputState::Pressed(_))) && self.click_count(button) == 2
+    }
+
+    /// Was the mouse button triple clicked on this frame.
+    pub fn triple_click(&self, button: &ev::MouseButton) -> bool {
+        matches!(self.mouse_button_state(button), Some(InputState::Pressed(_))) && self.click_count(button) == 3
+    }
+
+    /// Set the maximum time in nano seconds between successive clicks of a double or triple click.
+    pub fn set_click_interval(&mut self, click_interval: u128) {
+        self.mouse_buttons.set_click_interval(click_interval);
     }
     /// Update the state of keyboard.
-    fn track_keyboard(&mut self, evt: KeyEv) {
-        
-        match evt.physical_key {
-            PhysicalKey::Code(key_code) => 
-                match self.keyboard.get_mut(&key_code) {
-                    Some(state) => {
-                        let _debug_state = state.update(&evt.state, self.time_now);
-                    },
-                    None => {
-                        // The key doesn't have any state. Add a new pressed state for this key.
-                        let _ = self.keyboard.insert(key_code, InputState::Pressed(self.time_now));
-                    }
-                },
-                _ => {  },
-            // _ => {}
+    fn track_keyboard(&mut self, key_code: Key, element_state: ev::ElementState) {
+
+        // A key without a state gets a new pressed state. A release without a state is ignored.
+        match InputState::next(self.keyboard.get(&key_code).copied(), &element_state, self.time_now) {
+            Some(state) => { self.keyboard.insert(key_code, state); }
+            None => { self.keyboard.remove(&key_code); }
         }
     }
     /// Update the state of mouse buttons.
@@ -336,8 +583,17 @@ impl InputCache {
         self.mouse_buttons.update(&button, &state, self.time_now);
     }
     /// Update the state of mouse wheel.
-    fn track_mouse_wheel(&mut self, _delta: ev::MouseScrollDelta) {
-        //log::info!("track_mouse_wheel");
+    fn track_mouse_wheel(&mut self, delta: ev::MouseScrollDelta) {
+        match delta {
+            ev::MouseScrollDelta::LineDelta(x, y) => {
+                self.scroll_line_delta[0] += x;
+                self.scroll_line_delta[1] += y;
+            }
+            ev::MouseScrollDelta::PixelDelta(p) => {
+                self.scroll_pixel_delta.x += p.x;
+                self.scroll_pixel_delta.y += p.y;
+            }
+        }
     }
     /// Update the state of mouse movement.
     fn track_cursor_movement(&mut self, new_pos: PhysicalPosition<f64>) {
@@ -352,11 +608,11 @@ impl InputCache {
             }
         }
     }
-    /// Handle the cursor enter event. TODO: implement.
+    /// Handle the cursor enter event.
     fn track_cursor_enter(&mut self) {
         self.mouse_position.inside = true;
     }
-    /// Handle the cursor leave event. TODO: implement.
+    /// Handle the cursor leave event.
     fn track_cursor_leave(&mut self) {
         self.mouse_delta = PhysicalPosition::<f64>::new(0.0, 0.0);
         self.mouse_position.inside = false;
@@ -408,3 +664,212 @@ impl KeyboardManager {
         result
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ev::ElementState;
+
+    /// A small linear congruential generator for reproducible random sequences.
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self) -> u64 {
+            self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            self.0 >> 33
+        }
+    }
+
+    const KEYS: [Key; 3] = [Key::KeyA, Key::Space, Key::ShiftLeft];
+    const BUTTONS: [ev::MouseButton; 3] = [ev::MouseButton::Left, ev::MouseButton::Right, ev::MouseButton::Back];
+
+    fn element_state(value: u64) -> ElementState {
+        if value % 2 == 0 { ElementState::Pressed } else { ElementState::Released }
+    }
+
+    #[test]
+    fn every_state_and_event_has_a_successor() {
+        let states = [None, Some(InputState::Pressed(1)), Some(InputState::Down(1, 2)), Some(InputState::Released(1, 3))];
+
+        for state in states {
+            for event in [ElementState::Pressed, ElementState::Released] {
+                let next = InputState::next(state, &event, 10);
+                match event {
+                    ElementState::Pressed => assert!(matches!(next, Some(InputState::Pressed(_)) | Some(InputState::Down(_, _))),
+                                                     "{:?} + {:?} -> {:?}", state, event, next),
+                    ElementState::Released => assert!(matches!(next, Some(InputState::Released(_, _))) || state.is_none(),
+                                                      "{:?} + {:?} -> {:?}", state, event, next),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn random_key_sequences_follow_the_state_machine() {
+        let mut rng = Lcg(0x5eed);
+        let mut input = InputCache::with_clock(InputClock::Manual(0));
+        let mut time = 0;
+
+        for _ in 0..10000 {
+            if rng.next() % 4 == 0 {
+                let before: Vec<_> = KEYS.iter().map(|k| input.key_state(k)).collect();
+                time += 1 + rng.next() as u128 % 1000;
+                input.pre_update_with_time(time);
+
+                for (key, state) in KEYS.iter().zip(before) {
+                    let after = input.key_state(key);
+                    match state {
+                        Some(InputState::Pressed(start)) | Some(InputState::Down(start, _)) =>
+                            assert_eq!(after, Some(InputState::Down(start, time))),
+                        Some(InputState::Released(_, _)) | None => assert_eq!(after, None),
+                    }
+                }
+            }
+            else {
+                let key = KEYS[rng.next() as usize % KEYS.len()];
+                let event = element_state(rng.next());
+                let expected = InputState::next(input.key_state(&key), &event, time);
+                input.push_event(&InputEvent::Key { key, state: event });
+                assert_eq!(input.key_state(&key), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn random_mouse_button_sequences_follow_the_state_machine() {
+        let mut rng = Lcg(42);
+        let mut input = InputCache::with_clock(InputClock::Manual(0));
+        let mut time = 0;
+
+        for _ in 0..10000 {
+            if rng.next() % 4 == 0 {
+                let before: Vec<_> = BUTTONS.iter().map(|b| input.mouse_button_state(b)).collect();
+                time += 1 + rng.next() as u128 % 1000;
+                input.pre_update_with_time(time);
+
+                for (button, state) in BUTTONS.iter().zip(before) {
+                    let after = input.mouse_button_state(button);
+                    match state {
+                        Some(InputState::Pressed(start)) | Some(InputState::Down(start, _)) =>
+                            assert_eq!(after, Some(InputState::Down(start, time))),
+                        Some(InputState::Released(_, _)) | None => assert_eq!(after, None),
+                    }
+                }
+            }
+            else {
+                let button = BUTTONS[rng.next() as usize % BUTTONS.len()];
+                let event = element_state(rng.next());
+                let expected = InputState::next(input.mouse_button_state(&button), &event, time);
+                input.push_event(&InputEvent::MouseButton { button, state: event });
+                assert_eq!(input.mouse_button_state(&button), expected);
+            }
+        }
+    }
+
+    const MS: u128 = 1_000_000;
+
+    fn click(input: &mut InputCache, time: u128) {
+        input.pre_update_with_time(time);
+        input.push_event(&InputEvent::MouseButton { button: ev::MouseButton::Left, state: ElementState::Pressed });
+    }
+
+    fn release(input: &mut InputCache, time: u128) {
+        input.pre_update_with_time(time);
+        input.push_event(&InputEvent::MouseButton { button: ev::MouseButton::Left, state: ElementState::Released });
+    }
+
+    #[test]
+    fn wheel_deltas_accumulate_within_a_frame() {
+        let mut input = InputCache::with_clock(InputClock::Manual(0));
+        input.pre_update_with_time(1);
+        input.push_event(&InputEvent::MouseWheel { delta: ev::MouseScrollDelta::LineDelta(1.0, 2.0) });
+        input.push_event(&InputEvent::MouseWheel { delta: ev::MouseScrollDelta::LineDelta(0.5, -1.0) });
+        input.push_event(&InputEvent::MouseWheel { delta: ev::MouseScrollDelta::PixelDelta(PhysicalPosition::new(4.0, 2.0 * PIXELS_PER_LINE)) });
+
+        assert_eq!(input.get_scroll_line_delta(), [1.5, 1.0]);
+        assert_eq!(input.get_scroll_pixel_delta(), PhysicalPosition::new(4.0, 2.0 * PIXELS_PER_LINE));
+        // One line plus the pixel delta of two lines.
+        assert_eq!(input.get_scroll_delta(), 3.0);
+
+        // The deltas are per frame.
+        input.pre_update_with_time(2);
+        assert_eq!(input.get_scroll_line_delta(), [0.0, 0.0]);
+        assert_eq!(input.get_scroll_pixel_delta(), PhysicalPosition::new(0.0, 0.0));
+        assert_eq!(input.get_scroll_delta(), 0.0);
+    }
+
+    #[test]
+    fn successive_clicks_are_counted() {
+        let left = ev::MouseButton::Left;
+        let mut input = InputCache::with_clock(InputClock::Manual(0));
+
+        click(&mut input, 0);
+        assert_eq!(input.click_count(&left), 1);
+        assert!(!input.double_click(&left));
+        release(&mut input, 50 * MS);
+        assert_eq!(input.click_count(&left), 0);
+
+        click(&mut input, 100 * MS);
+        assert!(input.double_click(&left));
+        // Only the frame of the press is a double click, the count stays while the button is down.
+        input.pre_update_with_time(120 * MS);
+        assert!(!input.double_click(&left));
+        assert_eq!(input.click_count(&left), 2);
+        release(&mut input, 150 * MS);
+
+        click(&mut input, 200 * MS);
+        assert!(input.triple_click(&left));
+        release(&mut input, 250 * MS);
+
+        // The count starts over after a triple click.
+        click(&mut input, 300 * MS);
+        assert_eq!(input.click_count(&left), 1);
+        release(&mut input, 350 * MS);
+
+        // A press after the click interval is a single click.
+        click(&mut input, 300 * MS + DEFAULT_CLICK_INTERVAL + 1);
+        assert_eq!(input.click_count(&left), 1);
+    }
+
+    #[test]
+    fn click_interval_can_be_changed() {
+        let left = ev::MouseButton::Left;
+        let mut input = InputCache::with_clock(InputClock::Manual(0));
+        input.set_click_interval(100 * MS);
+
+        click(&mut input, 0);
+        release(&mut input, 10 * MS);
+        click(&mut input, 100 * MS);
+        assert!(input.double_click(&left));
+        release(&mut input, 110 * MS);
+        click(&mut input, 250 * MS);
+        assert_eq!(input.click_count(&left), 1);
+        assert!(!input.double_click(&left));
+    }
+
+    #[test]
+    fn cursor_enter_and_leave() {
+        let mut input = InputCache::with_clock(InputClock::Manual(0));
+        input.pre_update_with_time(1);
+        assert!(!input.is_cursor_inside());
+        assert_eq!(input.get_cursor_position(), None);
+
+        input.push_event(&InputEvent::CursorEntered);
+        input.push_event(&InputEvent::CursorMoved { position: PhysicalPosition::new(10.0, 10.0) });
+        input.push_event(&InputEvent::CursorMoved { position: PhysicalPosition::new(12.0, 7.0) });
+        assert!(input.is_cursor_inside());
+        assert_eq!(input.get_cursor_position(), Some(PhysicalPosition::new(12.0, 7.0)));
+        assert_eq!(input.get_mouse_delta(), PhysicalPosition::new(2.0, -3.0));
+
+        // Leaving clears the delta but keeps the last position.
+        input.push_event(&InputEvent::CursorLeft);
+        assert!(!input.is_cursor_inside());
+        assert_eq!(input.get_mouse_delta(), PhysicalPosition::new(0.0, 0.0));
+        assert_eq!(input.get_cursor_position(), Some(PhysicalPosition::new(12.0, 7.0)));
+
+        input.pre_update_with_time(2);
+        assert!(!input.is_cursor_inside());
+        input.push_event(&InputEvent::CursorEntered);
+        assert!(input.is_cursor_inside());
+    }
+}
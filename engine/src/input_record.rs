@@ -0,0 +1,282 @@
+use std::path::Path;
+use serde::{Serialize, Deserialize};
+
+use winit::event as ev;
+use winit::dpi::PhysicalPosition;
+use crate::input_cache::Key;
+use crate::text_input::{EditKey, Preedit};
+
+/// The environment variable for the file where the input is recorded.
+pub const INPUT_RECORD_ENV: &str = "INPUT_RECORD";
+
+/// The environment variable for the file from which the input is replayed.
+pub const INPUT_REPLAY_ENV: &str = "INPUT_REPLAY";
+
+/// An input event consumed by InputCache. A serializable subset of winit WindowEvent.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum InputEvent {
+    Key { key: Key, state: ev::ElementState },
+    MouseButton { button: ev::MouseButton, state: ev::ElementState },
+    MouseWheel { delta: ev::MouseScrollDelta },
+    CursorMoved { position: PhysicalPosition<f64> },
+    CursorEntered,
+    CursorLeft,
+    LogicalKey { key: winit::keyboard::Key, state: ev::ElementState },
+    Text(String),
+    Edit(EditKey),
+    ImePreedit(Preedit),
+}
+
+impl InputEvent {
+
+    /// Convert a winit WindowEvent to InputEvents. A keyboard event produces the physical and
+    /// the logical key and the typed text or editing key. IME commits are converted to text.
+    /// Returns an empty vector for the events InputCache doesn't handle.
+    pub fn from_window_event(event: &ev::WindowEvent) -> Vec<Self> {
+        use ev::WindowEvent::*;
+
+        match event {
+            KeyboardInput { event, .. } => Self::from_key_event(event.physical_key, &event.logical_key, event.text.as_deref(), event.state),
+            Ime(ev::Ime::Commit(text)) => vec![InputEvent::Text(text.clone())],
+            Ime(ev::Ime::Preedit(text, cursor)) => vec![InputEvent::ImePreedit(Preedit { text: text.clone(), cursor: *cursor })],
+            Ime(ev::Ime::Disabled) => vec![InputEvent::ImePreedit(Preedit::default())],
+            MouseInput { button, state, .. } => vec![InputEvent::MouseButton { button: *button, state: *state }],
+            MouseWheel { delta, .. } => vec![InputEvent::MouseWheel { delta: *delta }],
+            CursorMoved { position, .. } => vec![InputEvent::CursorMoved { position: *position }],
+            CursorEntered { .. } => vec![InputEvent::CursorEntered],
+            CursorLeft { .. } => vec![InputEvent::CursorLeft],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Convert the parts of a winit KeyEvent to InputEvents: the physical and the logical key
+    /// and on a press the editing key or the typed text without control characters.
+    pub fn from_key_event(physical_key: winit::keyboard::PhysicalKey,
+                          logical_key: &winit::keyboard::Key,
+                          text: Option<&str>,
+                          state: ev::ElementState) -> Vec<Self> {
+
+        let mut events = Vec::with_capacity(3);
+        if let winit::keyboard::PhysicalKey::Code(key) = physical_key {
+            events.push(InputEvent::Key { key, state });
+        }
+        events.push(InputEvent::LogicalKey { key: logical_key.clone(), state });
+
+        if state == ev::ElementState::Pressed {
+            if let Some(edit) = EditKey::from_logical_key(logical_key) {
+                events.push(InputEvent::Edit(edit));
+            }
+            else if let Some(text) = text {
+                let text: String = text.chars().filter(|c| !c.is_control()).collect();
+                if !text.is_empty() { events.push(InputEvent::Text(text)); }
+            }
+        }
+        events
+    }
+}
+
+/// A single entry of the input log.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum RecordEntry {
+    /// InputCache::pre_update was called. The time is in nano seconds.
+    Frame(u128),
+    /// An event was consumed after the previous frame.
+    Event(InputEvent),
+}
+
+/// A recorded input session.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct InputLog {
+    pub entries: Vec<RecordEntry>,
+}
+
+impl InputLog {
+
+    /// Create an empty log.
+    pub fn init() -> Self {
+        Self { entries: Vec::new() }
+    }
+
+    /// Serialize the log to a RON string.
+    pub fn to_ron(&self) -> Result<String, ron::Error> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+    }
+
+    /// Deserialize the log from a RON string.
+    pub fn from_ron(s: &str) -> Result<Self, ron::error::SpannedError> {
+        ron::from_str(s)
+    }
+
+    /// Save the log to a RON file.
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let s = self.to_ron().map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        std::fs::write(path, s)
+    }
+
+    /// Load the log from a RON file.
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let s = std::fs::read_to_string(path)?;
+        Self::from_ron(&s).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// Feeds a recorded log back frame by frame.
+#[derive(Clone, Debug)]
+pub struct InputReplay {
+    log: InputLog,
+    position: usize,
+}
+
+impl InputReplay {
+
+    /// Create a replay of the log.
+    pub fn init(log: InputLog) -> Self {
+        Self { log, position: 0 }
+    }
+
+    /// Is the whole log replayed.
+    pub fn is_finished(&self) -> bool {
+        self.position >= self.log.entries.len()
+    }
+
+    /// Get the time of the next frame and the events that were consumed after it. Events
+    /// before the first frame are returned with the first frame (logs recorded by InputCache
+    /// start with a frame). Returns None when the log is finished.
+    pub fn next_frame(&mut self) -> Option<(u128, Vec<InputEvent>)> {
+
+        let mut time = None;
+        let mut events = Vec::new();
+
+        while let Some(entry) = self.log.entries.get(self.position) {
+            match entry {
+                RecordEntry::Frame(t) => {
+                    if time.is_some() { break; }
+                    time = Some(*t);
+                }
+                RecordEntry::Event(e) => events.push(e.clone()),
+            }
+            self.position += 1;
+        }
+
+        time.map(|t| (t, events))
+    }
+}
+
+/// Start recording or replaying the input if INPUT_RECORD or INPUT_REPLAY environment variable
+/// is set. Replaying takes precedence.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn setup_from_env(input_cache: &mut crate::input_cache::InputCache) {
+    if let Ok(path) = std::env::var(INPUT_REPLAY_ENV) {
+        match InputLog::load(&path) {
+            Ok(log) => {
+                log::info!("Replaying input from {}.", path);
+                input_cache.start_replay(log);
+            }
+            Err(e) => log::error!("Failed to load input log {}: {}", path, e),
+        }
+    }
+    else if std::env::var(INPUT_RECORD_ENV).is_ok() {
+        log::info!("Recording input.");
+        input_cache.start_recording();
+    }
+}
+
+/// Save the recorded input to the file given in INPUT_RECORD environment variable.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save_to_env(input_cache: &mut crate::input_cache::InputCache) {
+    if let (Ok(path), Some(log)) = (std::env::var(INPUT_RECORD_ENV), input_cache.take_recording()) {
+        match log.save(&path) {
+            Ok(()) => log::info!("Input saved to {}.", path),
+            Err(e) => log::error!("Failed to save input log {}: {}", path, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input_cache::{InputCache, InputClock};
+    use winit::event::{ElementState, MouseButton, MouseScrollDelta};
+
+    /// The observable state of the cache.
+    fn state(input: &InputCache) -> String {
+        format!("{} {} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?}",
+                input.get_time(),
+                input.get_time_delta(),
+                input.key_state(&Key::KeyW),
+                input.key_state(&Key::Space),
+                input.mouse_button_state(&MouseButton::Left),
+                input.click_count(&MouseButton::Left),
+                input.get_cursor_position(),
+                input.get_scroll_line_delta(),
+                input.get_text_events(),
+                input.get_preedit())
+    }
+
+    fn frames() -> Vec<(u128, Vec<InputEvent>)> {
+        let key = |key, state| InputEvent::Key { key, state };
+        let button = |state| InputEvent::MouseButton { button: MouseButton::Left, state };
+        vec![
+            (10, vec![key(Key::KeyW, ElementState::Pressed), InputEvent::CursorEntered,
+                      InputEvent::CursorMoved { position: PhysicalPosition::new(3.0, 4.0) }]),
+            (20, vec![]),
+            (30, vec![button(ElementState::Pressed), InputEvent::MouseWheel { delta: MouseScrollDelta::LineDelta(0.0, 2.0) }]),
+            (40, vec![button(ElementState::Released), InputEvent::Text("ä".to_string()), key(Key::KeyW, ElementState::Released)]),
+            (50, vec![button(ElementState::Pressed), InputEvent::ImePreedit(Preedit { text: "k".to_string(), cursor: Some((1, 1)) })]),
+            (60, vec![]),
+        ]
+    }
+
+    #[test]
+    fn replay_reproduces_the_recorded_states() {
+        let mut recorder = InputCache::with_clock(InputClock::Manual(0));
+        recorder.start_recording();
+
+        // Consumed before the first pre_update.
+        recorder.push_event(&InputEvent::Key { key: Key::Space, state: ElementState::Pressed });
+        let mut expected = vec![state(&recorder)];
+
+        for (time, events) in frames() {
+            recorder.pre_update_with_time(time);
+            for e in events.iter() {
+                recorder.push_event(e);
+            }
+            expected.push(state(&recorder));
+        }
+
+        let log = recorder.take_recording().unwrap();
+        assert_eq!(log.entries.first(), Some(&RecordEntry::Frame(0)));
+        let log = InputLog::from_ron(&log.to_ron().unwrap()).unwrap();
+
+        let mut replayer = InputCache::with_clock(InputClock::Manual(1000));
+        replayer.start_replay(log);
+        let mut replayed = Vec::new();
+        while replayer.is_replaying() {
+            replayer.pre_update();
+            if replayer.is_replaying() { replayed.push(state(&replayer)); }
+        }
+        assert_eq!(replayed, expected);
+    }
+
+    #[test]
+    fn next_frame_groups_the_events_after_each_frame() {
+        let event = |key| RecordEntry::Event(InputEvent::Key { key, state: ElementState::Pressed });
+        let mut replay = InputReplay::init(InputLog {
+            entries: vec![event(Key::KeyA), RecordEntry::Frame(1), event(Key::KeyB), RecordEntry::Frame(2), RecordEntry::Frame(3), event(Key::KeyC)],
+        });
+
+        let keys = |events: Vec<InputEvent>| events.into_iter().map(|e| match e {
+            InputEvent::Key { key, .. } => key,
+            _ => unreachable!(),
+        }).collect::<Vec<_>>();
+
+        let (time, events) = replay.next_frame().unwrap();
+        assert_eq!((time, keys(events)), (1, vec![Key::KeyA, Key::KeyB]));
+        let (time, events) = replay.next_frame().unwrap();
+        assert_eq!((time, keys(events)), (2, vec![]));
+        let (time, events) = replay.next_frame().unwrap();
+        assert_eq!((time, keys(events)), (3, vec![Key::KeyC]));
+        assert!(replay.is_finished());
+        assert_eq!(replay.next_frame(), None);
+    }
+}
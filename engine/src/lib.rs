@@ -1,12 +1,28 @@
 pub mod core;
+pub mod error;
 pub mod basic_loop;
+pub mod headless_loop;
+pub mod fixed_step_loop;
 pub mod input_cache;
+pub mod input_record;
+pub mod action_map;
+pub mod text_input;
 pub mod texture;
 pub mod logger;
 pub mod camera;
+pub mod camera_controller;
+pub mod camera_registry;
+pub mod camera_path;
+pub mod camera_snapshot;
+pub mod frustum;
+pub mod picking;
+pub mod collision;
 pub mod render_pass;
 pub mod misc;
 pub mod buffer;
+pub mod readback;
+pub mod uniform_ring;
+pub mod wgsl_layout;
 pub mod meshes;
 pub mod pipelines;
 pub mod default_things;
@@ -17,6 +33,7 @@ pub mod lights;
 pub mod noise_maker;
 pub mod pipeline_stuff;
 pub mod histogram;
+pub mod frame_stats;
 pub mod common_structs;
 pub mod algorithms;
 pub mod gpu_debugger;
@@ -1,7 +1,5 @@
 use bytemuck::Pod;
 use bytemuck::Zeroable;
-use crate::impl_convert;
-use crate::misc::Convert2Vec;
 use crate::buffer::buffer_from_data;
 
 #[repr(C)]
@@ -15,6 +13,39 @@ pub struct Light {
     attentuation_factor: f32,
 }
 
+impl Light {
+
+    /// Create a light. The colors are converted from [0, 255] to [0, 1].
+    pub fn init(position: [f32; 3],
+                material_spec_color: [u8; 3],
+                light_color: [u8; 3],
+                material_shininess: f32,
+                ambient_coeffience: f32,
+                attentuation_factor: f32) -> Self {
+
+        Self {
+            light_pos: position,
+            material_shininess: material_shininess,
+            material_spec_color: [material_spec_color[0] as f32 / 255.0,
+                                  material_spec_color[1] as f32 / 255.0,
+                                  material_spec_color[2] as f32 / 255.0],
+            ambient_coeffience: ambient_coeffience,
+            light_color: [light_color[0] as f32 / 255.0, light_color[1] as f32 / 255.0, light_color[2] as f32 / 255.0],
+            attentuation_factor: attentuation_factor,
+        }
+    }
+
+    /// Set the position of the light.
+    pub fn set_position(&mut self, position: [f32; 3]) {
+        self.light_pos = position;
+    }
+
+    /// Get the position of the light.
+    pub fn get_position(&self) -> [f32; 3] {
+        self.light_pos
+    }
+}
+
 pub struct LightBuffer {
     _light: Light,
     buffer: wgpu::Buffer,
@@ -29,16 +60,7 @@ impl LightBuffer {
                   ambient_coeffience: f32,
                   attentuation_factor: f32) -> Self {
 
-        let light = Light {
-            light_pos: position,
-            material_shininess: material_shininess,
-            material_spec_color: [material_spec_color[0] as f32 / 255.0,
-                                  material_spec_color[1] as f32 / 255.0,
-                                  material_spec_color[2] as f32 / 255.0],
-            ambient_coeffience: ambient_coeffience,
-            light_color: [light_color[0] as f32 / 255.0, light_color[1] as f32 / 255.0, light_color[2] as f32 / 255.0],
-            attentuation_factor: attentuation_factor,
-        };
+        let light = Light::init(position, material_spec_color, light_color, material_shininess, ambient_coeffience, attentuation_factor);
 
         let buf = buffer_from_data::<Light>(
                   &device,
@@ -57,5 +79,3 @@ impl LightBuffer {
         &self.buffer
     }
 }
-
-impl_convert!{Light}
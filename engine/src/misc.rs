@@ -1,27 +1,23 @@
+use crate::error::EngineError;
+
 /// A trait for types that can be copied from wgpu::buffer buffer to
-/// a std::Vec. // TODO: check if there is already an implementation for this.
+/// a std::Vec. Implemented for all Pod types.
 pub trait Convert2Vec where Self: std::marker::Sized {
-    fn convert(data: &[u8]) -> Vec<Self>;  
+    /// Convert the bytes to elements. The length of the data must be a multiple of the element
+    /// size. The data doesn't need to be aligned.
+    fn convert(data: &[u8]) -> Result<Vec<Self>, EngineError>;
 }
 
-#[macro_export]
-macro_rules! impl_convert {
-  ($to_type:ty) => {
-    impl Convert2Vec for $to_type {
-      fn convert(data: &[u8]) -> Vec<Self> {
-            let result = data
-                .chunks_exact(std::mem::size_of::<Self>())
-                .map(|b| *bytemuck::try_from_bytes::<Self>(b).unwrap())
-                .collect();
-            result
-      }
+impl<T: bytemuck::Pod> Convert2Vec for T {
+    fn convert(data: &[u8]) -> Result<Vec<Self>, EngineError> {
+        let element_size = std::mem::size_of::<T>();
+        if element_size == 0 || data.len() % element_size != 0 {
+            return Err(EngineError::ByteLengthMismatch { len: data.len(), element_size: element_size });
+        }
+        Ok(data.chunks_exact(element_size).map(bytemuck::pod_read_unaligned).collect())
     }
-  }
 }
 
-impl_convert!{u32}
-impl_convert!{f32}
-
 pub fn udiv_up_32(x: u32, y: u32) -> u32 {
   (x + y - 1) / y
 }
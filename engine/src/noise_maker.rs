@@ -1,11 +1,12 @@
 use crate::pipelines::ComputePipelineWrapper;
-use std::mem::size_of;
 use crate::pipelines::BindGroupMapper;
 use bytemuck::Pod;
 use bytemuck::Zeroable;
-use crate::misc::Convert2Vec;
-use crate::impl_convert;
-use crate::buffer::buffer_from_data;
+use crate::buffer::GpuBuffer;
+use crate::readback::ReadbackPool;
+use crate::error::EngineError;
+use crate::check_wgsl_layout;
+use crate::wgsl_layout::AddressSpace;
 use crate::bindgroups::{
     create_uniform_bindgroup_layout,
     create_buffer_bindgroup_layout,
@@ -24,11 +25,19 @@ pub struct NoiseParams {
     pub param_c: f32,
 }
 
-impl_convert!{NoiseParams}
+impl NoiseParams {
+
+    /// Check the layout against the NoiseParams struct of the noise shader.
+    pub fn check_layout() -> Result<(), EngineError> {
+        check_wgsl_layout!(NoiseParams, include_str!("shaders/basic_noise.wgsl"), AddressSpace::Uniform,
+                           global_dim, param_a, local_dim, param_b, position, param_c)
+    }
+}
+
 
 pub struct NoiseParamBuffer {
     pub noise_params: NoiseParams, // TODO: getter
-    buffer: wgpu::Buffer,
+    buffer: GpuBuffer<NoiseParams>,
 }
 
 impl NoiseParamBuffer {
@@ -51,9 +60,9 @@ impl NoiseParamBuffer {
             param_c,
         };
 
-        let buf = buffer_from_data::<NoiseParams>(
+        let buf = GpuBuffer::<NoiseParams>::from_data(
                   &device,
-                  &vec![params],
+                  &[params],
                   wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST,
                   Some("noise params buffer.")
         );
@@ -65,7 +74,7 @@ impl NoiseParamBuffer {
     }
 
     pub fn get_buffer(&self) -> &wgpu::Buffer {
-        &self.buffer
+        self.buffer.get_buffer()
     }
 
     pub fn get_noise_params(&self) -> &NoiseParams {
@@ -74,11 +83,7 @@ impl NoiseParamBuffer {
 
     pub fn update(&self, queue: &wgpu::Queue) {
 
-        queue.write_buffer(
-            &self.buffer,
-            0,
-            bytemuck::cast_slice(&[self.noise_params])
-        );
+        self.buffer.write(queue, 0..1, &[self.noise_params]).expect("The noise params buffer holds one element.");
     }
 }
 
@@ -86,8 +91,9 @@ impl NoiseParamBuffer {
 pub struct NoiseMaker {
     compute_pipeline_wrapper: ComputePipelineWrapper,
     pub noise_params: NoiseParamBuffer, // TODO: getter
-    buffer: wgpu::Buffer,
+    buffer: GpuBuffer<f32>,
     bind_group: wgpu::BindGroup,
+    readback: ReadbackPool,
 }
 
 impl NoiseMaker {
@@ -101,23 +107,19 @@ impl NoiseMaker {
                 param_b:  f32,
                 param_c: f32) -> Self {
 
-        // Validation.
-        let buf_size = (global_dimension[0] *
-                        global_dimension[1] *
-                        global_dimension[2] *
-                        local_dimension[0] *
-                        local_dimension[1] *
-                        local_dimension[2] *
-                        size_of::<f32>() as u32) as u64;
+        let value_count = (global_dimension[0] *
+                           global_dimension[1] *
+                           global_dimension[2] *
+                           local_dimension[0] *
+                           local_dimension[1] *
+                           local_dimension[2]) as usize;
 
         // TODO: get buffer outside of struct.
-        let buf = device.create_buffer(&wgpu::BufferDescriptor{
-                      label: Some("noise buffer"), // Get label from function parameter
-                      size: buf_size,
-                      usage: wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
-                      mapped_at_creation: false,
-                      }
-        );
+        let buf = GpuBuffer::<f32>::zeroed(
+                      device,
+                      value_count,
+                      wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+                      Some("noise buffer")); // Get label from function parameter
 
         // Create here pipeline and bind group mapper.
         let mut bind_group_mapper = BindGroupMapper::init(device);
@@ -163,7 +165,7 @@ impl NoiseMaker {
             device,
             &vec![
             &params.get_buffer().as_entire_binding(),
-            &buf.as_entire_binding(),
+            &buf.get_buffer().as_entire_binding(),
             ],
             0);
 
@@ -172,6 +174,7 @@ impl NoiseMaker {
             noise_params: params,
             buffer: buf,
             bind_group: bind_group,
+            readback: ReadbackPool::init(),
         }
     }
 
@@ -234,10 +237,26 @@ impl NoiseMaker {
     }
 
     pub fn get_buffer(&self) -> &wgpu::Buffer {
-        &self.buffer
+        self.buffer.get_buffer()
+    }
+
+    /// Read the noise values (e.g. for collision::ScalarField). Blocks until the values are
+    /// read, so this returns EngineError::BlockingReadback on wasm.
+    pub fn read_values(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Vec<f32>, EngineError> {
+        self.buffer.read_with(&self.readback, device, queue, 0..self.buffer.len())
     }
 
     pub fn get_position(&self) -> [f32; 3] {
         self.noise_params.noise_params.position
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn noise_params_layout_matches_the_shader() {
+        NoiseParams::check_layout().unwrap();
+    }
+}
@@ -0,0 +1,245 @@
+use cgmath::{prelude::*, Vector3};
+use crate::gpu_debugger::primitive_processor::AABB;
+use crate::misc::uvec3_to_index;
+
+/// A ray with a normalized direction.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: Vector3<f32>,
+    pub direction: Vector3<f32>,
+}
+
+/// The first hit of a ray. For AABB picking the index is the index of the box, for voxel
+/// traversal the index is uvec3_to_index of the voxel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RayHit {
+    pub index: u32,
+    pub distance: f32,
+    pub point: [f32; 3],
+    pub normal: [f32; 3],
+}
+
+impl Ray {
+
+    /// Create a ray. The direction is normalized.
+    pub fn new(origin: [f32; 3], direction: [f32; 3]) -> Self {
+        let direction = Vector3::from(direction);
+        assert!(direction.magnitude2() > 0.0, "Ray direction must be non-zero.");
+
+        Self {
+            origin: origin.into(),
+            direction: direction.normalize(),
+        }
+    }
+
+    /// The point at distance t along the ray.
+    pub fn at(&self, t: f32) -> Vector3<f32> {
+        self.origin + self.direction * t
+    }
+
+    /// Ray/box slab test. Returns the entry and exit distances and the entry normal. If the
+    /// origin is inside the box, the entry distance is 0.0 and the normal is zero.
+    pub fn intersect_aabb(&self, min: [f32; 3], max: [f32; 3]) -> Option<(f32, f32, [f32; 3])> {
+
+        let mut t_enter = f32::NEG_INFINITY;
+        let mut t_exit = f32::INFINITY;
+        let mut normal = [0.0; 3];
+
+        for axis in 0..3 {
+            let o = self.origin[axis];
+            let d = self.direction[axis];
+
+            if d.abs() < 1e-12 {
+                // Parallel to the slab.
+                if o < min[axis] || o > max[axis] { return None; }
+                continue;
+            }
+
+            let (mut t0, mut t1) = ((min[axis] - o) / d, (max[axis] - o) / d);
+            let mut sign = -1.0;
+            if t0 > t1 {
+                std::mem::swap(&mut t0, &mut t1);
+                sign = 1.0;
+            }
+            if t0 > t_enter {
+                t_enter = t0;
+                normal = [0.0; 3];
+                normal[axis] = sign;
+            }
+            t_exit = t_exit.min(t1);
+
+            if t_enter > t_exit { return None; }
+        }
+
+        if t_exit < 0.0 { return None; }
+        if t_enter < 0.0 { return Some((0.0, t_exit, [0.0; 3])); }
+
+        Some((t_enter, t_exit, normal))
+    }
+
+    /// Find the nearest box hit by the ray.
+    pub fn pick_aabb(&self, aabbs: &[AABB]) -> Option<RayHit> {
+        aabbs.iter().enumerate().filter_map(|(i, aabb)| {
+            self.intersect_aabb([aabb.min[0], aabb.min[1], aabb.min[2]], [aabb.max[0], aabb.max[1], aabb.max[2]])
+                .map(|(t, _, normal)| {
+                    let p = self.at(t);
+                    RayHit { index: i as u32, distance: t, point: p.into(), normal }
+                })
+        }).min_by(|a, b| a.distance.total_cmp(&b.distance))
+    }
+}
+
+/// A regular voxel grid. Voxel (x, y, z) covers [origin + (x, y, z) * voxel_size, origin + (x + 1, y + 1, z + 1) * voxel_size].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VoxelGrid {
+    pub origin: [f32; 3],
+    pub voxel_size: f32,
+    pub dimensions: [u32; 3],
+}
+
+impl VoxelGrid {
+
+    /// Create a voxel grid.
+    pub fn new(origin: [f32; 3], voxel_size: f32, dimensions: [u32; 3]) -> Self {
+        assert!(voxel_size > 0.0, "Voxel size must be > 0.");
+        Self { origin, voxel_size, dimensions }
+    }
+
+    /// Traverse the voxels along the ray with DDA (Amanatides & Woo) and return the first voxel
+    /// for which is_solid returns true. is_solid gets the uvec3_to_index of the voxel. The
+    /// traversal stops at max_distance.
+    pub fn traverse<F: FnMut(u32) -> bool>(&self, ray: &Ray, max_distance: f32, mut is_solid: F) -> Option<RayHit> {
+
+        let dims = self.dimensions;
+        if dims.contains(&0) { return None; }
+
+        let grid_max = [
+            self.origin[0] + dims[0] as f32 * self.voxel_size,
+            self.origin[1] + dims[1] as f32 * self.voxel_size,
+            self.origin[2] + dims[2] as f32 * self.voxel_size,
+        ];
+
+        let (t_enter, t_exit, entry_normal) = ray.intersect_aabb(self.origin, grid_max)?;
+        let t_end = t_exit.min(max_distance);
+        if t_enter > t_end { return None; }
+
+        // The first voxel.
+        let start = ray.at(t_enter);
+        let mut voxel = [0i64; 3];
+        let mut step = [0i64; 3];
+        let mut t_max = [f32::INFINITY; 3];
+        let mut t_delta = [f32::INFINITY; 3];
+
+        for axis in 0..3 {
+            let local = (start[axis] - self.origin[axis]) / self.voxel_size;
+            voxel[axis] = (local.floor() as i64).clamp(0, dims[axis] as i64 - 1);
+
+            let d = ray.direction[axis];
+            if d > 0.0 {
+                step[axis] = 1;
+                let boundary = self.origin[axis] + (voxel[axis] + 1) as f32 * self.voxel_size;
+                t_max[axis] = t_enter + (boundary - start[axis]) / d;
+                t_delta[axis] = self.voxel_size / d;
+            }
+            else if d < 0.0 {
+                step[axis] = -1;
+                let boundary = self.origin[axis] + voxel[axis] as f32 * self.voxel_size;
+                t_max[axis] = t_enter + (boundary - start[axis]) / d;
+                t_delta[axis] = -self.voxel_size / d;
+            }
+        }
+
+        let mut t = t_enter;
+        let mut normal = entry_normal;
+
+        loop {
+            let index = uvec3_to_index(voxel[0] as u32, voxel[1] as u32, voxel[2] as u32, dims[0], dims[1]);
+            if is_solid(index) {
+                return Some(RayHit { index, distance: t, point: ray.at(t).into(), normal });
+            }
+
+            // Step to the next voxel along the axis with the nearest boundary.
+            let axis = if t_max[0] < t_max[1] {
+                if t_max[0] < t_max[2] { 0 } else { 2 }
+            }
+            else if t_max[1] < t_max[2] { 1 } else { 2 };
+
+            t = t_max[axis];
+            if t > t_end { return None; }
+
+            voxel[axis] += step[axis];
+            if voxel[axis] < 0 || voxel[axis] >= dims[axis] as i64 { return None; }
+
+            t_max[axis] += t_delta[axis];
+            normal = [0.0; 3];
+            normal[axis] = -step[axis] as f32;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aabb(min: [f32; 3], max: [f32; 3]) -> AABB {
+        AABB { min: [min[0], min[1], min[2], 0.0], max: [max[0], max[1], max[2], 0.0] }
+    }
+
+    #[test]
+    fn slab_hit_and_miss() {
+        let ray = Ray::new([0.0, 0.0, 5.0], [0.0, 0.0, -2.0]);
+
+        let (t_enter, t_exit, normal) = ray.intersect_aabb([-1.0, -1.0, -1.0], [1.0, 1.0, 1.0]).unwrap();
+        assert_eq!((t_enter, t_exit, normal), (4.0, 6.0, [0.0, 0.0, 1.0]));
+
+        assert_eq!(ray.intersect_aabb([2.0, -1.0, -1.0], [3.0, 1.0, 1.0]), None);
+        // The box is behind the ray.
+        assert_eq!(ray.intersect_aabb([-1.0, -1.0, 6.0], [1.0, 1.0, 7.0]), None);
+        // The origin is inside the box.
+        assert_eq!(ray.intersect_aabb([-1.0, -1.0, 4.0], [1.0, 1.0, 6.0]), Some((0.0, 1.0, [0.0; 3])));
+    }
+
+    #[test]
+    fn axis_parallel_ray() {
+        let ray = Ray::new([0.5, 0.5, -5.0], [0.0, 0.0, 1.0]);
+        assert!(ray.intersect_aabb([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]).is_some());
+        // Parallel to the x and y slabs but outside the x slab.
+        assert_eq!(ray.intersect_aabb([1.0001, 0.0, 0.0], [2.0, 1.0, 1.0]), None);
+    }
+
+    #[test]
+    fn pick_aabb_returns_the_nearest_box() {
+        let ray = Ray::new([0.0, 0.0, 10.0], [0.0, 0.0, -1.0]);
+        let aabbs = [
+            aabb([-1.0, -1.0, -6.0], [1.0, 1.0, -4.0]),
+            aabb([-1.0, -1.0, 1.0], [1.0, 1.0, 3.0]),
+            aabb([5.0, 5.0, 0.0], [6.0, 6.0, 1.0]),
+        ];
+        let hit = ray.pick_aabb(&aabbs).unwrap();
+        assert_eq!(hit.index, 1);
+        assert_eq!(hit.distance, 7.0);
+        assert_eq!(hit.point, [0.0, 0.0, 3.0]);
+        assert_eq!(hit.normal, [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn dda_visits_the_cells_in_order() {
+        let grid = VoxelGrid::new([0.0, 0.0, 0.0], 1.0, [4, 4, 1]);
+        let ray = Ray::new([-1.0, 0.5, 0.5], [1.0, 0.6, 0.0]);
+
+        let mut visited = Vec::new();
+        let hit = grid.traverse(&ray, 100.0, |index| { visited.push(index); false });
+        assert_eq!(hit, None);
+
+        // The ray enters the grid at (0.0, 1.1) and crosses y = 2 at x = 1.5 and y = 3 at x = 3.17.
+        let expected: Vec<u32> = [[0, 1], [1, 1], [1, 2], [2, 2], [3, 2], [3, 3]].iter()
+            .map(|[x, y]| uvec3_to_index(*x, *y, 0, 4, 4)).collect();
+        assert_eq!(visited, expected);
+
+        // Stop at the first solid voxel.
+        let solid = uvec3_to_index(2, 2, 0, 4, 4);
+        let hit = grid.traverse(&ray, 100.0, |index| index == solid).unwrap();
+        assert_eq!(hit.index, solid);
+        assert_eq!(hit.normal, [-1.0, 0.0, 0.0]);
+    }
+}
@@ -6,8 +6,10 @@ use crate::buffer::buffer_from_data;
 use crate::pipeline_stuff::pipeline_helper::create_render_pipeline_wrapper;
 use crate::pipelines::RenderPipelineWrapper;
 use crate::pipelines::BindGroupMapper;
+use crate::lights::Light;
 use crate::bindgroups::{
     create_uniform_bindgroup_layout,
+    create_dynamic_uniform_bindgroup_layout,
     create_texture,
     create_texture_sampler,
 };
@@ -16,12 +18,24 @@ use crate::pipeline_stuff::pipeline_helper::create_default_depth_stencil_state;
 
 /// Define a basic vvvvnnnn + camera + light + 2 textures render pipeline. TODO: Refactor pipeline creation.
 pub fn default_render_shader_v4n4_camera_light_tex2(device: &wgpu::Device, sc_desc: &wgpu::SurfaceConfiguration) -> RenderPipelineWrapper {
+    v4n4_camera_light_tex2(device, sc_desc, create_uniform_bindgroup_layout(1, wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT))
+}
+
+/// The vvvvnnnn + camera + light + 2 textures render pipeline with a dynamic offset for the light
+/// (group 0, binding 1). The light is bound from a UniformRing with a binding size of at least
+/// size_of::<Light>() and the offset of its slice is given to draw_with_offsets.
+pub fn default_render_shader_v4n4_camera_dynamic_light_tex2(device: &wgpu::Device, sc_desc: &wgpu::SurfaceConfiguration) -> RenderPipelineWrapper {
+    let light_size = std::num::NonZeroU64::new(std::mem::size_of::<Light>() as u64);
+    v4n4_camera_light_tex2(device, sc_desc, create_dynamic_uniform_bindgroup_layout(1, wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT, light_size))
+}
+
+fn v4n4_camera_light_tex2(device: &wgpu::Device, sc_desc: &wgpu::SurfaceConfiguration, light_layout: wgpu::BindGroupLayoutEntry) -> RenderPipelineWrapper {
 
     let vertex_attributes = vec![wgpu::VertexFormat::Float32x4, wgpu::VertexFormat::Float32x4];
       
     let mut bind_group_mapper = BindGroupMapper::init(device);
     bind_group_mapper.insert(device, 0, &create_uniform_bindgroup_layout(0, wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT));
-    bind_group_mapper.insert(device, 0, &create_uniform_bindgroup_layout(1, wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT));
+    bind_group_mapper.insert(device, 0, &light_layout);
     bind_group_mapper.insert(device, 1, &create_texture(0, wgpu::ShaderStages::FRAGMENT));
     bind_group_mapper.insert(device, 1, &create_texture_sampler(1, wgpu::ShaderStages::FRAGMENT));
     bind_group_mapper.insert(device, 1, &create_texture(2, wgpu::ShaderStages::FRAGMENT));
@@ -7,6 +7,7 @@ use crate::pipelines::{
     BindGroupMapper
 };
 use crate::vertex::create_vertex_attributes;
+use crate::texture::{Texture, DepthConvention};
 // use engine::bindgroups::{
 //     create_uniform_bindgroup_layout,
 //     create_texture,
@@ -15,10 +16,15 @@ use crate::vertex::create_vertex_attributes;
 
 /// Creates a default depth stencil state.
 pub fn create_default_depth_stencil_state() -> wgpu::DepthStencilState {
+    create_depth_stencil_state(DepthConvention::Standard)
+}
+
+/// Creates a depth stencil state with the compare function of the depth convention.
+pub fn create_depth_stencil_state(depth_convention: DepthConvention) -> wgpu::DepthStencilState {
     wgpu::DepthStencilState {
-        format: wgpu::TextureFormat::Depth32Float,
+        format: Texture::DEPTH_FORMAT,
         depth_write_enabled: true,
-        depth_compare: wgpu::CompareFunction::Less,
+        depth_compare: depth_convention.compare_function(),
         stencil: wgpu::StencilState {
             front: wgpu::StencilFaceState::IGNORE,
             back: wgpu::StencilFaceState::IGNORE,
@@ -1,6 +1,5 @@
 use bytemuck::{Pod, Zeroable};
 use std::borrow::Cow;
-use crate::impl_convert;
 use crate::pipelines::{BindGroupMapper, ComputePipelineWrapper};
 use crate::bindgroups::{
     create_uniform_bindgroup_layout,
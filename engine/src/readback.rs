@@ -0,0 +1,259 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use wgpu::{BufferAddress, WasmNotSend};
+use crate::error::EngineError;
+
+/// The smallest staging buffer size class in bytes.
+const MIN_STAGING_SIZE: BufferAddress = 256;
+
+/// The default number of free staging buffers kept per size class.
+const DEFAULT_MAX_FREE_PER_CLASS: usize = 4;
+
+type FreeBuffers = Arc<Mutex<HashMap<BufferAddress, Vec<Arc<wgpu::Buffer>>>>>;
+
+/// Copies gpu buffers to the cpu through reusable MAP_READ staging buffers. The staging buffers
+/// are allocated in power of two size classes and returned to the pool when the data has been
+/// read.
+///
+/// The mapping is asynchronous: the callbacks and the futures complete when wgpu processes the
+/// map request. On native this happens in device.poll, so call device.poll(wgpu::Maintain::Poll)
+/// e.g. once per frame when using read_with_callback or read_async. On wasm the browser completes
+/// the mapping and only the asynchronous APIs can be used.
+#[derive(Clone)]
+pub struct ReadbackPool {
+    free: FreeBuffers,
+    max_free_per_class: usize,
+}
+
+impl Default for ReadbackPool {
+    fn default() -> Self {
+        Self::init()
+    }
+}
+
+impl ReadbackPool {
+
+    /// Create an empty pool.
+    pub fn init() -> Self {
+        Self {
+            free: Arc::new(Mutex::new(HashMap::new())),
+            max_free_per_class: DEFAULT_MAX_FREE_PER_CLASS,
+        }
+    }
+
+    /// Set the number of free staging buffers kept per size class. Extra buffers are dropped.
+    pub fn set_max_free_per_class(&mut self, count: usize) {
+        self.max_free_per_class = count;
+    }
+
+    /// The size class of a read of size bytes. The class is capped at max_buffer_size (the
+    /// device limit) but never below size.
+    pub fn size_class(size: BufferAddress, max_buffer_size: BufferAddress) -> BufferAddress {
+        size.max(MIN_STAGING_SIZE).next_power_of_two().min(max_buffer_size).max(size)
+    }
+
+    /// The number of free staging buffers in the pool.
+    pub fn free_count(&self) -> usize {
+        self.free.lock().unwrap().values().map(|v| v.len()).sum()
+    }
+
+    /// Drop all free staging buffers.
+    pub fn clear(&self) {
+        self.free.lock().unwrap().clear();
+    }
+
+    fn acquire(&self, device: &wgpu::Device, size: BufferAddress) -> Arc<wgpu::Buffer> {
+        let class = Self::size_class(size, device.limits().max_buffer_size);
+        if let Some(buffer) = self.free.lock().unwrap().get_mut(&class).and_then(|v| v.pop()) {
+            return buffer;
+        }
+
+        Arc::new(device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback staging buffer"),
+            size: class,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        }))
+    }
+
+    /// Copy size bytes from the buffer starting at offset and call the callback with the data
+    /// once the staging buffer is mapped. The offset and the size must be multiples of
+    /// wgpu::COPY_BUFFER_ALIGNMENT.
+    pub fn read_with_callback(&self,
+                              device: &wgpu::Device,
+                              queue: &wgpu::Queue,
+                              buffer: &wgpu::Buffer,
+                              offset: BufferAddress,
+                              size: BufferAddress,
+                              callback: impl FnOnce(Result<Vec<u8>, EngineError>) + WasmNotSend + 'static) {
+
+        assert!(offset % wgpu::COPY_BUFFER_ALIGNMENT == 0 && size % wgpu::COPY_BUFFER_ALIGNMENT == 0,
+                "Readback offset and size must be multiples of {}.", wgpu::COPY_BUFFER_ALIGNMENT);
+
+        if size == 0 {
+            callback(Ok(Vec::new()));
+            return;
+        }
+
+        let staging = self.acquire(device, size);
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Readback encoder") });
+        encoder.copy_buffer_to_buffer(buffer, offset, &staging, 0, size);
+        queue.submit(Some(encoder.finish()));
+
+        let free = self.free.clone();
+        let max_free = self.max_free_per_class;
+        let mapped = staging.clone();
+
+        staging.slice(0..size).map_async(wgpu::MapMode::Read, move |result| {
+            match result {
+                Ok(()) => {
+                    let data = mapped.slice(0..size).get_mapped_range().to_vec();
+                    mapped.unmap();
+
+                    let mut free = free.lock().unwrap();
+                    let buffers = free.entry(mapped.size()).or_default();
+                    if buffers.len() < max_free { buffers.push(mapped); }
+                    drop(free);
+
+                    callback(Ok(data));
+                }
+                // A buffer that failed to map isn't reused.
+                Err(e) => callback(Err(e.into())),
+            }
+        });
+    }
+
+    /// Copy size bytes from the buffer starting at offset. The future completes when the
+    /// staging buffer is mapped (see ReadbackPool for polling on native).
+    pub fn read_async(&self,
+                      device: &wgpu::Device,
+                      queue: &wgpu::Queue,
+                      buffer: &wgpu::Buffer,
+                      offset: BufferAddress,
+                      size: BufferAddress) -> impl Future<Output = Result<Vec<u8>, EngineError>> {
+
+        let (sender, receiver) = flume::bounded(1);
+        self.read_with_callback(device, queue, buffer, offset, size, move |result| { let _ = sender.send(result); });
+
+        async move {
+            receiver.recv_async().await.unwrap_or(Err(EngineError::BufferMapping(wgpu::BufferAsyncError)))
+        }
+    }
+
+    /// Copy size bytes from the buffer starting at offset and wait for the result. Not
+    /// supported on wasm, where the mapping can't be waited for.
+    pub fn read(&self,
+                device: &wgpu::Device,
+                queue: &wgpu::Queue,
+                buffer: &wgpu::Buffer,
+                offset: BufferAddress,
+                size: BufferAddress) -> Result<Vec<u8>, EngineError> {
+
+        if cfg!(target_arch = "wasm32") {
+            return Err(EngineError::BlockingReadback);
+        }
+
+        let (sender, receiver) = flume::bounded(1);
+        self.read_with_callback(device, queue, buffer, offset, size, move |result| { let _ = sender.send(result); });
+        device.poll(wgpu::Maintain::Wait);
+
+        receiver.recv().unwrap_or(Err(EngineError::BufferMapping(wgpu::BufferAsyncError)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wgpu::util::DeviceExt;
+    use winit::dpi::PhysicalSize;
+    use crate::core::test_context;
+
+    /// Create a device. None (with a skip note) if the system has no adapter.
+    fn device() -> Option<(wgpu::Device, wgpu::Queue)> {
+        test_context(PhysicalSize::new(4, 4)).map(|(context, _)| (context.device, context.queue))
+    }
+
+    fn source(device: &wgpu::Device, words: u32) -> wgpu::Buffer {
+        let data: Vec<u32> = (0..words).collect();
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&data),
+            usage: wgpu::BufferUsages::COPY_SRC,
+        })
+    }
+
+    #[test]
+    fn size_classes() {
+        assert_eq!(ReadbackPool::size_class(4, 1 << 30), MIN_STAGING_SIZE);
+        assert_eq!(ReadbackPool::size_class(257, 1 << 30), 512);
+        assert_eq!(ReadbackPool::size_class(1024, 1 << 30), 1024);
+        // Capped at the maximum buffer size, but never below the requested size.
+        assert_eq!(ReadbackPool::size_class(600, 1000), 1000);
+        assert_eq!(ReadbackPool::size_class(1000, 1000), 1000);
+        assert_eq!(ReadbackPool::size_class(1200, 1000), 1200);
+    }
+
+    #[test]
+    fn staging_buffers_are_reused_per_size_class() {
+        let Some((device, queue)) = device() else { return };
+        let pool = ReadbackPool::init();
+        let buffer = source(&device, 256);
+
+        let data = pool.read(&device, &queue, &buffer, 4, 8).unwrap();
+        assert_eq!(bytemuck::cast_slice::<u8, u32>(&data), &[1, 2]);
+        assert_eq!(pool.free_count(), 1);
+
+        // The same size class reuses the free buffer.
+        pool.read(&device, &queue, &buffer, 0, 200).unwrap();
+        assert_eq!(pool.free_count(), 1);
+
+        // Another size class gets a new buffer.
+        pool.read(&device, &queue, &buffer, 0, 1024).unwrap();
+        assert_eq!(pool.free_count(), 2);
+    }
+
+    #[test]
+    fn async_read_completes_after_polling() {
+        let Some((device, queue)) = device() else { return };
+        let pool = ReadbackPool::init();
+        let buffer = source(&device, 16);
+
+        let future = pool.read_async(&device, &queue, &buffer, 8, 16);
+        device.poll(wgpu::Maintain::Wait);
+        let data = pollster::block_on(future).unwrap();
+        assert_eq!(bytemuck::cast_slice::<u8, u32>(&data), &[2, 3, 4, 5]);
+        assert_eq!(pool.free_count(), 1);
+    }
+
+    #[test]
+    fn free_buffers_are_evicted_past_the_maximum() {
+        let Some((device, queue)) = device() else { return };
+        let mut pool = ReadbackPool::init();
+        pool.set_max_free_per_class(1);
+        let buffer = source(&device, 64);
+
+        // Two reads in flight need two staging buffers, only one is kept.
+        let (sender, receiver) = flume::unbounded();
+        for _ in 0..2 {
+            let sender = sender.clone();
+            pool.read_with_callback(&device, &queue, &buffer, 0, 16, move |r| { sender.send(r.is_ok()).unwrap(); });
+        }
+        device.poll(wgpu::Maintain::Wait);
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec![true, true]);
+        assert_eq!(pool.free_count(), 1);
+
+        pool.clear();
+        assert_eq!(pool.free_count(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "multiples of")]
+    fn unaligned_read_panics() {
+        let Some((device, queue)) = device() else { panic!("multiples of (no adapter)") };
+        let pool = ReadbackPool::init();
+        let buffer = source(&device, 4);
+        let _ = pool.read(&device, &queue, &buffer, 0, 6);
+    }
+}
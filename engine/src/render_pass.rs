@@ -2,7 +2,106 @@ use wgpu::StoreOp;
 use wgpu::Label;
 use crate::texture::Texture as Tex;
 
-/// Create a render pass object. Resolve target not used yet.
+/// A viewport rectangle in pixels and the depth range.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub min_depth: f32,
+    pub max_depth: f32,
+}
+
+impl Viewport {
+
+    /// Create a viewport with the depth range [0, 1].
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Self { x, y, width, height, min_depth: 0.0, max_depth: 1.0 }
+    }
+
+    /// A viewport that covers a relative rectangle [x, y, width, height] (fractions in range
+    /// [0, 1]) of a surface. The rectangle is clamped to the surface. Returns None if the
+    /// clamped viewport is less than one pixel wide or high (e.g. a minimized window).
+    pub fn from_relative(rect: [f32; 4], surface_width: u32, surface_height: u32) -> Option<Self> {
+        let (w, h) = (surface_width as f32, surface_height as f32);
+        Self::new(rect[0] * w, rect[1] * h, rect[2] * w, rect[3] * h).clamp_to(surface_width, surface_height)
+    }
+
+    /// Clamp the viewport to a target of the given size. Returns None if the clamped viewport
+    /// is less than one pixel wide or high.
+    pub fn clamp_to(&self, target_width: u32, target_height: u32) -> Option<Self> {
+        let x0 = self.x.clamp(0.0, target_width as f32);
+        let y0 = self.y.clamp(0.0, target_height as f32);
+        let x1 = (self.x + self.width).clamp(0.0, target_width as f32);
+        let y1 = (self.y + self.height).clamp(0.0, target_height as f32);
+
+        if !(x1 - x0 >= 1.0 && y1 - y0 >= 1.0) { return None; }
+        Some(Self { x: x0, y: y0, width: x1 - x0, height: y1 - y0, ..*self })
+    }
+
+    /// The aspect ratio width / height. 1.0 for an empty viewport.
+    pub fn aspect(&self) -> f32 {
+        if self.width > 0.0 && self.height > 0.0 { self.width / self.height } else { 1.0 }
+    }
+
+    /// Is the position (in pixels) inside the viewport.
+    pub fn contains(&self, position: [f32; 2]) -> bool {
+        position[0] >= self.x && position[0] < self.x + self.width &&
+        position[1] >= self.y && position[1] < self.y + self.height
+    }
+
+    /// The viewport as [x, y, width, height] (see Camera::screen_ray).
+    pub fn to_array(&self) -> [f32; 4] {
+        [self.x, self.y, self.width, self.height]
+    }
+
+    /// The scissor rectangle covering the viewport. The viewport must be inside the render
+    /// target (see Viewport::clamp_to).
+    pub fn scissor(&self) -> ScissorRect {
+        let x = self.x.max(0.0).round() as u32;
+        let y = self.y.max(0.0).round() as u32;
+        ScissorRect {
+            x,
+            y,
+            width: ((self.x + self.width).round() as u32).saturating_sub(x),
+            height: ((self.y + self.height).round() as u32).saturating_sub(y),
+        }
+    }
+}
+
+/// A scissor rectangle in pixels. Must be inside the render target.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScissorRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Set the viewport and the scissor rectangle of the render pass. None keeps the whole target.
+pub fn set_viewport_and_scissor(render_pass: &mut wgpu::RenderPass,
+                                viewport: Option<Viewport>,
+                                scissor: Option<ScissorRect>) {
+    if let Some(v) = viewport {
+        render_pass.set_viewport(v.x, v.y, v.width, v.height, v.min_depth, v.max_depth);
+    }
+    if let Some(s) = scissor {
+        render_pass.set_scissor_rect(s.x, s.y, s.width, s.height);
+    }
+}
+
+/// Clear the whole color target and the depth texture. Call this once per frame before drawing
+/// several viewports, because the draw commands don't clear when a viewport is set.
+pub fn clear_target(encoder: &mut wgpu::CommandEncoder,
+                    view: &wgpu::TextureView,
+                    depth_texture: Option<&Tex>,
+                    clear_color: wgpu::Color) {
+    create_render_pass(encoder, view, depth_texture, true, &Some(clear_color), &None);
+}
+
+/// Create a render pass object. Resolve target not used yet. Clearing clears the whole target
+/// even if a viewport is set later.
 /// TODO: create a RenderPayload struct to pass in?
 pub fn create_render_pass<'a>(encoder: &'a mut wgpu::CommandEncoder,
                           view: &'a wgpu::TextureView,
@@ -10,7 +109,7 @@ pub fn create_render_pass<'a>(encoder: &'a mut wgpu::CommandEncoder,
                           depth_texture: Option<&'a Tex>,
                           clear: bool,
                           clear_color: &Option<wgpu::Color>,
-                          label: &Option<Label>) -> impl wgpu::util::RenderEncoder<'a> {
+                          label: &Option<Label>) -> wgpu::RenderPass<'a> {
 
     encoder.begin_render_pass(
         &wgpu::RenderPassDescriptor {
@@ -36,7 +135,7 @@ pub fn create_render_pass<'a>(encoder: &'a mut wgpu::CommandEncoder,
                 Some(wgpu::RenderPassDepthStencilAttachment {
                     view: depth_texture.as_ref().unwrap().get_view().as_ref().unwrap(),
                     depth_ops: Some(wgpu::Operations {
-                        load: match clear { true => wgpu::LoadOp::Clear(1.0), false => wgpu::LoadOp::Load },
+                        load: match clear { true => wgpu::LoadOp::Clear(depth_texture.unwrap().get_depth_convention().clear_value()), false => wgpu::LoadOp::Load },
                         store: StoreOp::Store,
                     }),
                     stencil_ops: None,
@@ -46,3 +145,23 @@ pub fn create_render_pass<'a>(encoder: &'a mut wgpu::CommandEncoder,
             occlusion_query_set: None,
         })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_viewport_is_clamped_to_the_surface() {
+        let v = Viewport::from_relative([0.5, 0.0, 0.75, 1.0], 800, 600).unwrap();
+        assert_eq!(v.to_array(), [400.0, 0.0, 400.0, 600.0]);
+        assert_eq!(v.scissor(), ScissorRect { x: 400, y: 0, width: 400, height: 600 });
+    }
+
+    #[test]
+    fn empty_viewport_is_rejected() {
+        assert_eq!(Viewport::from_relative([0.0, 0.0, 1.0, 1.0], 800, 0), None);
+        assert_eq!(Viewport::from_relative([0.0, 0.0, 0.0, 1.0], 800, 600), None);
+        assert_eq!(Viewport::from_relative([1.5, 0.0, 0.5, 1.0], 800, 600), None);
+        assert_eq!(Viewport::new(0.0, 0.0, 10.0, 0.0).aspect(), 1.0);
+    }
+}
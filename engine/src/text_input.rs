@@ -0,0 +1,247 @@
+use serde::{Serialize, Deserialize};
+use winit::keyboard::{Key as LogicalKey, NamedKey};
+
+use crate::input_cache::InputCache;
+
+/// An editing key for text input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EditKey {
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Home,
+    End,
+    Enter,
+}
+
+impl EditKey {
+
+    /// Convert a logical key to EditKey. Returns None for other keys.
+    pub fn from_logical_key(key: &LogicalKey) -> Option<Self> {
+        match key {
+            LogicalKey::Named(NamedKey::Backspace) => Some(EditKey::Backspace),
+            LogicalKey::Named(NamedKey::Delete) => Some(EditKey::Delete),
+            LogicalKey::Named(NamedKey::ArrowLeft) => Some(EditKey::Left),
+            LogicalKey::Named(NamedKey::ArrowRight) => Some(EditKey::Right),
+            LogicalKey::Named(NamedKey::Home) => Some(EditKey::Home),
+            LogicalKey::Named(NamedKey::End) => Some(EditKey::End),
+            LogicalKey::Named(NamedKey::Enter) => Some(EditKey::Enter),
+            _ => None,
+        }
+    }
+}
+
+/// A text input event of a frame. The events are in the order they were typed.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TextEvent {
+    /// Typed or IME committed text.
+    Insert(String),
+    /// An editing key was pressed (or repeated).
+    Edit(EditKey),
+}
+
+/// The IME composition that hasn't been committed yet. The cursor is a byte range in text.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Preedit {
+    pub text: String,
+    pub cursor: Option<(usize, usize)>,
+}
+
+/// A single line text buffer with a cursor. Can be used for a console or a text field.
+#[derive(Clone, Debug, Default)]
+pub struct TextBuffer {
+    text: String,
+    /// The cursor position as a byte index. Always on a char boundary.
+    cursor: usize,
+}
+
+impl TextBuffer {
+
+    /// Create an empty text buffer.
+    pub fn init() -> Self {
+        Self {
+            text: String::new(),
+            cursor: 0,
+        }
+    }
+
+    /// Get the text.
+    pub fn get_text(&self) -> &str {
+        &self.text
+    }
+
+    /// Get the cursor position as a byte index.
+    pub fn get_cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Replace the text and move the cursor to the end.
+    pub fn set_text(&mut self, text: &str) {
+        self.text = text.to_string();
+        self.cursor = self.text.len();
+    }
+
+    /// Remove the text.
+    pub fn clear(&mut self) {
+        self.text.clear();
+        self.cursor = 0;
+    }
+
+    /// Apply the text events of the current frame. Returns true if enter was pressed.
+    pub fn update(&mut self, input: &InputCache) -> bool {
+        let mut submit = false;
+        for e in input.get_text_events().iter() {
+            submit |= self.apply(e);
+        }
+        submit
+    }
+
+    /// Apply a single text event. Returns true if the event was enter.
+    pub fn apply(&mut self, event: &TextEvent) -> bool {
+        match event {
+            TextEvent::Insert(s) => {
+                self.text.insert_str(self.cursor, s);
+                self.cursor += s.len();
+            }
+            TextEvent::Edit(EditKey::Backspace) => {
+                if let Some(c) = self.text[..self.cursor].chars().next_back() {
+                    self.cursor -= c.len_utf8();
+                    self.text.remove(self.cursor);
+                }
+            }
+            TextEvent::Edit(EditKey::Delete) => {
+                if self.cursor < self.text.len() {
+                    self.text.remove(self.cursor);
+                }
+            }
+            TextEvent::Edit(EditKey::Left) => {
+                if let Some(c) = self.text[..self.cursor].chars().next_back() {
+                    self.cursor -= c.len_utf8();
+                }
+            }
+            TextEvent::Edit(EditKey::Right) => {
+                if let Some(c) = self.text[self.cursor..].chars().next() {
+                    self.cursor += c.len_utf8();
+                }
+            }
+            TextEvent::Edit(EditKey::Home) => { self.cursor = 0; }
+            TextEvent::Edit(EditKey::End) => { self.cursor = self.text.len(); }
+            TextEvent::Edit(EditKey::Enter) => { return true; }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input_cache::{InputClock, Key};
+    use crate::input_record::InputEvent;
+    use winit::event::{ElementState, Ime, WindowEvent};
+    use winit::keyboard::PhysicalKey;
+
+    fn buffer(text: &str) -> TextBuffer {
+        let mut buffer = TextBuffer::init();
+        buffer.set_text(text);
+        buffer
+    }
+
+    fn edit(buffer: &mut TextBuffer, key: EditKey) {
+        assert!(!buffer.apply(&TextEvent::Edit(key)));
+    }
+
+    #[test]
+    fn insert_moves_the_cursor_over_multibyte_text() {
+        let mut b = buffer("aö");
+        assert_eq!(b.get_cursor(), 3);
+        edit(&mut b, EditKey::Left);
+        assert_eq!(b.get_cursor(), 1);
+        b.apply(&TextEvent::Insert("€😀".to_string()));
+        assert_eq!(b.get_text(), "a€😀ö");
+        assert_eq!(b.get_cursor(), 1 + 3 + 4);
+    }
+
+    #[test]
+    fn left_and_right_step_over_whole_chars() {
+        let mut b = buffer("ä😀x");
+        let mut positions = vec![b.get_cursor()];
+        for _ in 0..4 {
+            edit(&mut b, EditKey::Left);
+            positions.push(b.get_cursor());
+        }
+        assert_eq!(positions, vec![7, 6, 2, 0, 0]);
+
+        for expected in [2, 6, 7, 7] {
+            edit(&mut b, EditKey::Right);
+            assert_eq!(b.get_cursor(), expected);
+        }
+    }
+
+    #[test]
+    fn backspace_and_delete_remove_whole_chars() {
+        let mut b = buffer("ä😀x");
+        edit(&mut b, EditKey::Left);
+        edit(&mut b, EditKey::Backspace);
+        assert_eq!((b.get_text(), b.get_cursor()), ("äx", 2));
+
+        edit(&mut b, EditKey::Home);
+        edit(&mut b, EditKey::Backspace);
+        assert_eq!((b.get_text(), b.get_cursor()), ("äx", 0));
+        edit(&mut b, EditKey::Delete);
+        assert_eq!((b.get_text(), b.get_cursor()), ("x", 0));
+
+        edit(&mut b, EditKey::End);
+        assert_eq!(b.get_cursor(), 1);
+        edit(&mut b, EditKey::Delete);
+        assert_eq!(b.get_text(), "x");
+        edit(&mut b, EditKey::Backspace);
+        assert_eq!((b.get_text(), b.get_cursor()), ("", 0));
+    }
+
+    #[test]
+    fn enter_submits() {
+        let mut b = buffer("ok");
+        assert!(b.apply(&TextEvent::Edit(EditKey::Enter)));
+        assert_eq!(b.get_text(), "ok");
+    }
+
+    #[test]
+    fn key_text_and_ime_reach_the_text_events() {
+        let mut input = InputCache::with_clock(InputClock::Manual(0));
+        input.pre_update_with_time(1);
+
+        let press = |key, logical: LogicalKey, text: Option<&str>| InputEvent::from_key_event(PhysicalKey::Code(key), &logical, text, ElementState::Pressed);
+        let typed = press(Key::KeyA, LogicalKey::Character("ä".into()), Some("ä"));
+        let backspace = press(Key::Backspace, LogicalKey::Named(NamedKey::Backspace), Some("\u{8}"));
+        let control = press(Key::KeyC, LogicalKey::Character("c".into()), Some("\u{3}"));
+        for e in typed.iter().chain(backspace.iter()).chain(control.iter()) {
+            input.push_event(e);
+        }
+
+        input.update(&WindowEvent::Ime(Ime::Preedit("にほ".to_string(), Some((3, 3)))));
+        assert_eq!(input.get_preedit(), Some(&Preedit { text: "にほ".to_string(), cursor: Some((3, 3)) }));
+        input.update(&WindowEvent::Ime(Ime::Preedit(String::new(), None)));
+        input.update(&WindowEvent::Ime(Ime::Commit("日本".to_string())));
+        assert_eq!(input.get_preedit(), None);
+
+        assert_eq!(input.get_text_events(), &[
+            TextEvent::Insert("ä".to_string()),
+            TextEvent::Edit(EditKey::Backspace),
+            TextEvent::Insert("日本".to_string()),
+        ]);
+        assert_eq!(input.get_text(), "ä日本");
+
+        let mut b = TextBuffer::init();
+        assert!(!b.update(&input));
+        assert_eq!(b.get_text(), "日本");
+
+        // The text events are per frame, the composition is kept until it changes.
+        input.update(&WindowEvent::Ime(Ime::Preedit("ご".to_string(), None)));
+        input.pre_update_with_time(2);
+        assert!(input.get_text_events().is_empty());
+        assert_eq!(input.get_preedit().map(|p| p.text.as_str()), Some("ご"));
+        input.update(&WindowEvent::Ime(Ime::Disabled));
+        assert_eq!(input.get_preedit(), None);
+    }
+}
@@ -1,5 +1,33 @@
-use std::num::NonZeroU32;
 use crate::core::WGPUContext;
+use crate::error::EngineError;
+
+/// The depth buffer convention. Reverse-Z maps the near plane to 1.0 and the far plane to 0.0,
+/// which gives better depth precision for far away geometry.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DepthConvention {
+    #[default]
+    Standard,
+    ReverseZ,
+}
+
+impl DepthConvention {
+
+    /// The depth compare function of the convention.
+    pub fn compare_function(&self) -> wgpu::CompareFunction {
+        match self {
+            DepthConvention::Standard => wgpu::CompareFunction::Less,
+            DepthConvention::ReverseZ => wgpu::CompareFunction::Greater,
+        }
+    }
+
+    /// The depth clear value of the convention (the far plane).
+    pub fn clear_value(&self) -> f32 {
+        match self {
+            DepthConvention::Standard => 1.0,
+            DepthConvention::ReverseZ => 0.0,
+        }
+    }
+}
 
 /// Texture.
 #[allow(dead_code)]
@@ -7,6 +35,7 @@ pub struct Texture {
     pub texture: Option<wgpu::Texture>,
     pub view: Option<wgpu::TextureView>,
     pub sampler: Option<wgpu::Sampler>,
+    pub depth_convention: Option<DepthConvention>, // Only for depth textures.
     // DO we need these? TODO: remove if now used.
     // width: u32,
     // height: u32,
@@ -18,13 +47,21 @@ pub struct Texture {
     /// TODO: how to compare function: parameter/struct?
     /// TODO: multisampling: parameter/struct?
     /// TODO: load png files parallel/simd?
-    /// TODO: error handling and validation?
+    /// TODO: validation?
 
 impl Texture {
     pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
 
     /// Create a depth texture. Should we have more parameters for depth texture?
     pub fn create_depth_texture(context: &WGPUContext, sc_desc: &wgpu::SurfaceConfiguration, label: Option<&str>) -> Self {
+        Self::create_depth_texture_with_convention(context, sc_desc, DepthConvention::Standard, label)
+    }
+
+    /// Create a depth texture for the given depth convention. See Camera::get_depth_convention.
+    pub fn create_depth_texture_with_convention(context: &WGPUContext,
+                                                sc_desc: &wgpu::SurfaceConfiguration,
+                                                depth_convention: DepthConvention,
+                                                label: Option<&str>) -> Self {
 
         log::debug!("Creating depth texture");
 
@@ -59,11 +96,11 @@ impl Texture {
             mag_filter: wgpu::FilterMode::Linear,
             min_filter: wgpu::FilterMode::Linear,
             mipmap_filter: wgpu::FilterMode::Nearest,
-            compare: Some(wgpu::CompareFunction::Less),
+            compare: Some(depth_convention.compare_function()),
             ..Default::default()
         });
 
-        Self { texture: Some(texture), view: Some(view), sampler: Some(sampler), } // width, height, depth }
+        Self { texture: Some(texture), view: Some(view), sampler: Some(sampler), depth_convention: Some(depth_convention), } // width, height, depth }
         // Self { texture: Some(texture), view: Some(view), sampler: Some(sampler), width, height, depth }
     }
 
@@ -71,74 +108,251 @@ impl Texture {
         &self.view
     }
 
-    /// Creates a texture from a sequency of bytes (expects bytes to be in png format 'rgb'). Alpha value is set to 255.
-    /// Returns a rgba texture.
-    /// TODO: give alpha value as function parameter.
-    /// TODO: check if aplha value already exists.
-    /// TODO: allow a texture to been created from non png data.
-    /// TODO: sample_count is not used. Do we need it?
-    pub fn create_from_bytes(queue: &wgpu::Queue, device: &wgpu::Device, sc_desc: &wgpu::SurfaceConfiguration, sample_count : u32, bytes: &[u8], label: Option<&str>) -> Self {
+    /// Get the depth convention. Standard for non-depth textures.
+    pub fn get_depth_convention(&self) -> DepthConvention {
+        self.depth_convention.unwrap_or_default()
+    }
 
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::MirrorRepeat,
-            address_mode_v: wgpu::AddressMode::MirrorRepeat,
-            address_mode_w: wgpu::AddressMode::MirrorRepeat,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Linear,
-            mipmap_filter: wgpu::FilterMode::Linear,
-            lod_min_clamp: 0.0,
-            lod_max_clamp: 100.0,
-            compare: None, // Some(wgpu::CompareFunction::Always),
-            ..Default::default()
-        });
+    /// Creates an sRGB Rgba8 texture from png bytes. The surface configuration and the sample
+    /// count are ignored: the texture format doesn't depend on the surface and sampled textures
+    /// aren't multisampled.
+    #[deprecated(note = "use TextureBuilder::new(TextureChannels::Rgba8).build_from_png")]
+    pub fn create_from_bytes(queue: &wgpu::Queue, device: &wgpu::Device, _sc_desc: &wgpu::SurfaceConfiguration, _sample_count : u32, bytes: &[u8], label: Option<&str>) -> Result<Self, EngineError> {
+        let mut builder = TextureBuilder::new(TextureChannels::Rgba8);
+        if let Some(label) = label {
+            builder = builder.label(label);
+        }
+        builder.build_from_png(device, queue, bytes)
+    }
+}
 
-        let png = std::io::Cursor::new(bytes);
-        let decoder = png::Decoder::new(png);
-        //let (info, mut reader) = decoder.read_info().expect("Can't read info!");
-        let mut reader = decoder.read_info().expect("Can't read info!");
-        let info = reader.info();
-        let width = info.width;
-        let height = info.height;
-        let bits_per_pixel = info.color_type.samples() as u32;
-
-        if !(bits_per_pixel == 3 || bits_per_pixel == 4) {
-            panic!("Bits per pixel must be 3 or 4. Bits per pixel == {}", bits_per_pixel);
+/// The channel layout of a texture created by TextureBuilder.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureChannels {
+    /// One 8-bit channel. For grayscale images.
+    R8,
+    /// Two 8-bit channels. For grayscale images with alpha (alpha in the green channel).
+    Rg8,
+    /// Four 8-bit channels.
+    Rgba8,
+    /// Four 16-bit channels. Requires wgpu::Features::TEXTURE_FORMAT_16BIT_NORM.
+    Rgba16,
+}
+
+impl TextureChannels {
+
+    /// The number of channels.
+    pub fn channel_count(&self) -> usize {
+        match self {
+            TextureChannels::R8 => 1,
+            TextureChannels::Rg8 => 2,
+            TextureChannels::Rgba8 | TextureChannels::Rgba16 => 4,
         }
+    }
+
+    /// The number of bytes per texel.
+    pub fn bytes_per_texel(&self) -> usize {
+        match self {
+            TextureChannels::Rgba16 => 8,
+            _ => self.channel_count(),
+        }
+    }
+}
+
+/// The color space of the image data. Color images are usually sRGB encoded, while data such as
+/// normal maps and height maps are linear.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorSpace {
+    #[default]
+    Srgb,
+    Linear,
+}
+
+/// A decoded image in the layout of a texture format.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecodedImage {
+    pub width: u32,
+    pub height: u32,
+    pub format: wgpu::TextureFormat,
+    pub data: Vec<u8>,
+}
+
+/// The wgpu format for the channels and the color space. The 8-bit sRGB data is decoded by the
+/// gpu (Rgba8UnormSrgb). There are no sRGB variants of the other formats: 16-bit sRGB data is
+/// converted to linear when decoding and sRGB R8 and Rg8 textures are not supported.
+pub fn texture_format(channels: TextureChannels, color_space: ColorSpace) -> Result<wgpu::TextureFormat, EngineError> {
+    match (channels, color_space) {
+        (TextureChannels::R8, ColorSpace::Linear) => Ok(wgpu::TextureFormat::R8Unorm),
+        (TextureChannels::Rg8, ColorSpace::Linear) => Ok(wgpu::TextureFormat::Rg8Unorm),
+        (TextureChannels::Rgba8, ColorSpace::Linear) => Ok(wgpu::TextureFormat::Rgba8Unorm),
+        (TextureChannels::Rgba8, ColorSpace::Srgb) => Ok(wgpu::TextureFormat::Rgba8UnormSrgb),
+        (TextureChannels::Rgba16, _) => Ok(wgpu::TextureFormat::Rgba16Unorm),
+        (channels, ColorSpace::Srgb) => Err(EngineError::UnsupportedImage(
+            format!("There is no sRGB format for {:?}. Use ColorSpace::Linear or TextureChannels::Rgba8.", channels))),
+    }
+}
+
+/// Convert a 16-bit sample to 8 bits with rounding.
+fn to_8bit(v: u16) -> u8 {
+    ((v as u32 + 128) / 257) as u8
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+/// Decode a png image into the layout of channels. Palette and low bit depth images are expanded
+/// and a transparency chunk becomes the alpha channel. Grayscale is replicated to the color
+/// channels and missing alpha is opaque. Color images can only be decoded to Rgba8 and Rgba16.
+pub fn decode_png(bytes: &[u8], channels: TextureChannels, color_space: ColorSpace) -> Result<DecodedImage, EngineError> {
+
+    let format = texture_format(channels, color_space)?;
+
+    let mut decoder = png::Decoder::new(std::io::Cursor::new(bytes));
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder.read_info()?;
 
-        let mut buffer: Vec<u8> = vec![0; (info.width * bits_per_pixel * info.height) as usize ];
-        reader.next_frame(&mut buffer).unwrap();
-
-        // TODO: check the size of the image.
-        let mut temp: Vec<u8> = Vec::new();
-
-        // The png has only rgb components. Add the alpha component to each texel.
-        if bits_per_pixel == 3 {
-            for i in 0..buffer.len()/3 {
-                let offset = i*3;
-                let red: u8 = buffer[offset];
-                let green: u8 = buffer[offset+1];
-                let blue: u8 = buffer[offset+2];
-                temp.push(blue); // blue
-                temp.push(green); // green
-                temp.push(red); // red
-                temp.push(255); // alpha
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut buffer)?;
+    buffer.truncate(frame.buffer_size());
+
+    let (color_type, bit_depth) = (frame.color_type, frame.bit_depth);
+    let source_channels = color_type.samples();
+    let has_alpha = matches!(color_type, png::ColorType::GrayscaleAlpha | png::ColorType::Rgba);
+    let is_gray = matches!(color_type, png::ColorType::Grayscale | png::ColorType::GrayscaleAlpha);
+
+    if !is_gray && channels.channel_count() < 4 {
+        return Err(EngineError::UnsupportedImage(format!("Can't decode a {:?} image to {:?}.", color_type, channels)));
+    }
+
+    // Read the samples as 16-bit values (big endian in png).
+    let samples: Vec<u16> = match bit_depth {
+        png::BitDepth::Eight => buffer.iter().map(|&v| v as u16 * 257).collect(),
+        png::BitDepth::Sixteen => buffer.chunks_exact(2).map(|b| u16::from_be_bytes([b[0], b[1]])).collect(),
+        depth => return Err(EngineError::UnsupportedImage(format!("Unexpected bit depth {:?} after expansion.", depth))),
+    };
+
+    let mut data = Vec::with_capacity(frame.width as usize * frame.height as usize * channels.bytes_per_texel());
+
+    for pixel in samples.chunks_exact(source_channels) {
+        let alpha = if has_alpha { pixel[source_channels - 1] } else { u16::MAX };
+        let rgba = if is_gray { [pixel[0], pixel[0], pixel[0], alpha] } else { [pixel[0], pixel[1], pixel[2], alpha] };
+
+        match channels {
+            TextureChannels::R8 => data.push(to_8bit(rgba[0])),
+            TextureChannels::Rg8 => data.extend_from_slice(&[to_8bit(rgba[0]), to_8bit(rgba[3])]),
+            TextureChannels::Rgba8 => data.extend(rgba.iter().map(|&v| to_8bit(v))),
+            TextureChannels::Rgba16 => {
+                for (i, &v) in rgba.iter().enumerate() {
+                    let v = if color_space == ColorSpace::Srgb && i < 3 {
+                        (srgb_to_linear(v as f32 / 65535.0) * 65535.0).round() as u16
+                    }
+                    else { v };
+                    data.extend_from_slice(&v.to_le_bytes());
+                }
             }
         }
+    }
+
+    Ok(DecodedImage { width: frame.width, height: frame.height, format, data })
+}
+
+/// A builder for textures loaded from images.
+///
+/// let texture = TextureBuilder::new(TextureChannels::R8).color_space(ColorSpace::Linear).build_from_png(device, queue, bytes)?;
+#[derive(Clone, Debug)]
+pub struct TextureBuilder<'a> {
+    channels: TextureChannels,
+    color_space: ColorSpace,
+    label: Option<&'a str>,
+    address_mode: wgpu::AddressMode,
+    filter: wgpu::FilterMode,
+    usage: wgpu::TextureUsages,
+}
+
+impl<'a> TextureBuilder<'a> {
+
+    /// Create a builder for an sRGB texture with mirror repeat addressing and linear filtering.
+    /// R8 and Rg8 textures need ColorSpace::Linear.
+    pub fn new(channels: TextureChannels) -> Self {
+        Self {
+            channels,
+            color_space: ColorSpace::Srgb,
+            label: None,
+            address_mode: wgpu::AddressMode::MirrorRepeat,
+            filter: wgpu::FilterMode::Linear,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        }
+    }
+
+    /// Set the color space of the image data.
+    pub fn color_space(mut self, color_space: ColorSpace) -> Self {
+        self.color_space = color_space;
+        self
+    }
+
+    /// Set the label of the texture.
+    pub fn label(mut self, label: &'a str) -> Self {
+        self.label = Some(label);
+        self
+    }
+
+    /// Set the address mode of the sampler for all directions.
+    pub fn address_mode(mut self, address_mode: wgpu::AddressMode) -> Self {
+        self.address_mode = address_mode;
+        self
+    }
+
+    /// Set the filter mode of the sampler.
+    pub fn filter(mut self, filter: wgpu::FilterMode) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Set the texture usages. COPY_DST is always added.
+    pub fn usage(mut self, usage: wgpu::TextureUsages) -> Self {
+        self.usage = usage | wgpu::TextureUsages::COPY_DST;
+        self
+    }
+
+    /// Get the wgpu format of the texture.
+    pub fn get_format(&self) -> Result<wgpu::TextureFormat, EngineError> {
+        texture_format(self.channels, self.color_space)
+    }
+
+    /// Decode the png image and create the texture.
+    pub fn build_from_png(&self, device: &wgpu::Device, queue: &wgpu::Queue, bytes: &[u8]) -> Result<Texture, EngineError> {
+        let image = decode_png(bytes, self.channels, self.color_space)?;
+        self.build_from_image(device, queue, &image)
+    }
+
+    /// Create the texture from a decoded image.
+    pub fn build_from_image(&self, device: &wgpu::Device, queue: &wgpu::Queue, image: &DecodedImage) -> Result<Texture, EngineError> {
+
+        let required = image.format.required_features();
+        if !device.features().contains(required) {
+            return Err(EngineError::MissingFeatures(required - device.features()));
+        }
+
+        let bytes_per_texel = image.format.block_copy_size(None).unwrap_or(0);
+        if image.width == 0 || image.height == 0 || image.data.len() as u64 != image.width as u64 * image.height as u64 * bytes_per_texel as u64 {
+            return Err(EngineError::UnsupportedImage(format!("Invalid {}x{} image of {} bytes.", image.width, image.height, image.data.len())));
+        }
 
         let texture_extent = wgpu::Extent3d {
-            width: width,
-            height: height,
+            width: image.width,
+            height: image.height,
             depth_or_array_layers: 1,
         };
 
         let texture = device.create_texture(&wgpu::TextureDescriptor {
-            label: label,
+            label: self.label,
             size: texture_extent,
             mip_level_count: 1,
-            sample_count: sample_count,
+            sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: sc_desc.format, // wgpu::TextureFormat::Rgba8UnormSrgb,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            format: image.format,
+            usage: self.usage,
             view_formats: &[],
         });
 
@@ -149,42 +363,119 @@ impl Texture {
                 origin: wgpu::Origin3d::ZERO,
                 aspect: wgpu::TextureAspect::All,
             },
-            match bits_per_pixel {
-                3 => &temp,
-                4 => &buffer,
-                _ => panic!("Bits size of {} is not supported", bits_per_pixel),
-            },
+            &image.data,
             wgpu::ImageDataLayout {
                 offset: 0,
-                bytes_per_row: Some(NonZeroU32::new(width * 4).unwrap().into()), // now only 4 bits per pixel is supported,
-                rows_per_image: Some(NonZeroU32::new(height).unwrap().into()),
+                bytes_per_row: Some(image.width * bytes_per_texel),
+                rows_per_image: Some(image.height),
             },
             texture_extent,
         );
 
-        let view = texture.create_view(&wgpu::TextureViewDescriptor {
-            label: None,
-            format: Some(sc_desc.format),
-            dimension: Some(wgpu::TextureViewDimension::D2),
-            aspect: wgpu::TextureAspect::All,
-            base_mip_level: 0,
-            mip_level_count: Some(1), // std::num::NonZeroU32::new(1),
-            base_array_layer: 0,
-            array_layer_count: Some(1), // std::num::NonZeroU32::new(1),
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: self.address_mode,
+            address_mode_v: self.address_mode,
+            address_mode_w: self.address_mode,
+            mag_filter: self.filter,
+            min_filter: self.filter,
+            mipmap_filter: self.filter,
+            lod_min_clamp: 0.0,
+            lod_max_clamp: 100.0,
+            compare: None,
+            ..Default::default()
         });
 
-        let _width = texture_extent.width;
-        let _height = texture_extent.height;
-        let _depth = texture_extent.depth_or_array_layers;
-
-        Self {
-
+        Ok(Texture {
             texture: Some(texture),
             view: Some(view),
             sampler: Some(sampler),
-            // width,
-            // height,
-            // depth,
+            depth_convention: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encode a 2x1 png in memory.
+    fn encode(color_type: png::ColorType, bit_depth: png::BitDepth, palette: Option<(&[u8], &[u8])>, data: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut bytes, 2, 1);
+            encoder.set_color(color_type);
+            encoder.set_depth(bit_depth);
+            if let Some((palette, trns)) = palette {
+                encoder.set_palette(palette.to_vec());
+                encoder.set_trns(trns.to_vec());
+            }
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(data).unwrap();
         }
+        bytes
+    }
+
+    #[test]
+    fn gray_is_replicated_and_opaque() {
+        let bytes = encode(png::ColorType::Grayscale, png::BitDepth::Eight, None, &[10, 200]);
+
+        let image = decode_png(&bytes, TextureChannels::Rgba8, ColorSpace::Srgb).unwrap();
+        assert_eq!((image.width, image.height, image.format), (2, 1, wgpu::TextureFormat::Rgba8UnormSrgb));
+        assert_eq!(image.data, vec![10, 10, 10, 255, 200, 200, 200, 255]);
+
+        let image = decode_png(&bytes, TextureChannels::R8, ColorSpace::Linear).unwrap();
+        assert_eq!(image.data, vec![10, 200]);
+    }
+
+    #[test]
+    fn gray_alpha_goes_to_the_alpha_channel() {
+        let bytes = encode(png::ColorType::GrayscaleAlpha, png::BitDepth::Eight, None, &[10, 20, 200, 0]);
+
+        let image = decode_png(&bytes, TextureChannels::Rg8, ColorSpace::Linear).unwrap();
+        assert_eq!(image.data, vec![10, 20, 200, 0]);
+
+        let image = decode_png(&bytes, TextureChannels::Rgba8, ColorSpace::Linear).unwrap();
+        assert_eq!(image.data, vec![10, 10, 10, 20, 200, 200, 200, 0]);
+    }
+
+    #[test]
+    fn palette_with_transparency_is_expanded() {
+        let palette = [255, 0, 0, 0, 0, 255];
+        let bytes = encode(png::ColorType::Indexed, png::BitDepth::Eight, Some((&palette, &[128])), &[0, 1]);
+
+        let image = decode_png(&bytes, TextureChannels::Rgba8, ColorSpace::Srgb).unwrap();
+        assert_eq!(image.data, vec![255, 0, 0, 128, 0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn sixteen_bit_is_rounded_to_eight_bits() {
+        let data = [0x12, 0x34, 0x00, 0x00, 0xff, 0xff, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00];
+        let bytes = encode(png::ColorType::Rgb, png::BitDepth::Sixteen, None, &data);
+
+        let image = decode_png(&bytes, TextureChannels::Rgba8, ColorSpace::Srgb).unwrap();
+        assert_eq!(image.data, vec![0x12, 0, 255, 255, 128, 0, 0, 255]);
+    }
+
+    #[test]
+    fn rgba16_srgb_is_converted_to_linear() {
+        let data = [0x80, 0x00, 0x80, 0x00, 0x80, 0x00, 0x80, 0x00, 0xff, 0xff, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff];
+        let bytes = encode(png::ColorType::Rgba, png::BitDepth::Sixteen, None, &data);
+
+        let image = decode_png(&bytes, TextureChannels::Rgba16, ColorSpace::Srgb).unwrap();
+        assert_eq!(image.format, wgpu::TextureFormat::Rgba16Unorm);
+        // Color 0x8000 is about 0.214 in linear space, alpha stays 0x8000. Little endian.
+        assert_eq!(&image.data[..8], &[204, 54, 204, 54, 204, 54, 0x00, 0x80]);
+        assert_eq!(&image.data[8..], &[0xff, 0xff, 0, 0, 0xff, 0xff, 0xff, 0xff]);
+
+        let image = decode_png(&bytes, TextureChannels::Rgba16, ColorSpace::Linear).unwrap();
+        assert_eq!(&image.data[..8], &[0x00, 0x80, 0x00, 0x80, 0x00, 0x80, 0x00, 0x80]);
+    }
+
+    #[test]
+    fn color_to_gray_channels_is_rejected() {
+        let bytes = encode(png::ColorType::Rgb, png::BitDepth::Eight, None, &[1, 2, 3, 4, 5, 6]);
+        assert!(matches!(decode_png(&bytes, TextureChannels::R8, ColorSpace::Linear), Err(EngineError::UnsupportedImage(_))));
+        assert!(matches!(decode_png(&bytes, TextureChannels::R8, ColorSpace::Srgb), Err(EngineError::UnsupportedImage(_))));
     }
 }
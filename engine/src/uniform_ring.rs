@@ -0,0 +1,331 @@
+use std::num::NonZeroU64;
+use bytemuck::Pod;
+use wgpu::BufferAddress;
+use crate::error::EngineError;
+
+/// The default number of frames in flight.
+pub const DEFAULT_FRAMES_IN_FLIGHT: usize = 2;
+
+/// Round offset up to the next multiple of alignment. The alignment must be a power of two.
+pub fn align_offset(offset: BufferAddress, alignment: BufferAddress) -> BufferAddress {
+    debug_assert!(alignment.is_power_of_two());
+    (offset + alignment - 1) & !(alignment - 1)
+}
+
+/// A slice of the uniform ring. The offset is used as the dynamic offset when the bind group is
+/// set.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UniformSlice {
+    pub offset: u32,
+    pub size: BufferAddress,
+}
+
+/// The cpu side allocation of the slices of one frame. Every slice reserves at least
+/// binding_size bytes, because the binding of the slice covers binding_size bytes from its offset.
+#[derive(Clone, Debug)]
+struct FrameAllocator {
+    data: Vec<u8>,
+    alignment: BufferAddress,
+    capacity: BufferAddress,
+    binding_size: BufferAddress,
+}
+
+impl FrameAllocator {
+
+    fn init(alignment: BufferAddress, capacity: BufferAddress, binding_size: BufferAddress) -> Self {
+        Self {
+            data: Vec::with_capacity(capacity as usize),
+            alignment,
+            capacity,
+            binding_size,
+        }
+    }
+
+    fn used(&self) -> BufferAddress {
+        self.data.len() as BufferAddress
+    }
+
+    fn clear(&mut self) {
+        self.data.clear();
+    }
+
+    fn push_bytes(&mut self, bytes: &[u8]) -> Result<UniformSlice, EngineError> {
+
+        let offset = align_offset(self.used(), self.alignment);
+        let size = bytes.len() as BufferAddress;
+
+        if size > self.binding_size {
+            return Err(EngineError::UniformTooLarge { size: size, binding_size: self.binding_size });
+        }
+        if offset + self.binding_size > self.capacity {
+            return Err(EngineError::UniformRingFull { requested: size, used: offset, capacity: self.capacity });
+        }
+
+        self.data.resize(offset as usize, 0);
+        self.data.extend_from_slice(bytes);
+
+        // Writes must be a multiple of wgpu::COPY_BUFFER_ALIGNMENT.
+        self.data.resize(align_offset(self.used(), wgpu::COPY_BUFFER_ALIGNMENT) as usize, 0);
+
+        Ok(UniformSlice { offset: offset as u32, size })
+    }
+}
+
+/// Sub-allocates uniform data of one frame from a single large buffer. There is one buffer per
+/// frame in flight so that the data of the previous frames isn't overwritten while the gpu may
+/// still use it.
+///
+/// Usage per frame: begin_frame, push the parameters of each draw, flush and bind the current
+/// bind group with the offsets of the slices. The bind group layout entry must be created with
+/// create_dynamic_uniform_bindgroup_layout.
+pub struct UniformRing {
+    buffers: Vec<wgpu::Buffer>,
+    allocator: FrameAllocator,
+    frame: usize,
+}
+
+impl UniformRing {
+
+    /// Create a ring with frame_capacity bytes for each of the frames_in_flight frames. The
+    /// slices are aligned by the min_uniform_buffer_offset_alignment limit of the device. The
+    /// binding_size is the size of the largest struct bound through the ring. Returns an error if
+    /// the capacity or the number of frames is 0, or the binding size isn't in range
+    /// (0, min(frame capacity, max_uniform_buffer_binding_size)].
+    pub fn init(device: &wgpu::Device, frame_capacity: BufferAddress, frames_in_flight: usize, binding_size: BufferAddress, label: Option<&str>) -> Result<Self, EngineError> {
+
+        if frame_capacity == 0 || frames_in_flight == 0 {
+            return Err(EngineError::InvalidUniformRing(format!(
+                "Frame capacity and frames in flight must be > 0. Frame capacity == {}, frames in flight == {}", frame_capacity, frames_in_flight)));
+        }
+
+        let limits = device.limits();
+        let alignment = limits.min_uniform_buffer_offset_alignment as BufferAddress;
+        let frame_capacity = align_offset(frame_capacity, alignment);
+        let max_binding_size = frame_capacity.min(limits.max_uniform_buffer_binding_size as BufferAddress);
+
+        if binding_size == 0 || binding_size > max_binding_size {
+            return Err(EngineError::InvalidUniformRing(format!(
+                "Binding size must be in range (0, {}]. Binding size == {}", max_binding_size, binding_size)));
+        }
+
+        let buffers = (0..frames_in_flight).map(|_| device.create_buffer(&wgpu::BufferDescriptor {
+            label: label,
+            size: frame_capacity,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })).collect();
+
+        Ok(Self {
+            buffers,
+            allocator: FrameAllocator::init(alignment, frame_capacity, binding_size),
+            frame: 0,
+        })
+    }
+
+    /// Get the offset alignment of the slices.
+    pub fn get_alignment(&self) -> BufferAddress {
+        self.allocator.alignment
+    }
+
+    /// Get the size of the bindings.
+    pub fn get_binding_size(&self) -> BufferAddress {
+        self.allocator.binding_size
+    }
+
+    /// Get the capacity of one frame in bytes.
+    pub fn get_frame_capacity(&self) -> BufferAddress {
+        self.allocator.capacity
+    }
+
+    /// Get the number of frames in flight.
+    pub fn get_frames_in_flight(&self) -> usize {
+        self.buffers.len()
+    }
+
+    /// Get the index of the current frame.
+    pub fn get_frame(&self) -> usize {
+        self.frame
+    }
+
+    /// Get the number of bytes used in the current frame.
+    pub fn get_used(&self) -> BufferAddress {
+        self.allocator.used()
+    }
+
+    /// Get the buffer of a frame.
+    pub fn get_buffer(&self, frame: usize) -> &wgpu::Buffer {
+        &self.buffers[frame]
+    }
+
+    /// Get the buffer of the current frame.
+    pub fn get_current_buffer(&self) -> &wgpu::Buffer {
+        &self.buffers[self.frame]
+    }
+
+    /// Move to the next frame buffer and discard the slices of the previous frame.
+    pub fn begin_frame(&mut self) {
+        self.frame = (self.frame + 1) % self.buffers.len();
+        self.allocator.clear();
+    }
+
+    /// Push the value to the current frame. The data is uploaded in flush.
+    pub fn push<T: Pod>(&mut self, value: &T) -> Result<UniformSlice, EngineError> {
+        self.push_bytes(bytemuck::bytes_of(value))
+    }
+
+    /// Push bytes to the current frame. The data is uploaded in flush. The slice reserves at
+    /// least binding size bytes.
+    pub fn push_bytes(&mut self, bytes: &[u8]) -> Result<UniformSlice, EngineError> {
+        self.allocator.push_bytes(bytes)
+    }
+
+    /// Upload the slices of the current frame with a single write.
+    pub fn flush(&self, queue: &wgpu::Queue) {
+        if self.allocator.data.is_empty() { return; }
+        queue.write_buffer(self.get_current_buffer(), 0, &self.allocator.data);
+    }
+
+    /// Get a binding of binding size bytes to the buffer of a frame.
+    pub fn get_binding(&self, frame: usize) -> wgpu::BindingResource<'_> {
+        wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+            buffer: &self.buffers[frame],
+            offset: 0,
+            size: NonZeroU64::new(self.allocator.binding_size),
+        })
+    }
+
+    /// Create one bind group per frame in flight. The resources of the bind group are created
+    /// by the closure from the uniform binding of each frame.
+    pub fn create_bind_groups<F>(&self, mut create: F) -> Vec<wgpu::BindGroup>
+        where F: FnMut(wgpu::BindingResource) -> wgpu::BindGroup {
+
+        (0..self.buffers.len()).map(|frame| create(self.get_binding(frame))).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem::size_of;
+    use winit::dpi::PhysicalSize;
+    use crate::camera::Camera;
+    use crate::core::test_context;
+    use crate::draw_commands::draw_with_offsets;
+    use crate::lights::Light;
+    use crate::meshes::create_cube;
+    use crate::pipeline_stuff::custom_pipelines::default_render_shader_v4n4_camera_dynamic_light_tex2;
+    use crate::texture::{Texture, TextureBuilder, TextureChannels};
+
+    #[test]
+    fn offsets_are_aligned_up() {
+        assert_eq!(align_offset(0, 256), 0);
+        assert_eq!(align_offset(1, 256), 256);
+        assert_eq!(align_offset(256, 256), 256);
+        assert_eq!(align_offset(257, 256), 512);
+        assert_eq!(align_offset(5, 4), 8);
+    }
+
+    #[test]
+    fn slices_are_aligned_and_padded() {
+        let mut allocator = FrameAllocator::init(256, 1024, 64);
+
+        assert_eq!(allocator.push_bytes(&[1; 6]).unwrap(), UniformSlice { offset: 0, size: 6 });
+        assert_eq!(allocator.used(), 8);
+        assert_eq!(allocator.push_bytes(&[2; 64]).unwrap(), UniformSlice { offset: 256, size: 64 });
+        assert_eq!(allocator.used(), 320);
+        assert_eq!(&allocator.data[..8], &[1, 1, 1, 1, 1, 1, 0, 0]);
+        assert!(allocator.data[8..256].iter().all(|b| *b == 0));
+
+        allocator.clear();
+        assert_eq!(allocator.push_bytes(&[3; 4]).unwrap().offset, 0);
+    }
+
+    #[test]
+    fn the_last_slice_reserves_the_binding_size() {
+        // The binding of the slice at 768 would end at 768 + 512 > 1024.
+        let mut allocator = FrameAllocator::init(256, 1024, 512);
+        for offset in [0, 256, 512] {
+            assert_eq!(allocator.push_bytes(&[0; 16]).unwrap().offset, offset);
+        }
+        assert!(matches!(allocator.push_bytes(&[0; 16]),
+                         Err(EngineError::UniformRingFull { requested: 16, used: 768, capacity: 1024 })));
+    }
+
+    #[test]
+    fn invalid_rings_are_rejected() {
+        let Some((context, _)) = test_context(PhysicalSize::new(4, 4)) else { return };
+        let device = &context.device;
+        let max_binding_size = device.limits().max_uniform_buffer_binding_size as BufferAddress;
+
+        for (frame_capacity, frames_in_flight, binding_size) in [(0, 2, 64), (1024, 0, 64), (1024, 2, 0), (1024, 2, 2048), (2 * max_binding_size, 2, max_binding_size + 4)] {
+            assert!(matches!(UniformRing::init(device, frame_capacity, frames_in_flight, binding_size, None),
+                             Err(EngineError::InvalidUniformRing(_))));
+        }
+
+        // The frame capacity is rounded up to the alignment.
+        let ring = UniformRing::init(device, 100, 3, 100, None).unwrap();
+        assert_eq!(ring.get_frame_capacity(), align_offset(100, ring.get_alignment()));
+        assert_eq!(ring.get_frames_in_flight(), 3);
+    }
+
+    #[test]
+    fn slices_are_drawn_with_dynamic_offsets() {
+        let Some((context, surface)) = test_context(PhysicalSize::new(16, 16)) else { return };
+        let (device, queue) = (&context.device, &context.queue);
+
+        let pipeline = default_render_shader_v4n4_camera_dynamic_light_tex2(device, surface.config());
+        let mut camera = Camera::new(16.0, 16.0, (15.0, 12.0, -18.0), (0.0, 0.0, 0.0));
+        let texture = TextureBuilder::new(TextureChannels::Rgba8)
+            .build_from_png(device, queue, &include_bytes!("../../textures/rock.png")[..]).unwrap();
+        let (view, sampler) = (texture.view.as_ref().unwrap(), texture.sampler.as_ref().unwrap());
+        let mut ring = UniformRing::init(device, 1024, DEFAULT_FRAMES_IN_FLIGHT, size_of::<Light>() as BufferAddress, None).unwrap();
+
+        let camera_uniform = camera.get_camera_uniform(device);
+        let bind_groups = ring.create_bind_groups(|light| pipeline.create_bind_group(device, &vec![&camera_uniform.as_entire_binding(), &light], 0));
+        let texture_bind_group = pipeline.create_bind_group(device, &vec![
+            &wgpu::BindingResource::TextureView(view), &wgpu::BindingResource::Sampler(sampler),
+            &wgpu::BindingResource::TextureView(view), &wgpu::BindingResource::Sampler(sampler)], 1);
+
+        let target = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d { width: 16, height: 16, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: surface.config().format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let target_view = target.create_view(&wgpu::TextureViewDescriptor::default());
+        let depth_texture = Texture::create_depth_texture(&context, surface.config(), None);
+        let cube = create_cube(device, 4.0, false);
+
+        // Two frames with two lights each, every draw bound at the offset of its own slice.
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        for _ in 0..2 {
+            ring.begin_frame();
+            let slices: Vec<UniformSlice> = [[10.0, 0.0, 0.0], [0.0, 10.0, 0.0]].iter()
+                .map(|p| ring.push(&Light::init(*p, [255; 3], [255; 3], 10.0, 0.5, 0.01)).unwrap()).collect();
+            assert_eq!(slices[1].offset as BufferAddress, ring.get_alignment().max(size_of::<Light>() as BufferAddress));
+            ring.flush(queue);
+
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+            for (i, slice) in slices.iter().enumerate() {
+                draw_with_offsets(&mut encoder, &target_view, Some(&depth_texture),
+                                  &vec![&bind_groups[ring.get_frame()], &texture_bind_group], &[&[slice.offset]],
+                                  pipeline.get_pipeline(), &cube, 0..36, &Some(wgpu::Color::BLACK), i == 0, None, None);
+            }
+            queue.submit(Some(encoder.finish()));
+        }
+        device.poll(wgpu::Maintain::Wait);
+        let error = pollster::block_on(device.pop_error_scope());
+        assert!(error.is_none(), "{:?}", error);
+    }
+
+    #[test]
+    fn slice_larger_than_the_binding_is_rejected() {
+        let mut allocator = FrameAllocator::init(256, 1024, 64);
+        assert!(matches!(allocator.push_bytes(&[0; 68]), Err(EngineError::UniformTooLarge { size: 68, binding_size: 64 })));
+        assert_eq!(allocator.used(), 0);
+    }
+}
@@ -0,0 +1,341 @@
+use crate::error::EngineError;
+
+/// The address space of a WGSL struct. The uniform address space follows the std140 like rules
+/// (the alignment and the size of nested structs and the array strides rounded up to 16) and the
+/// storage address space the std430 like rules. The top-level struct itself isn't rounded up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressSpace {
+    Uniform,
+    Storage,
+}
+
+/// The offset and size of a WGSL struct member in bytes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MemberLayout {
+    pub name: String,
+    pub offset: usize,
+    pub size: usize,
+}
+
+/// The memory layout of a WGSL struct.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StructLayout {
+    pub name: String,
+    pub size: usize,
+    pub align: usize,
+    pub members: Vec<MemberLayout>,
+}
+
+fn round_up(k: usize, n: usize) -> usize {
+    (n + k - 1) / k * k
+}
+
+fn layout_error(message: String) -> EngineError {
+    EngineError::WgslLayout(message)
+}
+
+/// Remove the line and block comments.
+fn strip_comments(source: &str) -> String {
+    let mut result = String::with_capacity(source.len());
+    let mut rest = source;
+
+    while !rest.is_empty() {
+        if rest.starts_with("//") {
+            rest = rest.find('\n').map_or("", |i| &rest[i..]);
+        }
+        else if rest.starts_with("/*") {
+            rest = rest[2..].find("*/").map_or("", |i| &rest[i + 4..]);
+            result.push(' ');
+        }
+        else {
+            let c = rest.chars().next().unwrap();
+            result.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    result
+}
+
+/// Split at the commas that are not inside angle brackets or parentheses.
+fn split_top_level(s: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0i32;
+    let mut start = 0;
+
+    for (i, c) in s.char_indices() {
+        match c {
+            '<' | '(' => depth += 1,
+            '>' | ')' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+    parts.into_iter().map(|p| p.trim()).filter(|p| !p.is_empty()).collect()
+}
+
+/// Find the body of the struct.
+fn find_struct<'a>(source: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = source;
+    while let Some(i) = rest.find("struct") {
+        let after = rest[i + "struct".len()..].trim_start();
+        let is_keyword = i == 0 || !rest[..i].ends_with(|c: char| c.is_alphanumeric() || c == '_');
+        if is_keyword && after.starts_with(name) && after[name.len()..].trim_start().starts_with('{') {
+            let body = &after[after.find('{').unwrap() + 1..];
+            return body.find('}').map(|end| &body[..end]);
+        }
+        rest = &rest[i + "struct".len()..];
+    }
+    None
+}
+
+/// Split a generic type e.g. vec3<f32> into ("vec3", ["f32"]).
+fn split_generic(ty: &str) -> (&str, Vec<&str>) {
+    match ty.find('<') {
+        Some(i) if ty.ends_with('>') => (ty[..i].trim(), split_top_level(&ty[i + 1..ty.len() - 1])),
+        _ => (ty, Vec::new()),
+    }
+}
+
+/// Get the (size, align) of a type.
+fn type_layout(source: &str, ty: &str, space: AddressSpace, depth: usize) -> Result<(usize, usize), EngineError> {
+
+    if depth > 32 { return Err(layout_error(format!("Type {} is nested too deep.", ty))); }
+
+    let (base, params) = split_generic(ty.trim());
+
+    // The predeclared aliases e.g. vec3f and mat4x4f.
+    let (base, alias_scalar) = match base.strip_suffix(|c| c == 'f' || c == 'i' || c == 'u' || c == 'h') {
+        Some(b) if (b.starts_with("vec") || b.starts_with("mat")) && params.is_empty() => {
+            (b, Some(if base.ends_with('h') { "f16" } else if base.ends_with('f') { "f32" } else { "u32" }))
+        }
+        _ => (base, None),
+    };
+    let scalar = |index: usize| -> Result<(usize, usize), EngineError> {
+        let s = alias_scalar.or(params.get(index).copied()).ok_or_else(|| layout_error(format!("Missing type parameter in {}.", ty)))?;
+        type_layout(source, s, space, depth + 1)
+    };
+
+    match base {
+        "f32" | "i32" | "u32" => Ok((4, 4)),
+        "f16" => Ok((2, 2)),
+        "bool" => Err(layout_error("bool isn't host shareable.".to_string())),
+        "atomic" => scalar(0),
+        "vec2" | "vec3" | "vec4" => {
+            let n = (base.as_bytes()[3] - b'0') as usize;
+            let (s, _) = scalar(0)?;
+            Ok((n * s, if n == 3 { 4 * s } else { n * s }))
+        }
+        b if b.starts_with("mat") && b.len() == 6 && b.as_bytes()[4] == b'x' => {
+            let columns = (b.as_bytes()[3] - b'0') as usize;
+            let rows = (b.as_bytes()[5] - b'0') as usize;
+            let (s, _) = scalar(0)?;
+            let align = if rows == 3 { 4 * s } else { rows * s };
+            Ok((columns * round_up(align, rows * s), align))
+        }
+        "array" => {
+            let element = params.first().ok_or_else(|| layout_error(format!("Missing element type in {}.", ty)))?;
+            let (size, align) = type_layout(source, element, space, depth + 1)?;
+            let (stride, align) = match space {
+                AddressSpace::Uniform => (round_up(16, round_up(align, size)), round_up(16, align)),
+                AddressSpace::Storage => (round_up(align, size), align),
+            };
+            // A runtime sized array has no fixed size.
+            let count = match params.get(1) {
+                Some(n) => n.trim_end_matches(|c| c == 'u' || c == 'i').parse::<usize>()
+                    .map_err(|_| layout_error(format!("Unsupported array length in {}.", ty)))?,
+                None => 0,
+            };
+            Ok((count * stride, align))
+        }
+        name => {
+            let layout = struct_layout_recursive(source, name, space, depth + 1)?;
+            match space {
+                // A nested struct is aligned to 16 and the next member starts at least
+                // round_up(16, size) bytes after it.
+                AddressSpace::Uniform => Ok((round_up(16, layout.size), round_up(16, layout.align))),
+                AddressSpace::Storage => Ok((layout.size, layout.align)),
+            }
+        }
+    }
+}
+
+fn struct_layout_recursive(source: &str, name: &str, space: AddressSpace, depth: usize) -> Result<StructLayout, EngineError> {
+
+    let body = find_struct(source, name).ok_or_else(|| layout_error(format!("Struct {} not found.", name)))?;
+
+    let mut members = Vec::new();
+    let mut end = 0;
+    let mut struct_align = 1;
+
+    for member in split_top_level(body) {
+        // Parse the @align(n) and @size(n) attributes.
+        let mut declaration = member;
+        let mut align_attribute = None;
+        let mut size_attribute = None;
+        while let Some(rest) = declaration.strip_prefix('@') {
+            let open = rest.find('(').ok_or_else(|| layout_error(format!("Unsupported attribute in {}.", member)))?;
+            let close = rest.find(')').ok_or_else(|| layout_error(format!("Unsupported attribute in {}.", member)))?;
+            let value = rest[open + 1..close].trim().trim_end_matches(|c| c == 'u' || c == 'i').parse::<usize>().ok();
+            match rest[..open].trim() {
+                "align" => align_attribute = value,
+                "size" => size_attribute = value,
+                _ => {}
+            }
+            declaration = rest[close + 1..].trim_start();
+        }
+
+        let (member_name, ty) = declaration.split_once(':')
+            .ok_or_else(|| layout_error(format!("Invalid member {} in struct {}.", member, name)))?;
+        let (size, align) = type_layout(source, ty, space, depth)?;
+        let size = size_attribute.unwrap_or(size);
+        let align = align_attribute.unwrap_or(align);
+
+        let offset = round_up(align, end);
+        end = offset + size;
+        struct_align = struct_align.max(align);
+        members.push(MemberLayout { name: member_name.trim().to_string(), offset, size });
+    }
+
+    Ok(StructLayout {
+        name: name.to_string(),
+        size: round_up(struct_align, end),
+        align: struct_align,
+        members,
+    })
+}
+
+/// Compute the layout of a struct declared in the WGSL source.
+pub fn struct_layout(source: &str, name: &str, space: AddressSpace) -> Result<StructLayout, EngineError> {
+    struct_layout_recursive(&strip_comments(source), name, space, 0)
+}
+
+/// Check that a rust struct matches the WGSL struct of the source. The size must be equal and
+/// the offsets are compared with the WGSL members in order (a rust struct may have additional
+/// padding fields at the end). See check_wgsl_layout.
+pub fn check_layout(source: &str, name: &str, space: AddressSpace, size: usize, offsets: &[(&str, usize)]) -> Result<(), EngineError> {
+
+    let layout = struct_layout(source, name, space)?;
+
+    if offsets.len() > layout.members.len() {
+        return Err(layout_error(format!("{} has {} fields but the WGSL struct has {} members.", name, offsets.len(), layout.members.len())));
+    }
+
+    for ((field, offset), member) in offsets.iter().zip(layout.members.iter()) {
+        if *offset != member.offset {
+            return Err(layout_error(format!("{}.{} is at offset {} but the WGSL member {} is at offset {}.",
+                                            name, field, offset, member.name, member.offset)));
+        }
+    }
+
+    if size != layout.size {
+        return Err(layout_error(format!("{} is {} bytes but the WGSL struct is {} bytes.", name, size, layout.size)));
+    }
+
+    Ok(())
+}
+
+/// Check the layout of a rust struct against the WGSL struct of the same name. The fields are
+/// listed in the order of the WGSL members.
+///
+/// check_wgsl_layout!(McParams, include_str!("mc.wgsl"), AddressSpace::Uniform, base_position, isovalue)
+#[macro_export]
+macro_rules! check_wgsl_layout {
+    ($ty:ident, $source:expr, $space:expr, $($field:ident),* $(,)?) => {
+        $crate::wgsl_layout::check_layout(
+            $source,
+            stringify!($ty),
+            $space,
+            std::mem::size_of::<$ty>(),
+            &[$((stringify!($field), std::mem::offset_of!($ty, $field))),*])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn offsets(layout: &StructLayout) -> Vec<(String, usize, usize)> {
+        layout.members.iter().map(|m| (m.name.clone(), m.offset, m.size)).collect()
+    }
+
+    fn member(name: &str, offset: usize, size: usize) -> (String, usize, usize) {
+        (name.to_string(), offset, size)
+    }
+
+    #[test]
+    fn vec3_is_aligned_to_16() {
+        let source = "struct A { a: f32, b: vec3<f32>, c: f32, d: vec2f }";
+        let layout = struct_layout(source, "A", AddressSpace::Storage).unwrap();
+        assert_eq!(offsets(&layout), vec![member("a", 0, 4), member("b", 16, 12), member("c", 28, 4), member("d", 32, 8)]);
+        assert_eq!((layout.size, layout.align), (48, 16));
+    }
+
+    #[test]
+    fn matrix_columns_are_aligned() {
+        let source = "struct M { a: mat3x3<f32>, b: mat4x2f, c: mat2x3<f32> }";
+        let layout = struct_layout(source, "M", AddressSpace::Storage).unwrap();
+        assert_eq!(offsets(&layout), vec![member("a", 0, 48), member("b", 48, 32), member("c", 80, 32)]);
+        assert_eq!(layout.size, 112);
+    }
+
+    #[test]
+    fn uniform_array_stride_is_rounded_to_16() {
+        let source = "struct A { a: array<f32, 4>, b: u32 }";
+        let uniform = struct_layout(source, "A", AddressSpace::Uniform).unwrap();
+        assert_eq!(offsets(&uniform), vec![member("a", 0, 64), member("b", 64, 4)]);
+        assert_eq!(uniform.size, 80);
+
+        let storage = struct_layout(source, "A", AddressSpace::Storage).unwrap();
+        assert_eq!(offsets(&storage), vec![member("a", 0, 16), member("b", 16, 4)]);
+        assert_eq!(storage.size, 20);
+    }
+
+    #[test]
+    fn uniform_rounding_applies_only_to_nested_structs() {
+        let source = "struct Inner { x: f32 } struct Outer { a: Inner, b: f32 } struct Params { scale_factor: f32 }";
+
+        let params = struct_layout(source, "Params", AddressSpace::Uniform).unwrap();
+        assert_eq!((params.size, params.align), (4, 4));
+
+        let outer = struct_layout(source, "Outer", AddressSpace::Uniform).unwrap();
+        assert_eq!(offsets(&outer), vec![member("a", 0, 16), member("b", 16, 4)]);
+        assert_eq!((outer.size, outer.align), (32, 16));
+
+        let outer = struct_layout(source, "Outer", AddressSpace::Storage).unwrap();
+        assert_eq!(offsets(&outer), vec![member("a", 0, 4), member("b", 4, 4)]);
+        assert_eq!(outer.size, 8);
+    }
+
+    #[test]
+    fn align_and_size_attributes() {
+        let source = "struct A { @align(16) a: f32, @size(8) b: u32, @align(8u) @size(12) c: f32, d: f32 }";
+        let layout = struct_layout(source, "A", AddressSpace::Storage).unwrap();
+        assert_eq!(offsets(&layout), vec![member("a", 0, 4), member("b", 4, 8), member("c", 16, 12), member("d", 28, 4)]);
+        assert_eq!((layout.size, layout.align), (32, 16));
+    }
+
+    #[test]
+    fn comments_are_ignored() {
+        let source = "// struct A { a: vec4<f32> }\n
+                      /* struct A { b: f32 } */
+                      struct A {
+                          a: f32, // b: vec3<f32>,
+                          /* c: vec4<f32>, */ d: u32,
+                      }";
+        let layout = struct_layout(source, "A", AddressSpace::Storage).unwrap();
+        assert_eq!(offsets(&layout), vec![member("a", 0, 4), member("d", 4, 4)]);
+    }
+
+    #[test]
+    fn check_layout_reports_mismatches() {
+        let source = "struct A { a: f32, b: vec3<f32> }";
+        assert!(check_layout(source, "A", AddressSpace::Storage, 32, &[("a", 0), ("b", 16)]).is_ok());
+        assert!(matches!(check_layout(source, "A", AddressSpace::Storage, 32, &[("a", 0), ("b", 4)]), Err(EngineError::WgslLayout(_))));
+        assert!(matches!(check_layout(source, "A", AddressSpace::Storage, 16, &[("a", 0)]), Err(EngineError::WgslLayout(_))));
+        assert!(matches!(struct_layout(source, "B", AddressSpace::Storage), Err(EngineError::WgslLayout(_))));
+    }
+}
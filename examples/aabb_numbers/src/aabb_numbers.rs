@@ -14,13 +14,15 @@ use engine::pipelines::RenderPipelineWrapper;
 use engine::texture::{
     Texture as Tex,
 };
-use engine::camera::Camera;
+use engine::camera::{Camera, CAMERA_BINDINGS_FILE};
+use engine::action_map::ActionMap;
 
 use wgpu::TextureView;
 
 use engine::core::SurfaceWrapper;
 use engine::basic_loop::BasicLoop;
 use engine::core::run;
+use engine::error::EngineError;
 // fn vs_main(@location(0) pos: vec3<f32>, @location(1) col: u32) -> FragmentInput {
 use engine::core::WGPUContext;
 use engine::core::Application;
@@ -57,8 +59,9 @@ impl Application for AabbApp {
                                      (-15.0, 12.0, 28.0),
                                      (0.0, 0.0, 0.0)
         );
-        camera.set_rotation_sensitivity(1.0);
-        camera.set_movement_sensitivity(0.1);
+        camera.get_state_mut().set_rotation_sensitivity(1.0);
+        camera.get_state_mut().set_movement_sensitivity(0.1);
+        camera.get_state_mut().set_action_map(ActionMap::load_or(CAMERA_BINDINGS_FILE, Camera::default_bindings()));
 
         let draw_buffer = context.device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Aabb draw buffer"),
@@ -127,21 +130,26 @@ impl Application for AabbApp {
         let mut clear = true;
 
         log::info!("Rendering");
-        self.gpu_debugger.render(
+        if let Err(e) = self.gpu_debugger.render(
                   &context.device,
                   &context.queue,
                   view,
                   &self.draw_buffer,
                   &self.depth_texture.as_ref().unwrap(), //depth_texture: &Tex,
-                  &mut clear
-            );
+                  &mut clear,
+                  None,
+                  None,
+                  None
+            ) {
+            log::error!("Failed to render the debug view: {}", e);
+        }
     }
 
     /// Resize window.
     fn resize(&mut self, context: &WGPUContext, surface_configuration: &wgpu::SurfaceConfiguration, _new_size: winit::dpi::PhysicalSize<u32>) {
 
         self.depth_texture = Some(Texture::create_depth_texture(context, &surface_configuration, Some("depth-texture")));
-        self.camera.resize(surface_configuration.width as f32, surface_configuration.height as f32);
+        self.camera.get_state_mut().resize(surface_configuration.width as f32, surface_configuration.height as f32);
     }
 
     /// Application update.
@@ -194,8 +202,8 @@ impl Application for AabbApp {
     }
 }
 
-fn main() {
+fn main() -> Result<(), EngineError> {
 
     initialize_env_logger(&vec![("mc".to_string(), LevelFilter::Info)]);
-    run::<AabbFeatures, BasicLoop, AabbApp>("yeah");
+    run::<AabbFeatures, BasicLoop, AabbApp>("yeah")
 }
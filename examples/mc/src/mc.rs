@@ -5,19 +5,21 @@ use engine::algorithms::mc::MarchingCubes;
 use engine::draw_commands::draw_indirect;
 use crate::configuration::McFeatures;
 use engine::draw_commands::draw;
-use engine::texture::Texture;
+use engine::texture::{Texture, TextureBuilder, TextureChannels};
 use engine::lights::LightBuffer;
 use engine::pipelines::RenderPipelineWrapper;
 use engine::texture::{
     Texture as Tex,
 };
-use engine::camera::Camera;
+use engine::camera::{Camera, CAMERA_BINDINGS_FILE};
+use engine::action_map::ActionMap;
 
 use wgpu::TextureView;
 
 use engine::core::SurfaceWrapper;
 use engine::basic_loop::BasicLoop;
 use engine::core::run;
+use engine::error::EngineError;
 use engine::core::WGPUContext;
 use engine::core::Application;
 use engine::input_cache::InputCache;
@@ -60,8 +62,9 @@ impl Application for McApp {
                                      (15.0, 12.0, -18.0),
                                      (0.0, 0.0, 0.0)
         );
-        camera.set_rotation_sensitivity(1.0);
-        camera.set_movement_sensitivity(0.1);
+        camera.get_state_mut().set_rotation_sensitivity(1.0);
+        camera.get_state_mut().set_movement_sensitivity(0.1);
+        camera.get_state_mut().set_action_map(ActionMap::load_or(CAMERA_BINDINGS_FILE, Camera::default_bindings()));
 
         log::info!("Creating light.");
 
@@ -77,21 +80,15 @@ impl Application for McApp {
 
         log::info!("Creating textures.");
 
-        let grass_texture = Texture::create_from_bytes(
-            &context.queue,
+        let grass_texture = TextureBuilder::new(TextureChannels::Rgba8).build_from_png(
             &context.device,
-            &surface.config(),
-            1,
-            &include_bytes!("../../../textures/grass_flowers.png")[..],
-            None);
-
-        let rock_texture = Texture::create_from_bytes(
             &context.queue,
+            &include_bytes!("../../../textures/grass_flowers.png")[..]).expect("Failed to load grass texture.");
+
+        let rock_texture = TextureBuilder::new(TextureChannels::Rgba8).build_from_png(
             &context.device,
-            &surface.config(),
-            1,
-            &include_bytes!("../../../textures/rock.png")[..],
-            None);
+            &context.queue,
+            &include_bytes!("../../../textures/rock.png")[..]).expect("Failed to load rock texture.");
 
         log::info!("Creating pipeline wrapper.");
         let render_pipeline_wrapper = default_render_shader_v4n4_camera_light_tex2(&context.device, &surface.config());
@@ -187,7 +184,9 @@ impl Application for McApp {
             self.marching_cubes.get_draw_indirect_buffer(),
             0,
             &clear_color,
-            true
+            true,
+            None,
+            None
             );
 
         draw(&mut encoder,
@@ -198,7 +197,9 @@ impl Application for McApp {
              &self.buffer,
              0..36,
              &clear_color,
-             false);
+             false,
+             None,
+             None);
 
 
         context.queue.submit(Some(encoder.finish()));
@@ -211,7 +212,7 @@ impl Application for McApp {
     fn resize(&mut self, context: &WGPUContext, surface_configuration: &wgpu::SurfaceConfiguration, _new_size: winit::dpi::PhysicalSize<u32>) {
 
         self.depth_texture = Some(Texture::create_depth_texture(context, &surface_configuration, Some("depth-texture")));
-        self.camera.resize(surface_configuration.width as f32, surface_configuration.height as f32);
+        self.camera.get_state_mut().resize(surface_configuration.width as f32, surface_configuration.height as f32);
     }
 
     /// Application update.
@@ -235,8 +236,8 @@ impl Application for McApp {
     }
 }
 
-fn main() {
+fn main() -> Result<(), EngineError> {
 
     initialize_env_logger(&vec![("mc".to_string(), LevelFilter::Info)]);
-    run::<McFeatures, BasicLoop, McApp>("yeah");
+    run::<McFeatures, BasicLoop, McApp>("yeah")
 }
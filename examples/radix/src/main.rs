@@ -22,13 +22,15 @@ use engine::pipelines::RenderPipelineWrapper;
 use engine::texture::{
     Texture as Tex,
 };
-use engine::camera::Camera;
+use engine::camera::{Camera, CAMERA_BINDINGS_FILE};
+use engine::action_map::ActionMap;
 
 use wgpu::TextureView;
 
 use engine::core::SurfaceWrapper;
 use engine::basic_loop::BasicLoop;
 use engine::core::run;
+use engine::error::EngineError;
 use engine::core::WGPUContext;
 use engine::core::Application;
 use engine::input_cache::InputCache;
@@ -113,8 +115,9 @@ impl Application for RadixApp {
                                      (-45.0, 132.0, 38.0),
                                      (50.0, 0.0, 50.0)
         );
-        camera.set_rotation_sensitivity(1.0);
-        camera.set_movement_sensitivity(0.1);
+        camera.get_state_mut().set_rotation_sensitivity(1.0);
+        camera.get_state_mut().set_movement_sensitivity(0.1);
+        camera.get_state_mut().set_action_map(ActionMap::load_or(CAMERA_BINDINGS_FILE, Camera::default_bindings()));
 
         let draw_buffer = context.device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("RadixDrawBuffer"),
@@ -167,21 +170,26 @@ impl Application for RadixApp {
 
         let mut clear = true;
 
-        self.gpu_debugger.render(
+        if let Err(e) = self.gpu_debugger.render(
                   &context.device,
                   &context.queue,
                   view,
                   &self.draw_buffer,
                   &self.depth_texture.as_ref().unwrap(),
-                  &mut clear
-            );
+                  &mut clear,
+                  None,
+                  None,
+                  None
+            ) {
+            log::error!("Failed to render the debug view: {}", e);
+        }
     }
 
     /// Resize window.
     fn resize(&mut self, context: &WGPUContext, surface_configuration: &wgpu::SurfaceConfiguration, _new_size: winit::dpi::PhysicalSize<u32>) {
 
         self.depth_texture = Some(Texture::create_depth_texture(context, &surface_configuration, Some("depth-texture")));
-        self.camera.resize(surface_configuration.width as f32, surface_configuration.height as f32);
+        self.camera.get_state_mut().resize(surface_configuration.width as f32, surface_configuration.height as f32);
     }
 
     /// Application update.
@@ -234,8 +242,8 @@ impl Application for RadixApp {
     }
 }
 
-fn main() {
+fn main() -> Result<(), EngineError> {
 
     initialize_env_logger(&vec![("mc".to_string(), LevelFilter::Info)]);
-    run::<RadixFeatures, BasicLoop, RadixApp>("yeah");
+    run::<RadixFeatures, BasicLoop, RadixApp>("yeah")
 }
@@ -1,19 +1,22 @@
-use engine::draw_commands::draw;
-use engine::texture::Texture;
-use engine::lights::LightBuffer;
+use engine::draw_commands::draw_with_offsets;
+use engine::texture::{Texture, TextureBuilder, TextureChannels};
+use engine::lights::Light;
+use engine::uniform_ring::{UniformRing, DEFAULT_FRAMES_IN_FLIGHT};
 use engine::pipelines::RenderPipelineWrapper;
 use engine::texture::{
     Texture as Tex,
 };
-use engine::camera::Camera;
+use engine::camera::{Camera, CAMERA_BINDINGS_FILE};
+use engine::action_map::ActionMap;
 
 use wgpu::TextureView;
 use engine::core::SurfaceWrapper;
 use engine::basic_loop::BasicLoop;
 use crate::configuration::SmokeFeatures;
-use engine::pipeline_stuff::custom_pipelines::default_render_shader_v4n4_camera_light_tex2;
+use engine::pipeline_stuff::custom_pipelines::default_render_shader_v4n4_camera_dynamic_light_tex2;
 
 use engine::core::run;
+use engine::error::EngineError;
 
 use engine::core::WGPUContext;
 use engine::core::Application;
@@ -30,9 +33,10 @@ struct SmokeApp {
     camera: Camera,
     buffer: wgpu::Buffer,
     render_pipeline_wrapper: RenderPipelineWrapper,
-    #[allow(dead_code)] 
-    light: LightBuffer,
-    bind_group1: wgpu::BindGroup,
+    light: Light,
+    light_angle: f32,
+    uniform_ring: UniformRing,
+    bind_groups1: Vec<wgpu::BindGroup>,
     bind_group2: wgpu::BindGroup,
 }
 
@@ -51,13 +55,13 @@ impl Application for SmokeApp {
                                      (15.0, 12.0, -18.0),
                                      (0.0, 0.0, 0.0)
         );
-        camera.set_rotation_sensitivity(1.0);
-        camera.set_movement_sensitivity(0.1);
+        camera.get_state_mut().set_rotation_sensitivity(1.0);
+        camera.get_state_mut().set_movement_sensitivity(0.1);
+        camera.get_state_mut().set_action_map(ActionMap::load_or(CAMERA_BINDINGS_FILE, Camera::default_bindings()));
 
         log::info!("Creating light.");
 
-        let light = LightBuffer::create(
-            &context.device,
+        let light = Light::init(
             [25.0, 55.0, 25.0], // pos
             [25, 25, 130],  // spec
             [255,200,255], // light
@@ -66,33 +70,38 @@ impl Application for SmokeApp {
             0.0013
             );
 
+        // The light is uploaded every frame through the ring.
+        let uniform_ring = UniformRing::init(
+            &context.device,
+            1024,
+            DEFAULT_FRAMES_IN_FLIGHT,
+            std::mem::size_of::<Light>() as u64,
+            Some("smoke uniform ring")).expect("Failed to create the uniform ring.");
+
         log::info!("Creating textures.");
 
-        let grass_texture = Texture::create_from_bytes(
-            &context.queue,
+        let grass_texture = TextureBuilder::new(TextureChannels::Rgba8).build_from_png(
             &context.device,
-            &surface.config(),
-            1,
-            &include_bytes!("../../../textures/grass_flowers.png")[..],
-            None);
-
-        let rock_texture = Texture::create_from_bytes(
             &context.queue,
+            &include_bytes!("../../../textures/grass_flowers.png")[..]).expect("Failed to load grass texture.");
+
+        let rock_texture = TextureBuilder::new(TextureChannels::Rgba8).build_from_png(
             &context.device,
-            &surface.config(),
-            1,
-            &include_bytes!("../../../textures/rock.png")[..],
-            None);
+            &context.queue,
+            &include_bytes!("../../../textures/rock.png")[..]).expect("Failed to load rock texture.");
         
         log::info!("Creating pipeline wrapper.");
-        let render_pipeline_wrapper = default_render_shader_v4n4_camera_light_tex2(&context.device, &surface.config());
+        let render_pipeline_wrapper = default_render_shader_v4n4_camera_dynamic_light_tex2(&context.device, &surface.config());
         log::info!("Creating bind groups.");
-        let bind_group1 = render_pipeline_wrapper.create_bind_group(&context.device,
+        let camera_uniform = camera.get_camera_uniform(&context.device);
+        let bind_groups1 = uniform_ring.create_bind_groups(|light_binding| {
+            render_pipeline_wrapper.create_bind_group(&context.device,
                                                   &vec![
-                                                    &camera.get_camera_uniform(&context.device).as_entire_binding(),
-                                                    &light.get_buffer().as_entire_binding(),
+                                                    &camera_uniform.as_entire_binding(),
+                                                    &light_binding,
                                                   ],
-                                                  0);
+                                                  0)
+        });
         let bind_group2 = render_pipeline_wrapper.create_bind_group(&context.device,
                          &vec![
                          &wgpu::BindingResource::TextureView(&grass_texture.view.unwrap()),
@@ -110,7 +119,9 @@ impl Application for SmokeApp {
             buffer: create_cube(&context.device, 18.0, false),
             render_pipeline_wrapper: render_pipeline_wrapper,
             light: light,
-            bind_group1: bind_group1, 
+            light_angle: 0.0,
+            uniform_ring: uniform_ring,
+            bind_groups1: bind_groups1,
             bind_group2: bind_group2, 
         }
     }
@@ -120,20 +131,32 @@ impl Application for SmokeApp {
 
             let clear_color = Some(wgpu::Color { r: 0.1, g: 0.0, b: 0.0, a: 1.0, });
 
+            self.uniform_ring.begin_frame();
+            let light_slice = match self.uniform_ring.push(&self.light) {
+                Ok(slice) => slice,
+                Err(e) => {
+                    log::error!("Failed to push the light: {}", e);
+                    return;
+                }
+            };
+            self.uniform_ring.flush(&context.queue);
 
             // If there is nothing to draw, this must be executed.
             let mut dummy_encoder = context.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Dummy encoder") });
             {
 
-                draw(&mut dummy_encoder,
+                draw_with_offsets(&mut dummy_encoder,
                      view,
                      self.depth_texture.as_ref(),
-                     &vec![&self.bind_group1, &self.bind_group2],
+                     &vec![&self.bind_groups1[self.uniform_ring.get_frame()], &self.bind_group2],
+                     &[&[light_slice.offset]],
                      self.render_pipeline_wrapper.get_pipeline(),
                      &self.buffer,
                      0..36,
                      &clear_color,
-                     true);
+                     true,
+                     None,
+                     None);
             }
             context.queue.submit(Some(dummy_encoder.finish()));
     }
@@ -142,20 +165,24 @@ impl Application for SmokeApp {
     fn resize(&mut self, context: &WGPUContext, surface_configuration: &wgpu::SurfaceConfiguration, _new_size: winit::dpi::PhysicalSize<u32>) {
 
         self.depth_texture = Some(Texture::create_depth_texture(context, &surface_configuration, Some("depth-texture")));
-        self.camera.resize(surface_configuration.width as f32, surface_configuration.height as f32);
+        self.camera.get_state_mut().resize(surface_configuration.width as f32, surface_configuration.height as f32);
     }
 
     /// Application update.
     fn update(&mut self, context: &WGPUContext, input_cache: &InputCache) {
         self.camera.update_from_input(&context.queue, &input_cache);
+
+        // Circle the light around the cube.
+        self.light_angle += 0.5 * input_cache.get_time_delta() as f32 / 1000000000.0;
+        self.light.set_position([35.0 * self.light_angle.cos(), 55.0, 35.0 * self.light_angle.sin()]);
     }
 
     fn close(&mut self, _wgpu_context: &WGPUContext){ 
     }
 }
 
-fn main() {
+fn main() -> Result<(), EngineError> {
 
     initialize_env_logger(&vec![("smoke".to_string(), LevelFilter::Info)]);
-    run::<SmokeFeatures, BasicLoop, SmokeApp>("yeah");
+    run::<SmokeFeatures, BasicLoop, SmokeApp>("yeah")
 }